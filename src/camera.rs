@@ -0,0 +1,246 @@
+use std::f32::consts::FRAC_PI_2;
+use winit::window::Window;
+
+use crate::{
+    input::Input,
+    math::{Matrix4, Vector3},
+};
+
+// keeps the camera from flipping over when looking straight up or down
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const MIN_ORBIT_DISTANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Vector3,
+    // radians, yaw 0 looks down -Z
+    pub yaw: f32,
+    pub pitch: f32,
+
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vector3::new(0.0, 0.0, 2.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: 45.0_f32.to_radians(),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn forward(&self) -> Vector3 {
+        Vector3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn right(&self) -> Vector3 {
+        self.forward().cross(Vector3::up()).normalize()
+    }
+
+    /// Points the camera at `target`, keeping its position.
+    pub fn look_at(&mut self, target: Vector3) {
+        let direction = (target - self.position).normalize();
+        self.pitch = direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+        self.yaw = direction.x.atan2(-direction.z);
+    }
+
+    pub fn view_matrix(&self) -> Matrix4 {
+        Matrix4::look_at(self.position, self.position + self.forward(), Vector3::up())
+    }
+
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Matrix4 {
+        Matrix4::perspective(self.fov_y, aspect_ratio, self.near, self.far)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    // move_* axes to move, mouse to look around, with the cursor grabbed by the window
+    Fly,
+    // orbit_rotate + mouse drag rotates around the target, scroll zooms in and out
+    Orbit,
+}
+
+/// Turns input actions into camera movement, scaled by the frame delta time so speed does not
+/// depend on the frame rate.
+pub struct CameraController {
+    pub mode: CameraMode,
+
+    // units per second
+    pub move_speed: f32,
+    // radians per pixel of mouse motion
+    pub look_sensitivity: f32,
+    // distance change per scroll line
+    pub zoom_speed: f32,
+
+    pub orbit_target: Vector3,
+    orbit_distance: f32,
+}
+
+impl CameraController {
+    pub fn new(mode: CameraMode, camera: &Camera) -> Self {
+        let orbit_target = Vector3::zero();
+
+        CameraController {
+            mode,
+            move_speed: 2.0,
+            look_sensitivity: 0.003,
+            zoom_speed: 0.25,
+            orbit_target,
+            orbit_distance: (camera.position - orbit_target).length(),
+        }
+    }
+
+    /// Switches between fly and orbit, grabbing the cursor only while flying.
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &mut Camera, window: &Window) {
+        self.mode = mode;
+
+        let grab = mode == CameraMode::Fly;
+        if let Err(err) = window.set_cursor_grab(grab) {
+            println!("Could not change cursor grab: {:?}", err);
+        }
+        window.set_cursor_visible(!grab);
+
+        if mode == CameraMode::Orbit {
+            self.orbit_distance = (camera.position - self.orbit_target)
+                .length()
+                .max(MIN_ORBIT_DISTANCE);
+            camera.look_at(self.orbit_target);
+        }
+    }
+
+    pub fn toggle_mode(&mut self, camera: &mut Camera, window: &Window) {
+        let mode = match self.mode {
+            CameraMode::Fly => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Fly,
+        };
+
+        self.set_mode(mode, camera, window);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, delta_time: f32) {
+        match self.mode {
+            CameraMode::Fly => self.update_fly(camera, input, delta_time),
+            CameraMode::Orbit => self.update_orbit(camera, input),
+        }
+    }
+
+    fn update_fly(&mut self, camera: &mut Camera, input: &Input, delta_time: f32) {
+        self.rotate(camera, input.mouse_delta());
+
+        let direction = camera.forward() * input.axis("move_forward")
+            + camera.right() * input.axis("move_right")
+            + Vector3::up() * input.axis("move_up");
+
+        // normalized so diagonal movement is not faster
+        camera.position += direction.normalize() * (self.move_speed * delta_time);
+    }
+
+    fn update_orbit(&mut self, camera: &mut Camera, input: &Input) {
+        if input.action_held("orbit_rotate") {
+            self.rotate(camera, input.mouse_delta());
+        }
+
+        self.orbit_distance =
+            (self.orbit_distance - input.scroll_delta() * self.zoom_speed).max(MIN_ORBIT_DISTANCE);
+
+        camera.position = self.orbit_target - camera.forward() * self.orbit_distance;
+    }
+
+    fn rotate(&self, camera: &mut Camera, mouse_delta: (f32, f32)) {
+        camera.yaw += mouse_delta.0 * self.look_sensitivity;
+        camera.pitch =
+            (camera.pitch - mouse_delta.1 * self.look_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::DeviceEvent;
+
+    use super::*;
+    use crate::input::InputMap;
+
+    fn assert_near(actual: Vector3, expected: Vector3) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn default_camera_looks_down_negative_z() {
+        let camera = Camera::default();
+
+        assert_near(camera.forward(), Vector3::new(0.0, 0.0, -1.0));
+        assert_near(camera.right(), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let mut camera = Camera {
+            position: Vector3::new(1.0, 2.0, 3.0),
+            ..Camera::default()
+        };
+        let target = Vector3::new(-2.0, 0.5, -1.0);
+        camera.look_at(target);
+
+        assert_near(camera.forward(), (target - camera.position).normalize());
+    }
+
+    #[test]
+    fn look_at_straight_down_is_clamped() {
+        let mut camera = Camera::default();
+        camera.look_at(camera.position - Vector3::up());
+
+        assert_eq!(camera.pitch, -MAX_PITCH);
+    }
+
+    #[test]
+    fn fly_mouse_motion_turns_the_camera_without_moving_it() {
+        let mut camera = Camera::default();
+        let mut controller = CameraController::new(CameraMode::Fly, &camera);
+        let mut input = Input::new(InputMap::default());
+        input.handle_device_event(&DeviceEvent::MouseMotion {
+            delta: (100.0, 1.0e6),
+        });
+
+        controller.update(&mut camera, &input, 0.016);
+
+        assert_eq!(camera.yaw, 100.0 * controller.look_sensitivity);
+        assert_eq!(camera.pitch, -MAX_PITCH);
+        assert_eq!(camera.position, Camera::default().position);
+    }
+
+    #[test]
+    fn orbit_keeps_the_distance_to_the_target() {
+        let mut camera = Camera {
+            position: Vector3::new(0.0, 3.0, 4.0),
+            yaw: 1.0,
+            pitch: -0.3,
+            ..Camera::default()
+        };
+        let mut controller = CameraController::new(CameraMode::Orbit, &camera);
+
+        controller.update(&mut camera, &Input::new(InputMap::default()), 0.016);
+
+        assert!(((camera.position - controller.orbit_target).length() - 5.0).abs() < 1e-4);
+        assert_near(
+            camera.forward(),
+            (controller.orbit_target - camera.position).normalize(),
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::{CpuAccessibleBuffer, CpuBufferPool},
+    descriptor::descriptor_set::PersistentDescriptorSet,
+    device::Device,
+};
+
+use crate::{
+    compute::{ComputeDispatch, ComputeProgram},
+    error_utils::EngineError,
+    mesh::{Mesh, Vertex},
+};
+
+// must match local_size_x in the shader
+const LOCAL_SIZE: u32 = 64;
+
+mod cycle_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 450
+
+        layout(local_size_x = 64) in;
+
+        // the Vertex struct of mesh.rs, 11 floats without padding, the colour from the 7th
+        const uint VERTEX_FLOATS = 11;
+        const uint COLOUR_OFFSET = 6;
+
+        layout(set = 0, binding = 0) buffer Vertices {
+            float data[];
+        } vertices;
+
+        layout(set = 0, binding = 1) uniform UboCycle {
+            float time;
+        } uboCycle;
+
+        void main() {
+            uint index = gl_GlobalInvocationID.x;
+            uint first = index * VERTEX_FLOATS + COLOUR_OFFSET;
+            if (first + 2 >= vertices.data.length()) {
+                return;
+            }
+
+            float phase = uboCycle.time + float(index) * 2.0;
+            vec3 colour = 0.5 + 0.5 * cos(phase + vec3(0.0, 2.0, 4.0));
+            vertices.data[first] = colour.r;
+            vertices.data[first + 1] = colour.g;
+            vertices.data[first + 2] = colour.b;
+        }"
+    }
+}
+
+// Matches the layout of UboCycle in the shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct UboCycle {
+    time: f32,
+}
+
+/// Cycles the vertex colours of a mesh through the hues on the GPU, each vertex a third of the
+/// way ahead of the previous one.
+pub struct ColourCycle {
+    program: Arc<ComputeProgram>,
+    vertices: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    vertex_count: u32,
+    time_pool: CpuBufferPool<UboCycle>,
+}
+
+impl ColourCycle {
+    pub fn new(device: Arc<Device>, mesh: &Mesh) -> Result<Self, EngineError> {
+        let shader = cycle_shader::Shader::load(device.clone())?;
+        let program = ComputeProgram::new(device.clone(), &shader.main_entry_point())?;
+
+        Ok(ColourCycle {
+            program,
+            vertices: mesh.vertex_buffer(),
+            vertex_count: mesh.vertex_count() as u32,
+            time_pool: CpuBufferPool::uniform_buffer(device),
+        })
+    }
+
+    /// The colours `time` seconds into the cycle. The mesh is written in place, so every draw
+    /// of it after the dispatch shows them.
+    pub fn dispatch(&self, time: f32) -> Result<ComputeDispatch, EngineError> {
+        let set_error =
+            |err| EngineError::ComputeError(format!("Could not bind the vertices: {:?}", err));
+        let uniform_buffer = self.time_pool.next(UboCycle { time })?;
+        let set = PersistentDescriptorSet::start(self.program.descriptor_set_layout(0)?)
+            .add_buffer(self.vertices.clone())
+            .map_err(set_error)?
+            .add_buffer(uniform_buffer)
+            .map_err(set_error)?
+            .build()
+            .map_err(|err| {
+                EngineError::ComputeError(format!("Could not bind the vertices: {:?}", err))
+            })?;
+
+        Ok(ComputeDispatch::covering(
+            self.program.clone(),
+            vec![Arc::new(set)],
+            [self.vertex_count, 1, 1],
+            [LOCAL_SIZE, 1, 1],
+        ))
+    }
+}
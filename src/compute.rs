@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::AutoCommandBufferBuilder,
+    descriptor::{
+        descriptor_set::{DescriptorSet, UnsafeDescriptorSetLayout},
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    pipeline::{shader::EntryPointAbstract, ComputePipeline, ComputePipelineAbstract},
+    sync::GpuFuture,
+};
+
+use crate::{debug_names, error_utils::EngineError};
+
+/// A compute shader built into a pipeline.
+///
+/// Shaders come from `vulkano_shaders::shader!` with `ty: "compute"`, like the graphics ones:
+/// `ComputeProgram::new(device, &shader.main_entry_point())`.
+pub struct ComputeProgram {
+    pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+}
+
+impl ComputeProgram {
+    pub fn new<Cs>(device: Arc<Device>, shader: &Cs) -> Result<Arc<Self>, EngineError>
+    where
+        Cs: EntryPointAbstract<SpecializationConstants = ()>,
+        Cs::PipelineLayout: Clone + Send + Sync + 'static,
+    {
+        let pipeline = ComputePipeline::new(device, shader, &())?;
+        debug_names::name_compute_pipeline(&pipeline, "compute");
+
+        Ok(Arc::new(ComputeProgram {
+            pipeline: Arc::new(pipeline),
+        }))
+    }
+
+    /// Layout to build descriptor sets with, storage buffers and images are bound with
+    /// `PersistentDescriptorSet::start(layout).add_buffer(..)` or `.add_image(..)`.
+    pub fn descriptor_set_layout(
+        &self,
+        set: usize,
+    ) -> Result<Arc<UnsafeDescriptorSetLayout>, EngineError> {
+        self.pipeline
+            .descriptor_set_layout(set)
+            .cloned()
+            .ok_or_else(|| {
+                EngineError::ComputeError(format!("The shader has no descriptor set {}", set))
+            })
+    }
+}
+
+/// One run of a compute program over a grid of workgroups.
+#[derive(Clone)]
+pub struct ComputeDispatch {
+    pub program: Arc<ComputeProgram>,
+    // bound to sets 0, 1... in order
+    pub sets: Vec<Arc<dyn DescriptorSet + Send + Sync>>,
+    pub workgroups: [u32; 3],
+}
+
+impl ComputeDispatch {
+    /// Enough workgroups of `local_size`, as declared in the shader, to cover `invocations`.
+    /// The shader must skip the invocations past the end when the sizes are not multiples.
+    pub fn covering(
+        program: Arc<ComputeProgram>,
+        sets: Vec<Arc<dyn DescriptorSet + Send + Sync>>,
+        invocations: [u32; 3],
+        local_size: [u32; 3],
+    ) -> Self {
+        let count = |axis: usize| invocations[axis].div_ceil(local_size[axis].max(1));
+        let workgroups = [count(0), count(1), count(2)];
+
+        ComputeDispatch {
+            program,
+            sets,
+            workgroups,
+        }
+    }
+}
+
+/// Records the dispatches in order. vulkano puts the barriers between a dispatch writing a
+/// buffer or image and the commands reading it afterwards.
+pub(crate) fn record_dispatches(
+    builder: &mut AutoCommandBufferBuilder,
+    dispatches: &[ComputeDispatch],
+) -> Result<(), EngineError> {
+    for dispatch in dispatches {
+        builder
+            .dispatch(
+                dispatch.workgroups,
+                dispatch.program.pipeline.clone(),
+                dispatch.sets.clone(),
+                (),
+            )
+            .map_err(|err| {
+                EngineError::ComputeError(format!("Could not record dispatch: {:?}", err))
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Submits the dispatches after `after` and blocks until they are done, so their results can
+/// be read on the CPU right away.
+pub(crate) fn run(
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    after: Box<dyn GpuFuture>,
+    dispatches: &[ComputeDispatch],
+) -> Result<(), EngineError> {
+    let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(device, queue.family())
+        .map_err(|err| {
+            EngineError::ComputeError(format!("Could not start command buffer: {:?}", err))
+        })?;
+    record_dispatches(&mut builder, dispatches)?;
+    let command_buffer = builder.build().unwrap();
+
+    after
+        .then_execute(queue, command_buffer)?
+        .then_signal_fence_and_flush()?
+        .wait(None)?;
+
+    Ok(())
+}
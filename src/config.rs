@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error_utils::EngineError;
+
+// Small INI like format used by our settings files:
+//
+//   # comment
+//   [section]
+//   key = value
+//
+// Keys may repeat, it is up to each reader to decide what that means.
+
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigSection {
+    pub name: String,
+    pub entries: Vec<ConfigEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    // file name (or any description of where the text came from) used in error messages
+    pub origin: String,
+    pub sections: Vec<ConfigSection>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, EngineError> {
+        let source = fs::read_to_string(path).map_err(|err| {
+            EngineError::ConfigError(format!("Could not read {}: {}", path.display(), err))
+        })?;
+
+        Self::parse(&source, &path.display().to_string())
+    }
+
+    pub fn parse(source: &str, origin: &str) -> Result<Self, EngineError> {
+        let mut sections: Vec<ConfigSection> = Vec::new();
+
+        for (index, raw_line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(Self::error(origin, line_number, "expected [section]"));
+                }
+
+                sections.push(ConfigSection {
+                    name: line[1..line.len() - 1].trim().to_string(),
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+
+            let separator = match line.find('=') {
+                Some(index) => index,
+                None => return Err(Self::error(origin, line_number, "expected key = value")),
+            };
+
+            let key = line[..separator].trim();
+            if key.is_empty() {
+                return Err(Self::error(origin, line_number, "missing key before '='"));
+            }
+
+            let section = match sections.last_mut() {
+                Some(section) => section,
+                None => {
+                    return Err(Self::error(
+                        origin,
+                        line_number,
+                        "entries must come after a [section]",
+                    ))
+                }
+            };
+
+            section.entries.push(ConfigEntry {
+                key: key.to_string(),
+                value: line[separator + 1..].trim().to_string(),
+                line: line_number,
+            });
+        }
+
+        Ok(ConfigFile {
+            origin: origin.to_string(),
+            sections,
+        })
+    }
+
+    pub fn section(&self, name: &str) -> Option<&ConfigSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Builds an error pointing at the line of `entry`.
+    pub fn entry_error(&self, entry: &ConfigEntry, message: &str) -> EngineError {
+        Self::error(&self.origin, entry.line, message)
+    }
+
+    fn error(origin: &str, line: usize, message: &str) -> EngineError {
+        EngineError::ConfigError(format!("{}:{}: {}", origin, line, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(error: EngineError) -> String {
+        match error {
+            EngineError::ConfigError(message) => message,
+            other => panic!("not a config error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_sections_and_entries() {
+        let config = ConfigFile::parse(
+            "# leading comment\n\n[window]\n  width = 800 \nmode=borderless\n[ empty ]\n[window]\ntitle = a = b\n",
+            "test.cfg",
+        )
+        .unwrap();
+
+        assert_eq!(config.origin, "test.cfg");
+        let names: Vec<&str> = config.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["window", "empty", "window"]);
+
+        // the first section of a name is the one found
+        let window = config.section("window").unwrap();
+        let entries: Vec<(&str, &str, usize)> = window
+            .entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.value.as_str(), e.line))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![("width", "800", 4), ("mode", "borderless", 5)]
+        );
+
+        // only the first '=' separates the key
+        assert_eq!(config.sections[2].entries[0].value, "a = b");
+        assert!(config.section("empty").unwrap().entries.is_empty());
+        assert!(config.section("missing").is_none());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |source: &str| message(ConfigFile::parse(source, "test.cfg").unwrap_err());
+
+        assert_eq!(
+            error("key = value"),
+            "test.cfg:1: entries must come after a [section]"
+        );
+        assert_eq!(error("[a]\n\n[b"), "test.cfg:3: expected [section]");
+        assert_eq!(error("[]"), "test.cfg:1: expected [section]");
+        assert_eq!(
+            error("[a]\n# note\nno separator"),
+            "test.cfg:3: expected key = value"
+        );
+        assert_eq!(error("[a]\n = 1"), "test.cfg:2: missing key before '='");
+    }
+
+    #[test]
+    fn entry_errors_use_the_entry_line() {
+        let config = ConfigFile::parse("[a]\n\nkey = 1\n", "test.cfg").unwrap();
+        let entry = &config.section("a").unwrap().entries[0];
+
+        assert_eq!(
+            message(config.entry_error(entry, "bad value")),
+            "test.cfg:3: bad value"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferAccess, CpuBufferPool},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    device::Device,
+    format::Format,
+    framebuffer::{RenderPassAbstract, Subpass},
+    pipeline::{
+        blend::AttachmentBlend,
+        depth_stencil::{Compare, DepthStencil},
+        GraphicsPipeline, GraphicsPipelineAbstract,
+    },
+};
+
+use crate::{
+    debug_names, display_settings,
+    error_utils::EngineError,
+    gpu_memory::{self, MemoryCategory, MemoryUsage},
+    material::ShaderProgram,
+    math::{Matrix4, Vector3},
+    vulkan_renderer::FrameUniforms,
+};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 450
+
+        layout(set = 0, binding = 0) uniform UboViewProjection {
+            mat4 projection;
+            mat4 view;
+        } uboViewProjection;
+
+        layout(location = 0) in vec3 position;
+        layout(location = 1) in vec4 colour;
+
+        layout(location = 0) out vec4 fragColour;
+
+        void main() {
+            gl_Position = uboViewProjection.projection * uboViewProjection.view * vec4(position, 1.0);
+            fragColour = colour;
+        }"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        layout(location = 0) in vec4 fragColour;
+
+        layout(location = 0) out vec4 f_color;
+
+        // a UNORM swapchain stores the colour as written, so it is encoded here
+        layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+        vec3 encodeSrgb(vec3 linear) {
+            linear = clamp(linear, 0.0, 1.0);
+            vec3 low = linear * 12.92;
+            vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+            return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+        }
+
+        void main() {
+            f_color = fragColour;
+
+            if (ENCODE_SRGB) {
+                f_color.rgb = encodeSrgb(f_color.rgb);
+            }
+        }"
+    }
+}
+
+// segments of the circles making up a sphere
+const CIRCLE_SEGMENTS: usize = 24;
+
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct DebugVertex {
+    position: [f32; 3],
+    colour: [f32; 4],
+}
+
+vulkano::impl_vertex!(DebugVertex, position, colour);
+
+/// Lines drawn over the next frame, for bounding boxes, lights, cameras and the like.
+///
+/// Primitives are collected until the frame is rendered; the next primitive added after that
+/// starts a new set, so a frame where nothing is added shows the previous lines again. A frame
+/// skipped while the window is minimized or out of date counts as rendered.
+pub struct DebugDraw {
+    // whether the primitives added from now on are hidden behind the scene
+    pub depth_test: bool,
+    // in pixels, for all the lines of the frame; one pixel on devices without wide lines
+    pub line_width: f32,
+
+    depth_tested: Vec<DebugVertex>,
+    overlay: Vec<DebugVertex>,
+    rendered: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        DebugDraw {
+            depth_test: true,
+            line_width: 1.0,
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+            rendered: false,
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, a: Vector3, b: Vector3, colour: [f32; 4]) {
+        if self.rendered {
+            self.clear();
+        }
+
+        let vertices = if self.depth_test {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlay
+        };
+        vertices.push(DebugVertex {
+            position: a.into(),
+            colour,
+        });
+        vertices.push(DebugVertex {
+            position: b.into(),
+            colour,
+        });
+    }
+
+    /// Axis aligned box between two opposite corners.
+    pub fn aabb(&mut self, min: Vector3, max: Vector3, colour: [f32; 4]) {
+        let corner = |x: bool, y: bool, z: bool| {
+            Vector3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+
+        for &a in &[false, true] {
+            for &b in &[false, true] {
+                self.line(corner(false, a, b), corner(true, a, b), colour);
+                self.line(corner(a, false, b), corner(a, true, b), colour);
+                self.line(corner(a, b, false), corner(a, b, true), colour);
+            }
+        }
+    }
+
+    /// One circle around each axis.
+    pub fn sphere(&mut self, centre: Vector3, radius: f32, colour: [f32; 4]) {
+        let point = |axis: usize, angle: f32| {
+            let (sin, cos) = (angle.sin() * radius, angle.cos() * radius);
+            centre
+                + match axis {
+                    0 => Vector3::new(0.0, cos, sin),
+                    1 => Vector3::new(cos, 0.0, sin),
+                    _ => Vector3::new(cos, sin, 0.0),
+                }
+        };
+
+        for axis in 0..3 {
+            for segment in 0..CIRCLE_SEGMENTS {
+                let start = segment as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+                let end = (segment + 1) as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+                self.line(point(axis, start), point(axis, end), colour);
+            }
+        }
+    }
+
+    /// The X, Y and Z axes of `transform` in red, green and blue, `size` long before scaling.
+    pub fn axes(&mut self, transform: &Matrix4, size: f32) {
+        let origin = transform.transform_point(Vector3::zero());
+        let axes = [
+            (Vector3::new(size, 0.0, 0.0), [1.0, 0.0, 0.0, 1.0]),
+            (Vector3::new(0.0, size, 0.0), [0.0, 1.0, 0.0, 1.0]),
+            (Vector3::new(0.0, 0.0, size), [0.0, 0.0, 1.0, 1.0]),
+        ];
+
+        for &(axis, colour) in &axes {
+            self.line(origin, transform.transform_point(axis), colour);
+        }
+    }
+
+    /// Square grid on the XZ plane around `centre`, `cells` cells of `spacing` on each side.
+    pub fn grid(&mut self, centre: Vector3, cells: u32, spacing: f32, colour: [f32; 4]) {
+        let half_size = cells as f32 * spacing / 2.0;
+
+        for line in 0..=cells {
+            let offset = line as f32 * spacing - half_size;
+            self.line(
+                centre + Vector3::new(offset, 0.0, -half_size),
+                centre + Vector3::new(offset, 0.0, half_size),
+                colour,
+            );
+            self.line(
+                centre + Vector3::new(-half_size, 0.0, offset),
+                centre + Vector3::new(half_size, 0.0, offset),
+                colour,
+            );
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.depth_tested.clear();
+        self.overlay.clear();
+        self.rendered = false;
+    }
+
+    /// Number of lines waiting to be drawn.
+    pub fn line_count(&self) -> usize {
+        (self.depth_tested.len() + self.overlay.len()) / 2
+    }
+}
+
+/// The debug lines of a frame uploaded, with the pipeline drawing each batch.
+pub(crate) struct PreparedDebugLines {
+    batches: Vec<(
+        Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        Arc<dyn BufferAccess + Send + Sync>,
+    )>,
+    line_width: f32,
+}
+
+impl PreparedDebugLines {
+    /// Records the lines inside the main render pass, after the scene so depth tested lines
+    /// are hidden by it. Overlay lines come last and are drawn over everything.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        frame: &FrameUniforms,
+    ) {
+        let dynamic_state = DynamicState {
+            line_width: Some(self.line_width),
+            ..dynamic_state.clone()
+        };
+
+        for (pipeline, vertices) in &self.batches {
+            // only the camera is needed, as for unlit materials
+            let frame_set = frame.descriptor_set(pipeline, ShaderProgram::Unlit);
+
+            builder
+                .draw(
+                    pipeline.clone(),
+                    &dynamic_state,
+                    vec![vertices.clone()],
+                    frame_set,
+                    (),
+                )
+                .unwrap();
+        }
+    }
+}
+
+/// Line list pipelines drawing `DebugDraw` primitives at the end of the main pass, blended and
+/// without writing depth.
+pub(crate) struct DebugRenderer {
+    depth_tested_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    overlay_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    // a new chunk every frame, like the instance data
+    vertex_pool: CpuBufferPool<DebugVertex>,
+    // the narrowest and widest lines the device draws
+    line_widths: [f32; 2],
+}
+
+impl DebugRenderer {
+    /// Pipelines drawing to `render_pass`, whose colour attachment has `format`.
+    pub fn new(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        format: Format,
+    ) -> Result<Self, EngineError> {
+        let encode_srgb = !display_settings::expects_linear_output(format);
+
+        Ok(DebugRenderer {
+            depth_tested_pipeline: Self::create_pipeline(
+                device.clone(),
+                render_pass.clone(),
+                Compare::Less,
+                encode_srgb,
+            )?,
+            overlay_pipeline: Self::create_pipeline(
+                device.clone(),
+                render_pass,
+                Compare::Always,
+                encode_srgb,
+            )?,
+            line_widths: if device.enabled_features().wide_lines {
+                device.physical_device().limits().line_width_range()
+            } else {
+                [1.0, 1.0]
+            },
+            vertex_pool: CpuBufferPool::vertex_buffer(device),
+        })
+    }
+
+    pub fn prepare(&self, debug: &mut DebugDraw) -> PreparedDebugLines {
+        debug.rendered = true;
+
+        let batches = [
+            (&self.depth_tested_pipeline, &debug.depth_tested),
+            (&self.overlay_pipeline, &debug.overlay),
+        ];
+
+        PreparedDebugLines {
+            batches: batches
+                .iter()
+                .filter(|(_, vertices)| !vertices.is_empty())
+                .map(|&(pipeline, vertices)| {
+                    let chunk = self.vertex_pool.chunk(vertices.iter().cloned()).unwrap();
+                    (
+                        pipeline.clone(),
+                        Arc::new(chunk) as Arc<dyn BufferAccess + Send + Sync>,
+                    )
+                })
+                .collect(),
+            line_width: debug
+                .line_width
+                .clamp(self.line_widths[0], self.line_widths[1]),
+        }
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.add(
+            MemoryCategory::VertexBuffers,
+            gpu_memory::pool_bytes(&self.vertex_pool),
+        );
+    }
+
+    fn create_pipeline(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        depth_compare: Compare,
+        encode_srgb: bool,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
+        let vertex_shader = vertex_shader::Shader::load(device.clone())?;
+        let fragment_shader = fragment_shader::Shader::load(device.clone())?;
+
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_single_buffer::<DebugVertex>()
+            .vertex_shader(vertex_shader.main_entry_point(), ())
+            .line_list()
+            .line_width_dynamic()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(
+                fragment_shader.main_entry_point(),
+                fragment_shader::SpecializationConstants {
+                    ENCODE_SRGB: encode_srgb as u32,
+                },
+            )
+            .depth_stencil(DepthStencil {
+                depth_compare,
+                depth_write: false,
+                ..DepthStencil::simple_depth_test()
+            })
+            .blend_collective(AttachmentBlend::alpha_blending())
+            .render_pass(Subpass::from(render_pass, 0).unwrap())
+            .build(device)?;
+        debug_names::name_graphics_pipeline(
+            &pipeline,
+            &format!("debug lines, depth {:?}", depth_compare),
+        );
+
+        Ok(Arc::new(pipeline))
+    }
+}
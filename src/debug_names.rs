@@ -0,0 +1,117 @@
+use std::ffi::CString;
+use std::sync::Arc;
+use vulkano::{
+    buffer::{sys::UnsafeBuffer, BufferAccess},
+    device::{Device, DeviceOwned, Queue},
+    framebuffer::{RenderPassAbstract, RenderPassSys},
+    image::{sys::UnsafeImage, ImageAccess},
+    pipeline::{
+        ComputePipelineAbstract, ComputePipelineSys, GraphicsPipelineAbstract, GraphicsPipelineSys,
+    },
+    VulkanHandle, VulkanObject,
+};
+
+use crate::{error_utils::EngineError, raw_commands::RawCommandBuffer};
+
+// Names and labels go through VK_EXT_debug_utils, which the instance only loads with the
+// validation layers. Without it every function here does nothing, so callers name their objects
+// unconditionally.
+
+/// Whether the names and labels will show up in validation messages and captures.
+pub fn enabled(device: &Device) -> bool {
+    device.instance().loaded_extensions().ext_debug_utils
+}
+
+pub fn name_buffer(buffer: &dyn BufferAccess, name: &str) {
+    let inner = buffer.inner().buffer;
+    set_name::<UnsafeBuffer>(buffer.device(), inner.internal_object().value(), name);
+}
+
+pub fn name_image(image: &dyn ImageAccess, name: &str) {
+    let inner = image.inner().image;
+    set_name::<UnsafeImage>(inner.device(), inner.internal_object().value(), name);
+}
+
+pub fn name_render_pass(render_pass: &dyn RenderPassAbstract, name: &str) {
+    let handle = render_pass.inner().internal_object().value();
+    set_name::<RenderPassSys>(render_pass.device(), handle, name);
+}
+
+pub fn name_graphics_pipeline(pipeline: &dyn GraphicsPipelineAbstract, name: &str) {
+    let handle = GraphicsPipelineAbstract::inner(pipeline)
+        .internal_object()
+        .value();
+    set_name::<GraphicsPipelineSys>(pipeline.device(), handle, name);
+}
+
+pub fn name_compute_pipeline(pipeline: &dyn ComputePipelineAbstract, name: &str) {
+    let handle = ComputePipelineAbstract::inner(pipeline)
+        .internal_object()
+        .value();
+    set_name::<ComputePipelineSys>(pipeline.device(), handle, name);
+}
+
+/// Objects vulkano hands out directly, like samplers.
+pub fn name_object<T: VulkanObject + DeviceOwned>(object: &T, name: &str) {
+    set_name::<T>(object.device(), object.internal_object().value(), name);
+}
+
+/// Returns the command buffers opening and closing a label around the work executed between
+/// them, or `None` when labels are disabled. Labels may span command buffers submitted to the
+/// same queue, so a pass keeps being recorded with `AutoCommandBufferBuilder`.
+pub fn labels(
+    device: &Arc<Device>,
+    queue: &Queue,
+    name: &str,
+) -> Result<Option<(RawCommandBuffer, RawCommandBuffer)>, EngineError> {
+    if !enabled(device) {
+        return Ok(None);
+    }
+
+    let name = label_text(name);
+    // a zero colour lets the tools pick one
+    let begin = unsafe {
+        RawCommandBuffer::record(device, queue, |builder| {
+            builder.debug_marker_begin(&name, [0.0; 4])
+        })?
+    };
+    let end =
+        unsafe { RawCommandBuffer::record(device, queue, |builder| builder.debug_marker_end())? };
+
+    Ok(Some((begin, end)))
+}
+
+// `T` only gives the object type, the handle must be one of `device`
+fn set_name<T: VulkanObject>(device: &Device, handle: u64, name: &str) {
+    if !enabled(device) {
+        return;
+    }
+
+    let text = label_text(name);
+    if let Err(err) = unsafe { device.set_object_name_raw(T::TYPE, handle, &text) } {
+        eprintln!("Could not name {}: {:?}", name, err);
+    }
+}
+
+fn label_text(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_text_is_kept_as_is() {
+        assert_eq!(label_text("shadow pass").to_str(), Ok("shadow pass"));
+        assert_eq!(label_text("floor vertices").as_bytes(), b"floor vertices");
+        assert_eq!(label_text("").as_bytes(), b"");
+    }
+
+    #[test]
+    fn nul_bytes_are_dropped_from_labels() {
+        // a name taken from a file could hold one, and must not cut the label short
+        assert_eq!(label_text("mesh\0.obj").to_str(), Ok("mesh.obj"));
+        assert_eq!(label_text("\0\0").as_bytes(), b"");
+    }
+}
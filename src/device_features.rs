@@ -0,0 +1,104 @@
+use vulkano::{
+    device::{Device, Features},
+    instance::PhysicalDevice,
+};
+
+/// Features the renderer can not run without, devices missing any of them are not suitable.
+/// Nothing beyond what every Vulkan 1.0 device has is needed for now.
+pub fn required_features() -> Features {
+    Features::none()
+}
+
+/// Features the renderer uses when the device has them, each with a fallback when it does not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptionalFeatures {
+    // line and point polygon modes, emulated by the pipeline cache without it
+    pub fill_mode_non_solid: bool,
+    // anisotropic filtering of material textures
+    pub sampler_anisotropy: bool,
+    // debug lines wider than one pixel
+    pub wide_lines: bool,
+}
+
+impl OptionalFeatures {
+    /// The optional features `device` was created with.
+    pub fn enabled_on(device: &Device) -> Self {
+        Self::from_features(device.enabled_features())
+    }
+
+    fn from_features(features: &Features) -> Self {
+        OptionalFeatures {
+            fill_mode_non_solid: features.fill_mode_non_solid,
+            sampler_anisotropy: features.sampler_anisotropy,
+            wide_lines: features.wide_lines,
+        }
+    }
+
+    // `features` with these turned on as well
+    fn enable(&self, features: Features) -> Features {
+        Features {
+            fill_mode_non_solid: features.fill_mode_non_solid || self.fill_mode_non_solid,
+            sampler_anisotropy: features.sampler_anisotropy || self.sampler_anisotropy,
+            wide_lines: features.wide_lines || self.wide_lines,
+            ..features
+        }
+    }
+}
+
+pub fn supports_required_features(physical: PhysicalDevice) -> bool {
+    physical
+        .supported_features()
+        .superset_of(&required_features())
+}
+
+/// What the logical device is created with: the required features and the optional ones
+/// `physical` supports, nothing else.
+pub fn device_features(physical: PhysicalDevice) -> Features {
+    device_features_for(physical.supported_features())
+}
+
+// `device_features` for a device supporting `supported`
+fn device_features_for(supported: &Features) -> Features {
+    OptionalFeatures::from_features(supported).enable(required_features())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_supported_optional_features_are_enabled() {
+        let enabled = device_features_for(&Features::all());
+
+        let expected = Features {
+            fill_mode_non_solid: true,
+            sampler_anisotropy: true,
+            wide_lines: true,
+            ..required_features()
+        };
+        assert_eq!(enabled, expected);
+        // everything else the device has stays off
+        assert!(!enabled.geometry_shader);
+        assert!(!enabled.robust_buffer_access);
+    }
+
+    #[test]
+    fn missing_optional_features_stay_off() {
+        assert_eq!(device_features_for(&Features::none()), required_features());
+
+        let supported = Features {
+            sampler_anisotropy: true,
+            tessellation_shader: true,
+            ..Features::none()
+        };
+        let enabled = device_features_for(&supported);
+        assert_eq!(
+            OptionalFeatures::from_features(&enabled),
+            OptionalFeatures {
+                sampler_anisotropy: true,
+                ..OptionalFeatures::default()
+            }
+        );
+        assert!(!enabled.tessellation_shader);
+    }
+}
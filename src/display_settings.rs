@@ -0,0 +1,403 @@
+use vulkano::{
+    device::DeviceExtensions,
+    format::Format,
+    instance::{InstanceExtensions, PhysicalDevice},
+    swapchain::{ColorSpace, FullscreenExclusive, PresentMode, SupportedPresentModes},
+};
+
+use crate::error_utils::EngineError;
+
+/// Colour space asked of the swapchain beyond plain sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrMode {
+    Off,
+    // scRGB: linear values in a half float image, 1.0 is SDR white and brighter goes above it.
+    // The shaders already write linear colours, so it works as is
+    ExtendedSrgbLinear,
+}
+
+/// How the swapchain format and colour space are picked from what the surface offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceFormatPolicy {
+    // SRGB formats encode the linear colours the shaders write; turning this off puts UNORM
+    // formats first, for shaders that encode sRGB themselves
+    pub prefer_srgb: bool,
+    // tried first, the SDR formats are used when the surface does not offer it
+    pub hdr: HdrMode,
+}
+
+impl Default for SurfaceFormatPolicy {
+    fn default() -> Self {
+        SurfaceFormatPolicy {
+            prefer_srgb: true,
+            hdr: HdrMode::Off,
+        }
+    }
+}
+
+impl SurfaceFormatPolicy {
+    /// The first candidate the surface offers. When it offers none of them, any sRGB colour
+    /// space format, and as a last resort whatever comes first, with a warning. Fails when the
+    /// surface offers nothing.
+    pub fn choose(
+        &self,
+        available: &[(Format, ColorSpace)],
+    ) -> Result<(Format, ColorSpace), EngineError> {
+        if let Some(&format) = self
+            .candidates()
+            .iter()
+            .find(|candidate| available.contains(candidate))
+        {
+            return Ok(format);
+        }
+
+        let fallback = available
+            .iter()
+            .find(|(_, colour_space)| *colour_space == ColorSpace::SrgbNonLinear)
+            .or_else(|| available.first())
+            .ok_or_else(|| {
+                EngineError::VulkanValidationError(String::from("The surface offers no formats"))
+            })?;
+        eprintln!(
+            "No preferred surface format is available, falling back to {:?} in {:?}",
+            fallback.0, fallback.1
+        );
+
+        Ok(*fallback)
+    }
+
+    // in order of preference
+    fn candidates(&self) -> Vec<(Format, ColorSpace)> {
+        let mut candidates = match self.hdr {
+            HdrMode::Off => Vec::new(),
+            HdrMode::ExtendedSrgbLinear => {
+                vec![(Format::R16G16B16A16Sfloat, ColorSpace::ExtendedSrgbLinear)]
+            }
+        };
+
+        let srgb = [
+            Format::B8G8R8A8Srgb,
+            Format::R8G8B8A8Srgb,
+            Format::A8B8G8R8SrgbPack32,
+        ];
+        let unorm = [
+            Format::B8G8R8A8Unorm,
+            Format::R8G8B8A8Unorm,
+            Format::A8B8G8R8UnormPack32,
+        ];
+        let (first, second) = if self.prefer_srgb {
+            (srgb, unorm)
+        } else {
+            (unorm, srgb)
+        };
+        candidates.extend(
+            first
+                .iter()
+                .chain(second.iter())
+                .map(|&format| (format, ColorSpace::SrgbNonLinear)),
+        );
+
+        candidates
+    }
+}
+
+/// When presented frames reach the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VsyncMode {
+    // FIFO: every frame waits for a vertical blank, never tears
+    On,
+    // FIFO relaxed: like On, but a late frame is shown right away, tearing instead of stuttering
+    Relaxed,
+    // mailbox: frames are rendered freely and the newest is shown at the vertical blank
+    LowLatency,
+    // immediate: frames are shown as soon as they are done, tearing
+    Off,
+}
+
+impl VsyncMode {
+    pub const ALL: [VsyncMode; 4] = [
+        VsyncMode::On,
+        VsyncMode::Relaxed,
+        VsyncMode::LowLatency,
+        VsyncMode::Off,
+    ];
+
+    pub fn next(self) -> Self {
+        match self {
+            VsyncMode::On => VsyncMode::Relaxed,
+            VsyncMode::Relaxed => VsyncMode::LowLatency,
+            VsyncMode::LowLatency => VsyncMode::Off,
+            VsyncMode::Off => VsyncMode::On,
+        }
+    }
+
+    /// Reads the name of a present mode, or of the vsync mode trying it first.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_ascii_lowercase().as_str() {
+            "fifo" | "on" => Some(VsyncMode::On),
+            "relaxed" | "fifo-relaxed" => Some(VsyncMode::Relaxed),
+            "mailbox" | "low-latency" => Some(VsyncMode::LowLatency),
+            "immediate" | "off" => Some(VsyncMode::Off),
+            _ => None,
+        }
+    }
+
+    /// The first of the mode's present modes the surface supports.
+    pub fn choose_present_mode(self, supported: SupportedPresentModes) -> PresentMode {
+        self.present_modes()
+            .iter()
+            .cloned()
+            .find(|&mode| match mode {
+                PresentMode::Immediate => supported.immediate,
+                PresentMode::Mailbox => supported.mailbox,
+                PresentMode::Relaxed => supported.relaxed,
+                _ => supported.fifo,
+            })
+            // every surface supports FIFO
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    // in order of preference, ending with FIFO
+    fn present_modes(self) -> &'static [PresentMode] {
+        match self {
+            VsyncMode::On => &[PresentMode::Fifo],
+            VsyncMode::Relaxed => &[PresentMode::Relaxed, PresentMode::Fifo],
+            VsyncMode::LowLatency => &[PresentMode::Mailbox, PresentMode::Fifo],
+            VsyncMode::Off => &[
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+            ],
+        }
+    }
+}
+
+/// Whether the display holds back frames presented with `mode`. Only the other modes go
+/// through the frame limiter.
+pub fn is_display_paced(mode: PresentMode) -> bool {
+    matches!(mode, PresentMode::Fifo | PresentMode::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    // a window without decorations covering the monitor, the desktop keeps its video mode
+    Borderless,
+    // the monitor switches to the chosen video mode and the window owns it. Whether the
+    // presentation bypasses the compositor is up to the driver, including when focus is lost
+    Exclusive,
+}
+
+impl WindowMode {
+    pub const ALL: [WindowMode; 3] = [
+        WindowMode::Windowed,
+        WindowMode::Borderless,
+        WindowMode::Exclusive,
+    ];
+
+    /// What the swapchain tells drivers with VK_EXT_full_screen_exclusive. Borderless windows
+    /// keep them from taking the display over behind our back.
+    ///
+    /// Application controlled exclusivity would let us acquire and release it ourselves, around
+    /// focus changes for instance, but vulkano does not pass the monitor Windows needs for it,
+    /// so `Swapchain::acquire_fullscreen_exclusive` is never called and drivers decide when.
+    pub fn fullscreen_exclusive(self) -> FullscreenExclusive {
+        match self {
+            WindowMode::Windowed => FullscreenExclusive::Default,
+            WindowMode::Borderless => FullscreenExclusive::Disallowed,
+            WindowMode::Exclusive => FullscreenExclusive::Allowed,
+        }
+    }
+}
+
+/// Video mode asked for in exclusive fullscreen. Unset parts pick the largest size and the
+/// highest refresh rate the monitor offers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VideoModeRequest {
+    pub size: Option<[u32; 2]>,
+    pub refresh_rate: Option<u16>,
+}
+
+/// Settings of the window and what is presented in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplaySettings {
+    pub surface_format: SurfaceFormatPolicy,
+    pub vsync: VsyncMode,
+    // frames per second when not paced by the display, None renders as fast as possible
+    pub frame_limit: Option<f32>,
+    pub window_mode: WindowMode,
+    // index in the list of monitors for fullscreen modes, None for the one the window is on
+    pub monitor: Option<usize>,
+    pub video_mode: VideoModeRequest,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            surface_format: SurfaceFormatPolicy::default(),
+            vsync: VsyncMode::LowLatency,
+            frame_limit: None,
+            window_mode: WindowMode::Windowed,
+            monitor: None,
+            video_mode: VideoModeRequest::default(),
+        }
+    }
+}
+
+/// Whether what shaders write to images of `format` is linear, either because the GPU encodes
+/// it (SRGB formats) or because it is stored as is (float formats).
+pub(crate) fn expects_linear_output(format: Format) -> bool {
+    matches!(
+        format,
+        Format::B8G8R8A8Srgb
+            | Format::R8G8B8A8Srgb
+            | Format::A8B8G8R8SrgbPack32
+            | Format::R16G16B16A16Sfloat
+    )
+}
+
+/// Instance extensions letting surfaces offer colour spaces other than sRGB and tell about
+/// fullscreen exclusivity, when the loader has them.
+pub(crate) fn surface_instance_extensions() -> InstanceExtensions {
+    let supported =
+        InstanceExtensions::supported_by_core().unwrap_or_else(|_| InstanceExtensions::none());
+
+    InstanceExtensions {
+        ext_swapchain_colorspace: supported.ext_swapchain_colorspace,
+        khr_get_surface_capabilities2: supported.khr_get_surface_capabilities2,
+        ..InstanceExtensions::none()
+    }
+}
+
+/// Device extensions used for fullscreen when the device has them.
+pub(crate) fn optional_device_extensions(physical: PhysicalDevice) -> DeviceExtensions {
+    DeviceExtensions {
+        ext_full_screen_exclusive: DeviceExtensions::supported_by_device(physical)
+            .ext_full_screen_exclusive,
+        ..DeviceExtensions::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRGB: (Format, ColorSpace) = (Format::B8G8R8A8Srgb, ColorSpace::SrgbNonLinear);
+    const UNORM: (Format, ColorSpace) = (Format::B8G8R8A8Unorm, ColorSpace::SrgbNonLinear);
+    const SCRGB: (Format, ColorSpace) =
+        (Format::R16G16B16A16Sfloat, ColorSpace::ExtendedSrgbLinear);
+
+    fn policy(prefer_srgb: bool, hdr: HdrMode) -> SurfaceFormatPolicy {
+        SurfaceFormatPolicy { prefer_srgb, hdr }
+    }
+
+    #[test]
+    fn srgb_formats_come_first_by_default() {
+        let available = [UNORM, SRGB, SCRGB];
+
+        assert_eq!(
+            SurfaceFormatPolicy::default().choose(&available).unwrap(),
+            SRGB
+        );
+    }
+
+    #[test]
+    fn unorm_formats_come_first_without_srgb_preference() {
+        let available = [SRGB, UNORM];
+
+        assert_eq!(
+            policy(false, HdrMode::Off).choose(&available).unwrap(),
+            UNORM
+        );
+        assert_eq!(policy(false, HdrMode::Off).choose(&[SRGB]).unwrap(), SRGB);
+    }
+
+    #[test]
+    fn hdr_modes_pick_their_colour_space() {
+        let available = [SRGB, SCRGB];
+
+        assert_eq!(
+            policy(true, HdrMode::ExtendedSrgbLinear)
+                .choose(&available)
+                .unwrap(),
+            SCRGB
+        );
+        // the SDR formats when the display has no HDR
+        assert_eq!(
+            policy(true, HdrMode::ExtendedSrgbLinear)
+                .choose(&[UNORM, SRGB])
+                .unwrap(),
+            SRGB
+        );
+    }
+
+    #[test]
+    fn unknown_formats_fall_back_to_srgb_colour_space() {
+        let odd = (Format::R5G6B5UnormPack16, ColorSpace::SrgbNonLinear);
+        let odd_hdr = (Format::R16G16B16A16Sfloat, ColorSpace::Bt2020Linear);
+
+        assert_eq!(
+            SurfaceFormatPolicy::default()
+                .choose(&[odd_hdr, odd])
+                .unwrap(),
+            odd
+        );
+        assert_eq!(
+            SurfaceFormatPolicy::default().choose(&[odd_hdr]).unwrap(),
+            odd_hdr
+        );
+    }
+
+    fn present_modes(immediate: bool, mailbox: bool, relaxed: bool) -> SupportedPresentModes {
+        SupportedPresentModes {
+            immediate,
+            mailbox,
+            fifo: true,
+            relaxed,
+            shared_demand: false,
+            shared_continuous: false,
+        }
+    }
+
+    #[test]
+    fn vsync_modes_pick_their_present_mode() {
+        let all = present_modes(true, true, true);
+
+        assert_eq!(VsyncMode::On.choose_present_mode(all), PresentMode::Fifo);
+        assert_eq!(
+            VsyncMode::Relaxed.choose_present_mode(all),
+            PresentMode::Relaxed
+        );
+        assert_eq!(
+            VsyncMode::LowLatency.choose_present_mode(all),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            VsyncMode::Off.choose_present_mode(all),
+            PresentMode::Immediate
+        );
+    }
+
+    #[test]
+    fn unsupported_present_modes_fall_back() {
+        let fifo_only = present_modes(false, false, false);
+        for &vsync in &VsyncMode::ALL {
+            assert_eq!(vsync.choose_present_mode(fifo_only), PresentMode::Fifo);
+        }
+
+        // without immediate, mailbox still does not wait for the display
+        let mailbox = present_modes(false, true, false);
+        assert_eq!(
+            VsyncMode::Off.choose_present_mode(mailbox),
+            PresentMode::Mailbox
+        );
+    }
+
+    #[test]
+    fn no_formats_is_an_error() {
+        assert!(matches!(
+            SurfaceFormatPolicy::default().choose(&[]),
+            Err(EngineError::VulkanValidationError(_))
+        ));
+    }
+}
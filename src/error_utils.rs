@@ -1,13 +1,23 @@
+use image::ImageError;
 use std::error;
 use std::fmt;
 use vulkano::{
+    command_buffer::CommandBufferExecError,
     device::DeviceCreationError,
+    image::ImageCreationError,
     instance::InstanceCreationError,
-    swapchain::{CapabilitiesError, SwapchainCreationError},
+    memory::DeviceMemoryAllocError,
+    pipeline::{ComputePipelineCreationError, GraphicsPipelineCreationError},
+    query::QueryPoolCreationError,
+    sampler::SamplerCreationError,
+    swapchain::{AcquireError, CapabilitiesError, SwapchainCreationError},
+    sync::FlushError,
     OomError,
 };
 use vulkano_win::CreationError;
 
+// the variants are named after the errors they wrap
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum EngineError {
     VulkanInstanceCreationError(InstanceCreationError),
@@ -17,11 +27,75 @@ pub enum EngineError {
     VulkanCapabilitiesError(CapabilitiesError),
     VulkanSwapchainCreationError(SwapchainCreationError),
     VulkanOomError(OomError),
+    VulkanDeviceMemoryAllocError(DeviceMemoryAllocError),
+    VulkanAcquireError(AcquireError),
+    VulkanFlushError(FlushError),
+    VulkanCommandBufferExecError(CommandBufferExecError),
+    VulkanImageCreationError(ImageCreationError),
+    VulkanQueryPoolCreationError(QueryPoolCreationError),
+    VulkanSamplerCreationError(SamplerCreationError),
+    VulkanGraphicsPipelineCreationError(GraphicsPipelineCreationError),
+    VulkanComputePipelineCreationError(ComputePipelineCreationError),
+    VulkanCaptureError(String),
+    ImageError(ImageError),
+    ConfigError(String),
+    LightingError(String),
+    SceneError(String),
+    MeshLoadError(String),
+    ComputeError(String),
 }
 
 impl fmt::Display for EngineError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            EngineError::VulkanInstanceCreationError(error) => {
+                write!(f, "Could not create the Vulkan instance: {}", error)
+            }
+            EngineError::VulkanDeviceCreationError(error) => {
+                write!(f, "Could not create the device: {}", error)
+            }
+            EngineError::VulkanCreationError(error) => {
+                write!(f, "Could not create the window: {}", error)
+            }
+            EngineError::VulkanCapabilitiesError(error) => {
+                write!(f, "Could not query the surface: {}", error)
+            }
+            EngineError::VulkanSwapchainCreationError(error) => {
+                write!(f, "Could not create the swapchain: {}", error)
+            }
+            EngineError::VulkanOomError(error) => write!(f, "{}", error),
+            EngineError::VulkanDeviceMemoryAllocError(error) => write!(f, "{}", error),
+            EngineError::VulkanAcquireError(error) => {
+                write!(f, "Could not acquire a swapchain image: {}", error)
+            }
+            EngineError::VulkanFlushError(error) => write!(f, "Could not submit: {}", error),
+            EngineError::VulkanCommandBufferExecError(error) => {
+                write!(f, "Could not execute a command buffer: {}", error)
+            }
+            EngineError::VulkanImageCreationError(error) => {
+                write!(f, "Could not create an image: {}", error)
+            }
+            EngineError::VulkanQueryPoolCreationError(error) => {
+                write!(f, "Could not create a query pool: {}", error)
+            }
+            EngineError::VulkanSamplerCreationError(error) => {
+                write!(f, "Could not create a sampler: {}", error)
+            }
+            EngineError::VulkanGraphicsPipelineCreationError(error) => {
+                write!(f, "Could not create a graphics pipeline: {}", error)
+            }
+            EngineError::VulkanComputePipelineCreationError(error) => {
+                write!(f, "Could not create a compute pipeline: {}", error)
+            }
+            EngineError::ImageError(error) => write!(f, "{}", error),
+            EngineError::VulkanValidationError(message)
+            | EngineError::VulkanCaptureError(message)
+            | EngineError::ConfigError(message)
+            | EngineError::LightingError(message)
+            | EngineError::SceneError(message)
+            | EngineError::MeshLoadError(message)
+            | EngineError::ComputeError(message) => write!(f, "{}", message),
+        }
     }
 }
 
@@ -62,3 +136,63 @@ impl From<OomError> for EngineError {
         EngineError::VulkanOomError(error)
     }
 }
+
+impl From<DeviceMemoryAllocError> for EngineError {
+    fn from(error: DeviceMemoryAllocError) -> Self {
+        EngineError::VulkanDeviceMemoryAllocError(error)
+    }
+}
+
+impl From<AcquireError> for EngineError {
+    fn from(error: AcquireError) -> Self {
+        EngineError::VulkanAcquireError(error)
+    }
+}
+
+impl From<FlushError> for EngineError {
+    fn from(error: FlushError) -> Self {
+        EngineError::VulkanFlushError(error)
+    }
+}
+
+impl From<CommandBufferExecError> for EngineError {
+    fn from(error: CommandBufferExecError) -> Self {
+        EngineError::VulkanCommandBufferExecError(error)
+    }
+}
+
+impl From<ImageCreationError> for EngineError {
+    fn from(error: ImageCreationError) -> Self {
+        EngineError::VulkanImageCreationError(error)
+    }
+}
+
+impl From<QueryPoolCreationError> for EngineError {
+    fn from(error: QueryPoolCreationError) -> Self {
+        EngineError::VulkanQueryPoolCreationError(error)
+    }
+}
+
+impl From<SamplerCreationError> for EngineError {
+    fn from(error: SamplerCreationError) -> Self {
+        EngineError::VulkanSamplerCreationError(error)
+    }
+}
+
+impl From<GraphicsPipelineCreationError> for EngineError {
+    fn from(error: GraphicsPipelineCreationError) -> Self {
+        EngineError::VulkanGraphicsPipelineCreationError(error)
+    }
+}
+
+impl From<ComputePipelineCreationError> for EngineError {
+    fn from(error: ComputePipelineCreationError) -> Self {
+        EngineError::VulkanComputePipelineCreationError(error)
+    }
+}
+
+impl From<ImageError> for EngineError {
+    fn from(error: ImageError) -> Self {
+        EngineError::ImageError(error)
+    }
+}
@@ -0,0 +1,108 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// limits outside of this are typos rather than frame rates
+const MIN_FPS: f32 = 1.0;
+const MAX_FPS: f32 = 1000.0;
+
+/// Caps the frame rate by sleeping before frames that would come too early.
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    /// `fps` of None does not limit anything.
+    pub fn new(fps: Option<f32>) -> Self {
+        let mut limiter = FrameLimiter {
+            frame_time: None,
+            next_frame: None,
+        };
+        limiter.set_limit(fps);
+
+        limiter
+    }
+
+    pub fn limit(&self) -> Option<f32> {
+        self.frame_time
+            .map(|frame_time| 1.0 / frame_time.as_secs_f32())
+    }
+
+    /// Limits to `fps` frames per second, clamped to 1..=1000. None removes the limit.
+    pub fn set_limit(&mut self, fps: Option<f32>) {
+        self.frame_time = fps
+            .filter(|fps| !fps.is_nan())
+            .map(|fps| Duration::from_secs_f32(1.0 / fps.clamp(MIN_FPS, MAX_FPS)));
+        self.next_frame = None;
+    }
+
+    /// Sleeps until the next frame is due. A frame running late moves the schedule instead of
+    /// the next ones rushing to catch up.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return,
+        };
+
+        let mut now = Instant::now();
+        if let Some(next_frame) = self.next_frame {
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+                now = next_frame;
+            }
+        }
+        self.next_frame = Some(now + frame_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_limit(limiter: &FrameLimiter, expected: f32) {
+        let limit = limiter.limit().expect("no limit");
+        assert!((limit - expected).abs() < 0.01, "{} != {}", limit, expected);
+    }
+
+    #[test]
+    fn limits_are_clamped() {
+        let mut limiter = FrameLimiter::new(Some(144.0));
+        assert_limit(&limiter, 144.0);
+
+        limiter.set_limit(Some(0.0));
+        assert_limit(&limiter, 1.0);
+        limiter.set_limit(Some(-30.0));
+        assert_limit(&limiter, 1.0);
+        limiter.set_limit(Some(100_000.0));
+        assert_limit(&limiter, 1000.0);
+        limiter.set_limit(Some(f32::INFINITY));
+        assert_limit(&limiter, 1000.0);
+    }
+
+    #[test]
+    fn no_limit_does_not_wait() {
+        let mut limiter = FrameLimiter::new(None);
+        assert_eq!(limiter.limit(), None);
+        limiter.set_limit(Some(f32::NAN));
+        assert_eq!(limiter.limit(), None);
+
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn frames_are_spaced_by_the_limit() {
+        let mut limiter = FrameLimiter::new(Some(100.0));
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.wait();
+        }
+        // the first frame is not held back, the three others wait 10 ms each
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+}
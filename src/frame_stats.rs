@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+// about 4 seconds at 240 Hz, enough to smooth averages and still react to changes
+pub const DEFAULT_HISTORY: usize = 1000;
+
+/// CPU side timings of one frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTimings {
+    // time since the previous frame started, the inverse of the frame rate
+    pub frame: Duration,
+    // time blocked in vkAcquireNextImageKHR waiting for a swapchain image
+    pub acquire: Duration,
+    // time to submit the command buffer, queue the present and flush
+    pub present: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimingSummary {
+    pub average_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    // 99th percentile, only 1% of the samples took longer
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStatsSummary {
+    pub samples: usize,
+    pub average_fps: f64,
+    // average frame rate of the slowest 1% of frames
+    pub one_percent_low_fps: f64,
+    pub frame: TimingSummary,
+    pub acquire: TimingSummary,
+    pub present: TimingSummary,
+}
+
+impl fmt::Display for FrameStatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} frames, {:.1} FPS average, {:.1} FPS 1% low",
+            self.samples, self.average_fps, self.one_percent_low_fps
+        )?;
+        for (name, timing) in &[
+            ("frame", self.frame),
+            ("acquire", self.acquire),
+            ("present", self.present),
+        ] {
+            writeln!(
+                f,
+                "  {:<8} avg {:.3} ms, min {:.3} ms, max {:.3} ms, p99 {:.3} ms",
+                name, timing.average_ms, timing.min_ms, timing.max_ms, timing.p99_ms
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Rolling window of the last frame timings.
+pub struct FrameStats {
+    history: usize,
+    samples: VecDeque<FrameTimings>,
+}
+
+impl FrameStats {
+    pub fn new(history: usize) -> Self {
+        FrameStats {
+            history,
+            samples: VecDeque::with_capacity(history),
+        }
+    }
+
+    pub fn record(&mut self, timings: FrameTimings) {
+        if self.samples.len() == self.history {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(timings);
+    }
+
+    pub fn summary(&self) -> FrameStatsSummary {
+        let frame = Self::milliseconds(&self.samples, |t| t.frame);
+        let acquire = Self::milliseconds(&self.samples, |t| t.acquire);
+        let present = Self::milliseconds(&self.samples, |t| t.present);
+
+        let frame_summary = Self::summarize(&frame);
+
+        FrameStatsSummary {
+            samples: self.samples.len(),
+            average_fps: Self::fps(frame_summary.average_ms),
+            one_percent_low_fps: Self::fps(Self::slowest_percent_average(&frame)),
+            frame: frame_summary,
+            acquire: Self::summarize(&acquire),
+            present: Self::summarize(&present),
+        }
+    }
+
+    /// Writes the samples in the window as CSV, one frame per line, in milliseconds.
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "frame_ms,acquire_ms,present_ms")?;
+
+        for timings in &self.samples {
+            writeln!(
+                file,
+                "{:.4},{:.4},{:.4}",
+                timings.frame.as_secs_f64() * 1000.0,
+                timings.acquire.as_secs_f64() * 1000.0,
+                timings.present.as_secs_f64() * 1000.0
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // sorted, so percentiles can be read by index
+    fn milliseconds<F>(samples: &VecDeque<FrameTimings>, field: F) -> Vec<f64>
+    where
+        F: Fn(&FrameTimings) -> Duration,
+    {
+        let mut values: Vec<f64> = samples
+            .iter()
+            .map(|t| field(t).as_secs_f64() * 1000.0)
+            .collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        values
+    }
+
+    fn summarize(sorted: &[f64]) -> TimingSummary {
+        if sorted.is_empty() {
+            return TimingSummary::default();
+        }
+
+        let p99_index = ((sorted.len() as f64 * 0.99).ceil() as usize).max(1) - 1;
+
+        TimingSummary {
+            average_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            min_ms: sorted[0],
+            max_ms: sorted[sorted.len() - 1],
+            p99_ms: sorted[p99_index],
+        }
+    }
+
+    fn slowest_percent_average(sorted: &[f64]) -> f64 {
+        if sorted.is_empty() {
+            return 0.0;
+        }
+
+        let count = (sorted.len() / 100).max(1);
+        sorted[sorted.len() - count..].iter().sum::<f64>() / count as f64
+    }
+
+    fn fps(milliseconds: f64) -> f64 {
+        if milliseconds <= 0.0 {
+            return 0.0;
+        }
+
+        1000.0 / milliseconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(milliseconds: u64) -> FrameTimings {
+        FrameTimings {
+            frame: Duration::from_millis(milliseconds),
+            acquire: Duration::from_millis(1),
+            present: Duration::from_millis(2),
+        }
+    }
+
+    // 1, 2, ... count milliseconds, recorded in reverse so sorting matters
+    fn stats_with_frames(count: u64) -> FrameStats {
+        let mut stats = FrameStats::new(DEFAULT_HISTORY);
+        for milliseconds in (1..=count).rev() {
+            stats.record(frame(milliseconds));
+        }
+        stats
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn summarize_reads_the_sorted_samples() {
+        let sorted: Vec<f64> = (1..=200).map(f64::from).collect();
+        let summary = FrameStats::summarize(&sorted);
+
+        assert_near(summary.average_ms, 100.5);
+        assert_near(summary.min_ms, 1.0);
+        assert_near(summary.max_ms, 200.0);
+        // 198 of the 200 samples are at or below the 99th percentile
+        assert_near(summary.p99_ms, 198.0);
+    }
+
+    #[test]
+    fn summarize_small_windows() {
+        let single = FrameStats::summarize(&[16.0]);
+        assert_near(single.p99_ms, 16.0);
+        assert_near(single.average_ms, 16.0);
+
+        let few: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_near(FrameStats::summarize(&few).p99_ms, 10.0);
+
+        assert_near(FrameStats::summarize(&[]).max_ms, 0.0);
+    }
+
+    #[test]
+    fn slowest_percent_averages_the_slowest_hundredth() {
+        let sorted: Vec<f64> = (1..=300).map(f64::from).collect();
+        assert_near(FrameStats::slowest_percent_average(&sorted), 299.0);
+
+        // fewer than 100 frames still count the slowest one
+        let few: Vec<f64> = (1..=42).map(f64::from).collect();
+        assert_near(FrameStats::slowest_percent_average(&few), 42.0);
+        assert_near(FrameStats::slowest_percent_average(&[]), 0.0);
+    }
+
+    #[test]
+    fn summary_of_recorded_frames() {
+        let summary = stats_with_frames(50).summary();
+
+        assert_eq!(summary.samples, 50);
+        assert_near(summary.frame.average_ms, 25.5);
+        assert_near(summary.average_fps, 1000.0 / 25.5);
+        assert_near(summary.one_percent_low_fps, 1000.0 / 50.0);
+        assert_near(summary.acquire.max_ms, 1.0);
+        assert_near(summary.present.average_ms, 2.0);
+    }
+
+    #[test]
+    fn old_frames_leave_the_window() {
+        let mut stats = FrameStats::new(10);
+        for milliseconds in 1..=25 {
+            stats.record(frame(milliseconds));
+        }
+        let summary = stats.summary();
+
+        assert_eq!(summary.samples, 10);
+        assert_near(summary.frame.min_ms, 16.0);
+        assert_near(summary.frame.max_ms, 25.0);
+    }
+
+    #[test]
+    fn empty_window_has_no_frame_rate() {
+        let summary = FrameStats::new(DEFAULT_HISTORY).summary();
+
+        assert_eq!(summary.samples, 0);
+        assert_near(summary.average_fps, 0.0);
+        assert_near(summary.one_percent_low_fps, 0.0);
+    }
+}
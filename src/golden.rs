@@ -0,0 +1,148 @@
+// Golden image harness: renders are compared against the reference PNGs in tests/golden.
+//
+// The references are rendered with lavapipe, Mesa's software Vulkan driver, so every machine
+// compares against the same output. To (re)write them from the current renders, then commit
+// tests/golden:
+//
+//   VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json GOLDEN_UPDATE=1 \
+//       cargo test -- --ignored
+//
+// When a comparison fails, a diff image (mismatching pixels in red over a dimmed copy of the
+// render) and the actual render are written to target/golden-diff.
+use image::Rgba;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{headless_renderer::HeadlessRenderer, vulkan_renderer::CapturedFrame};
+
+pub const GOLDEN_WIDTH: u32 = 256;
+pub const GOLDEN_HEIGHT: u32 = 256;
+
+// Per channel difference accepted between drivers (rasterization and interpolation precision)
+pub const DEFAULT_TOLERANCE: u8 = 2;
+
+const UPDATE_ENV_VAR: &str = "GOLDEN_UPDATE";
+
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub diff: CapturedFrame,
+}
+
+/// Returns a renderer for the image tests. They are ignored by default since they need a Vulkan
+/// device; run with `--ignored` where one is available, a missing device fails them.
+pub fn headless_renderer() -> HeadlessRenderer {
+    match HeadlessRenderer::init(GOLDEN_WIDTH, GOLDEN_HEIGHT) {
+        Ok(renderer) => renderer,
+        Err(err) => panic!("No headless renderer available: {}", err),
+    }
+}
+
+pub fn compare(actual: &CapturedFrame, expected: &CapturedFrame, tolerance: u8) -> Comparison {
+    let mut mismatched_pixels = 0;
+
+    let diff = CapturedFrame::from_fn(actual.width(), actual.height(), |x, y| {
+        let actual_pixel = actual.get_pixel(x, y);
+        let expected_pixel = expected.get_pixel(x, y);
+
+        let matches = actual_pixel
+            .0
+            .iter()
+            .zip(expected_pixel.0.iter())
+            .all(|(a, e)| (*a as i16 - *e as i16).abs() <= tolerance as i16);
+
+        if matches {
+            let [r, g, b, _] = actual_pixel.0;
+            Rgba([r / 4, g / 4, b / 4, 255])
+        } else {
+            mismatched_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+
+    Comparison {
+        mismatched_pixels,
+        diff,
+    }
+}
+
+/// Compares `actual` with tests/golden/<name>.png, panicking with the number of mismatching
+/// pixels when they differ by more than `tolerance` on any channel.
+pub fn assert_golden(name: &str, actual: &CapturedFrame, tolerance: u8) {
+    let reference_path = reference_dir().join(format!("{}.png", name));
+
+    if env::var(UPDATE_ENV_VAR).map(|v| v == "1").unwrap_or(false) {
+        fs::create_dir_all(reference_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        println!("Updated golden image {}", reference_path.display());
+        return;
+    }
+
+    let expected = match image::open(&reference_path) {
+        Ok(image) => image.to_rgba(),
+        Err(err) => panic!(
+            "Missing golden image {} ({}), run the tests with {}=1 to create it",
+            reference_path.display(),
+            err,
+            UPDATE_ENV_VAR
+        ),
+    };
+
+    if expected.dimensions() != actual.dimensions() {
+        panic!(
+            "Golden image {} is {:?} but the render is {:?}",
+            name,
+            expected.dimensions(),
+            actual.dimensions()
+        );
+    }
+
+    let comparison = compare(actual, &expected, tolerance);
+    if comparison.mismatched_pixels > 0 {
+        fs::create_dir_all(diff_dir()).unwrap();
+        let diff_path = diff_dir().join(format!("{}_diff.png", name));
+        let actual_path = diff_dir().join(format!("{}_actual.png", name));
+        comparison.diff.save(&diff_path).unwrap();
+        actual.save(&actual_path).unwrap();
+
+        panic!(
+            "Golden image {} differs in {} pixels, see {}",
+            name,
+            comparison.mismatched_pixels,
+            diff_path.display()
+        );
+    }
+}
+
+fn reference_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn diff_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden-diff")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_counts_the_pixels_past_the_tolerance() {
+        let expected = CapturedFrame::from_pixel(2, 1, Rgba([100, 100, 100, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, Rgba([102, 98, 100, 255]));
+        actual.put_pixel(1, 0, Rgba([100, 103, 100, 255]));
+
+        let comparison = compare(&actual, &expected, 2);
+        assert_eq!(comparison.mismatched_pixels, 1);
+        // matching pixels dimmed, the others red
+        assert_eq!(*comparison.diff.get_pixel(0, 0), Rgba([25, 24, 25, 255]));
+        assert_eq!(*comparison.diff.get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+
+        assert_eq!(compare(&actual, &expected, 3).mismatched_pixels, 0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::os::raw::c_void;
+use vulkano::{
+    buffer::CpuBufferPool,
+    device::RawDeviceExtensions,
+    format::Format,
+    instance::{InstanceExtensions, PhysicalDevice},
+    VulkanObject,
+};
+
+const MEMORY_BUDGET_EXTENSION: &str = "VK_EXT_memory_budget";
+// VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT, which vk-sys does not know
+const STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT: u32 = 1000237000;
+
+// VkPhysicalDeviceMemoryBudgetPropertiesEXT
+#[repr(C)]
+struct PhysicalDeviceMemoryBudgetProperties {
+    s_type: u32,
+    p_next: *mut c_void,
+    heap_budget: [u64; vk_sys::MAX_MEMORY_HEAPS as usize],
+    heap_usage: [u64; vk_sys::MAX_MEMORY_HEAPS as usize],
+}
+
+/// What the renderer's allocations are used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    // mesh vertices and per instance data
+    VertexBuffers,
+    Textures,
+    // swapchain images, depth buffers, shadow maps and offscreen targets
+    RenderTargets,
+    // host visible buffers the CPU writes every frame, such as uniforms
+    Staging,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 4] = [
+        MemoryCategory::VertexBuffers,
+        MemoryCategory::Textures,
+        MemoryCategory::RenderTargets,
+        MemoryCategory::Staging,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::VertexBuffers => "vertex buffers",
+            MemoryCategory::Textures => "textures",
+            MemoryCategory::RenderTargets => "render targets",
+            MemoryCategory::Staging => "staging",
+        }
+    }
+}
+
+/// Bytes allocated by the renderer in each category.
+///
+/// These are the sizes we asked for, from element sizes and image formats; the driver's
+/// alignment and padding are not included, so the heaps always show a bit more.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryUsage {
+    bytes: [u64; 4],
+}
+
+impl MemoryUsage {
+    pub fn add(&mut self, category: MemoryCategory, bytes: u64) {
+        self.bytes[category as usize] += bytes;
+    }
+
+    pub fn get(&self, category: MemoryCategory) -> u64 {
+        self.bytes[category as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.bytes.iter().sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeapInfo {
+    pub index: u32,
+    pub size: u64,
+    pub device_local: bool,
+    // from VK_EXT_memory_budget: how much the process can use before allocations may fail or be
+    // slow, and how much it uses, including other APIs and libraries in the process
+    pub budget: Option<u64>,
+    pub usage: Option<u64>,
+}
+
+/// The renderer's allocations next to the memory heaps of the device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    pub allocated: MemoryUsage,
+    pub heaps: Vec<HeapInfo>,
+}
+
+impl MemoryReport {
+    pub(crate) fn new(physical: PhysicalDevice, allocated: MemoryUsage) -> Self {
+        let budget = query_budget(physical);
+
+        let heaps = physical
+            .memory_heaps()
+            .map(|heap| {
+                let index = heap.id() as usize;
+                HeapInfo {
+                    index: heap.id(),
+                    size: heap.size() as u64,
+                    device_local: heap.is_device_local(),
+                    budget: budget.as_ref().map(|b| b.heap_budget[index]),
+                    usage: budget.as_ref().map(|b| b.heap_usage[index]),
+                }
+            })
+            .collect();
+
+        MemoryReport { allocated, heaps }
+    }
+
+    /// Whether the heaps have budget and usage figures.
+    pub fn has_budget(&self) -> bool {
+        self.heaps.iter().any(|heap| heap.budget.is_some())
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "allocated {}", mebibytes(self.allocated.total()))?;
+        for &category in &MemoryCategory::ALL {
+            writeln!(
+                f,
+                "  {:<16} {}",
+                category.name(),
+                mebibytes(self.allocated.get(category))
+            )?;
+        }
+
+        for heap in &self.heaps {
+            write!(
+                f,
+                "heap {} ({}) {}",
+                heap.index,
+                if heap.device_local {
+                    "device local"
+                } else {
+                    "host"
+                },
+                mebibytes(heap.size)
+            )?;
+            if let (Some(budget), Some(usage)) = (heap.budget, heap.usage) {
+                write!(
+                    f,
+                    ", budget {}, used {}",
+                    mebibytes(budget),
+                    mebibytes(usage)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        if !self.has_budget() {
+            writeln!(
+                f,
+                "no budget figures, {} is not available",
+                MEMORY_BUDGET_EXTENSION
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Instance extensions needed to read the memory budget, when the loader has them.
+pub(crate) fn budget_instance_extensions() -> InstanceExtensions {
+    let supported =
+        InstanceExtensions::supported_by_core().unwrap_or_else(|_| InstanceExtensions::none());
+
+    InstanceExtensions {
+        khr_get_physical_device_properties2: supported.khr_get_physical_device_properties2,
+        ..InstanceExtensions::none()
+    }
+}
+
+// None when the instance or the device can not give budget figures
+fn query_budget(physical: PhysicalDevice) -> Option<PhysicalDeviceMemoryBudgetProperties> {
+    let instance = physical.instance();
+    if !instance
+        .loaded_extensions()
+        .khr_get_physical_device_properties2
+    {
+        return None;
+    }
+
+    let extension = CString::new(MEMORY_BUDGET_EXTENSION).unwrap();
+    if !RawDeviceExtensions::supported_by_device(physical)
+        .iter()
+        .any(|supported| *supported == extension)
+    {
+        return None;
+    }
+
+    unsafe {
+        let mut budget: PhysicalDeviceMemoryBudgetProperties = mem::zeroed();
+        budget.s_type = STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT;
+
+        let mut properties: vk_sys::PhysicalDeviceMemoryProperties2KHR = mem::zeroed();
+        properties.sType = vk_sys::STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_PROPERTIES_2_KHR;
+        properties.pNext = &mut budget as *mut _ as *const c_void;
+
+        instance
+            .pointers()
+            .GetPhysicalDeviceMemoryProperties2KHR(physical.internal_object(), &mut properties);
+
+        Some(budget)
+    }
+}
+
+/// Bytes of a 2D image without mipmaps.
+pub(crate) fn image_bytes(dimensions: [u32; 2], format: Format) -> u64 {
+    let texel_size = format.size().unwrap_or(4) as u64;
+
+    dimensions[0] as u64 * dimensions[1] as u64 * texel_size
+}
+
+/// Bytes of the buffer a pool currently hands out chunks from.
+pub(crate) fn pool_bytes<T>(pool: &CpuBufferPool<T>) -> u64 {
+    (pool.capacity() * mem::size_of::<T>()) as u64
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::Arc;
+use vulkano::{
+    device::{Device, Queue},
+    query::{QueryType, UnsafeQueryPool},
+    sync::PipelineStages,
+    VulkanObject,
+};
+
+use crate::{error_utils::EngineError, raw_commands::RawCommandBuffer};
+
+// Results are read back this many frames after being written. By then the GPU has almost always
+// finished them, so reading never stalls the CPU; results that are still not ready are dropped.
+const FRAME_LATENCY: usize = 3;
+const MAX_REGIONS_PER_FRAME: u32 = 16;
+// weight of the newest sample in the rolling average
+const AVERAGE_WEIGHT: f64 = 0.05;
+
+// what vkGetQueryPoolResults writes per query with the availability flag requested
+type QueryResult = [u64; 2];
+
+#[derive(Debug, Clone)]
+pub struct GpuRegionTiming {
+    pub name: &'static str,
+    pub average_ms: f64,
+    pub last_ms: f64,
+}
+
+impl fmt::Display for GpuRegionTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<12} avg {:.3} ms, last {:.3} ms",
+            self.name, self.average_ms, self.last_ms
+        )
+    }
+}
+
+#[derive(Default)]
+struct FrameQueries {
+    // region i wrote its begin and end timestamps in the queries 2i and 2i + 1 of the frame
+    regions: Vec<&'static str>,
+    submitted: bool,
+}
+
+/// Measures how long named regions of a frame take on the GPU with timestamp queries.
+///
+/// Every region is surrounded by two small command buffers writing a timestamp once all the
+/// previous work on the queue is done, so the measured time includes any wait for the previous
+/// region.
+pub struct GpuProfiler {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    query_pool: UnsafeQueryPool,
+
+    // nanoseconds per timestamp tick
+    timestamp_period: f64,
+    // timestamps only have timestamp_valid_bits meaningful bits and wrap around
+    timestamp_mask: u64,
+
+    frames: Vec<FrameQueries>,
+    current_frame: usize,
+
+    timings: Vec<GpuRegionTiming>,
+}
+
+impl GpuProfiler {
+    /// Returns `None` when the queue can not write timestamps.
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Result<Option<Self>, EngineError> {
+        let valid_bits = match queue.family().timestamp_valid_bits() {
+            Some(bits) => bits,
+            None => {
+                println!(
+                    "Timestamps are not supported by the graphics queue, GPU profiling disabled"
+                );
+                return Ok(None);
+            }
+        };
+
+        let query_count = FRAME_LATENCY as u32 * MAX_REGIONS_PER_FRAME * 2;
+        let query_pool = UnsafeQueryPool::new(device.clone(), QueryType::Timestamp, query_count)?;
+
+        let timestamp_period = device.physical_device().limits().timestamp_period() as f64;
+        let timestamp_mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << valid_bits) - 1
+        };
+
+        Ok(Some(GpuProfiler {
+            device,
+            queue,
+            query_pool,
+            timestamp_period,
+            timestamp_mask,
+            frames: (0..FRAME_LATENCY)
+                .map(|_| FrameQueries::default())
+                .collect(),
+            current_frame: 0,
+            timings: Vec::new(),
+        }))
+    }
+
+    /// Resolves the frame recorded `FRAME_LATENCY` frames ago and reuses its queries.
+    pub fn begin_frame(&mut self) {
+        self.current_frame = (self.current_frame + 1) % FRAME_LATENCY;
+        self.resolve_frame(self.current_frame);
+
+        let frame = &mut self.frames[self.current_frame];
+        frame.regions.clear();
+        frame.submitted = false;
+    }
+
+    /// Must be called once the command buffers of the frame were flushed, frames that never
+    /// reached the GPU are not read back.
+    pub fn end_frame(&mut self, submitted: bool) {
+        self.frames[self.current_frame].submitted = submitted;
+    }
+
+    /// Returns the command buffers to execute right before and right after the work of the
+    /// region, or `None` when the frame ran out of queries.
+    pub fn region(
+        &mut self,
+        name: &'static str,
+    ) -> Result<Option<(RawCommandBuffer, RawCommandBuffer)>, EngineError> {
+        let frame = &mut self.frames[self.current_frame];
+        let region_index = frame.regions.len() as u32;
+        if region_index >= MAX_REGIONS_PER_FRAME {
+            return Ok(None);
+        }
+        frame.regions.push(name);
+
+        let frame_first_query = self.current_frame as u32 * MAX_REGIONS_PER_FRAME * 2;
+        let begin_query = frame_first_query + region_index * 2;
+        let bottom_of_pipe = PipelineStages {
+            bottom_of_pipe: true,
+            ..PipelineStages::none()
+        };
+
+        // queries have to be reset before being written again, the first region of the frame
+        // resets all the queries the frame may use
+        let begin = unsafe {
+            RawCommandBuffer::record(&self.device, &self.queue, |builder| {
+                if region_index == 0 {
+                    let frame_queries = self
+                        .query_pool
+                        .queries_range(frame_first_query, MAX_REGIONS_PER_FRAME * 2)
+                        .unwrap();
+                    builder.reset_query_pool(frame_queries);
+                }
+                builder
+                    .write_timestamp(self.query_pool.query(begin_query).unwrap(), bottom_of_pipe);
+            })?
+        };
+        let end = unsafe {
+            RawCommandBuffer::record(&self.device, &self.queue, |builder| {
+                builder.write_timestamp(
+                    self.query_pool.query(begin_query + 1).unwrap(),
+                    bottom_of_pipe,
+                );
+            })?
+        };
+
+        Ok(Some((begin, end)))
+    }
+
+    /// Rolling averages of every region measured so far, in the order they first appeared.
+    pub fn timings(&self) -> &[GpuRegionTiming] {
+        &self.timings
+    }
+
+    fn resolve_frame(&mut self, frame_index: usize) {
+        let frame = &self.frames[frame_index];
+        if !frame.submitted || frame.regions.is_empty() {
+            return;
+        }
+
+        let first_query = frame_index as u32 * MAX_REGIONS_PER_FRAME * 2;
+        let mut results: Vec<QueryResult> = vec![[0, 0]; frame.regions.len() * 2];
+
+        let status = unsafe {
+            let vk = self.device.pointers();
+            vk.GetQueryPoolResults(
+                self.device.internal_object(),
+                self.query_pool.internal_object(),
+                first_query,
+                results.len() as u32,
+                results.len() * mem::size_of::<QueryResult>(),
+                results.as_mut_ptr() as *mut c_void,
+                mem::size_of::<QueryResult>() as u64,
+                vk_sys::QUERY_RESULT_64_BIT | vk_sys::QUERY_RESULT_WITH_AVAILABILITY_BIT,
+            )
+        };
+        if status != vk_sys::SUCCESS && status != vk_sys::NOT_READY {
+            println!("Could not read GPU timestamps: VkResult {}", status);
+            return;
+        }
+
+        let measured: Vec<(&'static str, f64)> = frame
+            .regions
+            .iter()
+            .zip(results.chunks_exact(2))
+            .filter(|(_, queries)| queries[0][1] != 0 && queries[1][1] != 0)
+            .map(|(&name, queries)| {
+                let ticks = queries[1][0].wrapping_sub(queries[0][0]) & self.timestamp_mask;
+                (name, ticks as f64 * self.timestamp_period / 1_000_000.0)
+            })
+            .collect();
+
+        for (name, milliseconds) in measured {
+            self.record(name, milliseconds);
+        }
+    }
+
+    fn record(&mut self, name: &'static str, milliseconds: f64) {
+        match self.timings.iter_mut().find(|timing| timing.name == name) {
+            Some(timing) => {
+                timing.average_ms += (milliseconds - timing.average_ms) * AVERAGE_WEIGHT;
+                timing.last_ms = milliseconds;
+            }
+            None => self.timings.push(GpuRegionTiming {
+                name,
+                average_ms: milliseconds,
+                last_ms: milliseconds,
+            }),
+        }
+    }
+}
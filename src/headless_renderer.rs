@@ -0,0 +1,405 @@
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool},
+    command_buffer::{AutoCommandBufferBuilder, CommandBuffer, DynamicState},
+    device::{Device, DeviceExtensions, Queue},
+    format::Format,
+    framebuffer::{Framebuffer, FramebufferAbstract},
+    image::{AttachmentImage, ImageUsage},
+    instance::{debug::DebugCallback, Instance, PhysicalDevice},
+    pipeline::viewport::Viewport,
+    sync::{now, GpuFuture},
+};
+
+use crate::{
+    camera::Camera,
+    compute::{self, ComputeDispatch},
+    debug_draw::{DebugDraw, DebugRenderer},
+    debug_names,
+    device_features::{self, OptionalFeatures},
+    error_utils::EngineError,
+    gpu_memory::{self, MemoryCategory, MemoryReport, MemoryUsage},
+    instancing::{DrawQueue, MeshInstance},
+    lighting::{Lights, UboLights},
+    material::{Material, MaterialBindings},
+    mesh::Vertex,
+    overlay::{OverlayFrame, OverlayRenderer},
+    pipeline_cache::{PipelineCache, PolygonMode},
+    resources::{MeshHandle, Resources, TextureHandle},
+    scene::Scene,
+    scene_file,
+    shadows::{ShadowMap, ShadowSettings},
+    vulkan_renderer::{
+        CapturedFrame, FrameUniforms, UboViewProjection, VulkanRenderer, DEPTH_FORMAT,
+        ENABLE_VALIDATION_LAYERS,
+    },
+};
+
+// sRGB encoded like the window's default format, so captures look alike
+pub const HEADLESS_FORMAT: Format = Format::R8G8B8A8Srgb;
+
+/// Renders the same scene as `VulkanRenderer` into an offscreen image, without a window or
+/// surface. Used by the image tests, where it runs fine on software devices such as lavapipe.
+pub struct HeadlessRenderer {
+    pub device: Arc<Device>,
+
+    graphics_queue: Arc<Queue>,
+
+    target: Arc<AttachmentImage>,
+    pipelines: PipelineCache,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    dynamic_state: DynamicState,
+
+    draw_queue: DrawQueue,
+    materials: MaterialBindings,
+    default_material: Arc<Material>,
+    resources: Resources,
+
+    camera: Camera,
+    view_projection_pool: CpuBufferPool<UboViewProjection>,
+
+    lights: Lights,
+    lights_pool: CpuBufferPool<UboLights>,
+    shadow_map: ShadowMap,
+    debug_draw: DebugDraw,
+    debug_renderer: DebugRenderer,
+    compute_dispatches: Vec<ComputeDispatch>,
+    overlay_renderer: OverlayRenderer,
+    overlay_framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    overlay: Option<OverlayFrame>,
+
+    // must live to keep working
+    _debug_callback: Option<DebugCallback>,
+}
+
+impl HeadlessRenderer {
+    pub fn init(width: u32, height: u32) -> Result<Self, EngineError> {
+        let instance = VulkanRenderer::create_instance(ENABLE_VALIDATION_LAYERS)?;
+        let debug_callback =
+            VulkanRenderer::setup_debug_callback(&instance, ENABLE_VALIDATION_LAYERS);
+        let physical_device = Self::get_physical_device(&instance)?;
+        let (device, graphics_queue) = Self::create_logical_device(physical_device)?;
+
+        let target = AttachmentImage::with_usage(
+            device.clone(),
+            [width, height],
+            HEADLESS_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            },
+        )?;
+
+        let render_pass =
+            VulkanRenderer::create_render_pass(device.clone(), HEADLESS_FORMAT, false, 1)?;
+        let pipelines = PipelineCache::new(device.clone(), render_pass.clone(), HEADLESS_FORMAT)?;
+        let materials = MaterialBindings::new(device.clone(), graphics_queue.clone())?;
+        // render waits for the GPU, nothing is ever in flight
+        let resources = Resources::new(graphics_queue.clone(), 0);
+
+        let depth_buffer =
+            AttachmentImage::transient(device.clone(), [width, height], DEPTH_FORMAT)?;
+        debug_names::name_image(&*target, "headless target");
+        debug_names::name_image(&*depth_buffer, "headless depth");
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(target.clone())
+                .unwrap()
+                .add(depth_buffer)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [width as f32, height as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            ..DynamicState::none()
+        };
+
+        let shadow_map = ShadowMap::new(device.clone(), ShadowSettings::default())?;
+        let debug_renderer =
+            DebugRenderer::new(device.clone(), render_pass.clone(), HEADLESS_FORMAT)?;
+        let overlay_renderer = OverlayRenderer::new(
+            device.clone(),
+            graphics_queue.clone(),
+            HEADLESS_FORMAT,
+            false,
+        )?;
+        let overlay_framebuffer = overlay_renderer.framebuffer(target.clone());
+
+        let result = HeadlessRenderer {
+            device: device.clone(),
+            graphics_queue,
+            target,
+            pipelines,
+            framebuffer,
+            dynamic_state,
+            draw_queue: DrawQueue::new(device.clone()),
+            materials,
+            default_material: Arc::new(Material::default()),
+            resources,
+            camera: Camera::default(),
+            view_projection_pool: CpuBufferPool::uniform_buffer(device.clone()),
+            lights: Lights::default(),
+            lights_pool: CpuBufferPool::uniform_buffer(device),
+            shadow_map,
+            debug_draw: DebugDraw::default(),
+            debug_renderer,
+            compute_dispatches: Vec::new(),
+            overlay_renderer,
+            overlay_framebuffer,
+            overlay: None,
+            _debug_callback: debug_callback,
+        };
+
+        Ok(result)
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = *camera;
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Lights {
+        &mut self.lights
+    }
+
+    pub fn create_mesh(&mut self, vertices: &[Vertex]) -> Result<MeshHandle, EngineError> {
+        self.resources.create_mesh(vertices)
+    }
+
+    pub fn load_texture(&mut self, path: &Path) -> Result<TextureHandle, EngineError> {
+        self.resources.load_texture(path)
+    }
+
+    pub fn load_scene(&mut self, path: &Path) -> Result<Scene, EngineError> {
+        scene_file::load_scene(&mut self.resources, path)
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut usage = MemoryUsage::default();
+        self.resources.memory_usage(&mut usage);
+        self.draw_queue.memory_usage(&mut usage);
+        self.materials.memory_usage(&mut usage);
+        self.shadow_map.memory_usage(&mut usage);
+        self.debug_renderer.memory_usage(&mut usage);
+        self.overlay_renderer.memory_usage(&mut usage);
+
+        let dimensions = self.target.dimensions();
+        usage.add(
+            MemoryCategory::RenderTargets,
+            gpu_memory::image_bytes(dimensions, HEADLESS_FORMAT)
+                + gpu_memory::image_bytes(dimensions, DEPTH_FORMAT),
+        );
+        usage.add(
+            MemoryCategory::Staging,
+            gpu_memory::pool_bytes(&self.view_projection_pool)
+                + gpu_memory::pool_bytes(&self.lights_pool),
+        );
+
+        MemoryReport::new(self.device.physical_device(), usage)
+    }
+
+    pub fn draw_instanced(&mut self, mesh: &MeshHandle, instances: &[MeshInstance]) {
+        self.draw_queue
+            .push(mesh, &self.default_material, instances);
+    }
+
+    pub fn draw_with_material(
+        &mut self,
+        mesh: &MeshHandle,
+        material: &Arc<Material>,
+        instances: &[MeshInstance],
+    ) {
+        self.draw_queue.push(mesh, material, instances);
+    }
+
+    /// Optional features the device was created with, the others have fallbacks.
+    pub fn optional_features(&self) -> OptionalFeatures {
+        OptionalFeatures::enabled_on(&self.device)
+    }
+
+    /// Number of pipelines built so far, one per shader program and render state drawn.
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.pipelines.set_polygon_mode(polygon_mode);
+    }
+
+    /// Draws line and point modes the way devices without fill_mode_non_solid do.
+    pub fn set_polygon_modes_emulated(&mut self, emulated: bool) {
+        self.pipelines.set_polygon_modes_emulated(emulated);
+    }
+
+    /// Queues the meshes of the scene for the next frame and replaces the lights with the
+    /// scene's lights.
+    pub fn draw_scene(&mut self, scene: &mut Scene) {
+        scene.submit(&mut self.draw_queue, &mut self.lights);
+    }
+
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    pub fn set_overlay(&mut self, overlay: Option<OverlayFrame>) -> Result<(), EngineError> {
+        if let Some(overlay) = &overlay {
+            self.overlay_renderer.prepare(overlay)?;
+        }
+        self.overlay = overlay;
+
+        Ok(())
+    }
+
+    /// Queues a dispatch recorded before the passes of the next render.
+    pub fn dispatch_compute(&mut self, dispatch: ComputeDispatch) {
+        self.compute_dispatches.push(dispatch);
+    }
+
+    /// Runs the dispatches without rendering, and waits for them.
+    pub fn run_compute(&self, dispatches: &[ComputeDispatch]) -> Result<(), EngineError> {
+        compute::run(
+            self.device.clone(),
+            self.graphics_queue.clone(),
+            now(self.device.clone()).boxed(),
+            dispatches,
+        )
+    }
+
+    // nothing is in flight between two renders, the old map can go right away
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) -> Result<(), EngineError> {
+        if !self.shadow_map.update_settings(settings) {
+            self.shadow_map = ShadowMap::new(self.device.clone(), settings)?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders one frame and reads it back. Blocks until the GPU has finished.
+    pub fn render(&mut self) -> Result<CapturedFrame, EngineError> {
+        let [width, height] = self.target.dimensions();
+
+        let capture_buffer = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_destination(),
+            true,
+            (0..width * height * 4).map(|_| 0u8),
+        )?;
+        debug_names::name_buffer(&*capture_buffer, "headless capture");
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.graphics_queue.family(),
+        )
+        .unwrap();
+
+        let dispatches = std::mem::take(&mut self.compute_dispatches);
+        compute::record_dispatches(&mut builder, &dispatches)?;
+
+        let draws = self.draw_queue.prepare();
+        self.shadow_map
+            .record_shadow_pass(&mut builder, &draws, &self.lights);
+
+        let frame = FrameUniforms::new(
+            &self.view_projection_pool,
+            &self.lights_pool,
+            &self.shadow_map,
+            &self.camera,
+            &self.lights,
+            width as f32 / height as f32,
+        );
+        let debug_lines = self.debug_renderer.prepare(&mut self.debug_draw);
+
+        VulkanRenderer::record_render_pass(
+            &mut builder,
+            self.framebuffer.clone(),
+            &mut self.pipelines,
+            &self.materials,
+            &self.dynamic_state,
+            &draws,
+            &debug_lines,
+            &frame,
+        )?;
+
+        if let Some(overlay) = self.overlay.take() {
+            self.overlay_renderer.record(
+                &mut builder,
+                self.overlay_framebuffer.clone(),
+                &overlay,
+            )?;
+        }
+
+        builder
+            .copy_image_to_buffer(self.target.clone(), capture_buffer.clone())
+            .unwrap();
+
+        let command_buffer = builder.build().unwrap();
+
+        command_buffer
+            .execute_after(now(self.device.clone()), self.graphics_queue.clone())?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+        self.resources.end_frame();
+
+        let pixels = capture_buffer.read().map_err(|err| {
+            EngineError::VulkanCaptureError(format!("Could not read capture buffer: {:?}", err))
+        })?;
+        let rgba = VulkanRenderer::pixels_to_rgba(HEADLESS_FORMAT, &pixels)?;
+
+        CapturedFrame::from_raw(width, height, rgba).ok_or_else(|| {
+            EngineError::VulkanCaptureError(String::from(
+                "Capture buffer size does not match target dimensions",
+            ))
+        })
+    }
+
+    fn get_physical_device<'a>(
+        instance: &'a Arc<Instance>,
+    ) -> Result<PhysicalDevice<'a>, EngineError> {
+        // no surface to present to, any device able to draw will do
+        PhysicalDevice::enumerate(instance)
+            .find(|device| {
+                device.queue_families().any(|q| q.supports_graphics())
+                    && device_features::supports_required_features(*device)
+            })
+            .ok_or_else(|| {
+                EngineError::VulkanValidationError(String::from(
+                    "No valid physical device available",
+                ))
+            })
+    }
+
+    fn create_logical_device(
+        physical: PhysicalDevice,
+    ) -> Result<(Arc<Device>, Arc<Queue>), EngineError> {
+        let graphics_family = physical
+            .queue_families()
+            .find(|q| q.supports_graphics())
+            .unwrap();
+
+        let (device, mut queues) = Device::new(
+            physical,
+            &device_features::device_features(physical),
+            &DeviceExtensions::none(),
+            [(graphics_family, 0.5)].iter().cloned(),
+        )?;
+
+        let graphics_queue = queues.next().unwrap();
+
+        Ok((device, graphics_queue))
+    }
+}
@@ -0,0 +1,737 @@
+// Image tests: every change to the pipeline, render pass or shaders gets a scene here.
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    descriptor::descriptor_set::PersistentDescriptorSet,
+};
+
+use crate::{
+    camera::Camera,
+    compute::{ComputeDispatch, ComputeProgram},
+    device_features,
+    golden::{
+        assert_golden, compare, headless_renderer, DEFAULT_TOLERANCE, GOLDEN_HEIGHT, GOLDEN_WIDTH,
+    },
+    gpu_memory::MemoryCategory,
+    headless_renderer::HeadlessRenderer,
+    instancing::MeshInstance,
+    lighting::Light,
+    material::{BlendMode, Material, RenderState, ShaderProgram},
+    math::{Matrix4, Quaternion, Vector3},
+    mesh,
+    overlay::Overlay,
+    pipeline_cache::PolygonMode,
+    scene::{MeshComponent, Scene, Transform},
+    scene_file,
+    shadows::ShadowSettings,
+    vulkan_renderer::CapturedFrame,
+};
+
+// the triangle over a floor, as drawn by the demo
+fn draw_demo_scene(renderer: &mut HeadlessRenderer) {
+    let triangle = renderer.create_mesh(&mesh::triangle_vertices()).unwrap();
+    let floor = renderer
+        .create_mesh(&mesh::floor_vertices(2.0, [0.8, 0.8, 0.8]))
+        .unwrap();
+
+    renderer.draw_instanced(&triangle, &[MeshInstance::default()]);
+    renderer.draw_instanced(
+        &floor,
+        &[MeshInstance {
+            transform: Matrix4::translation(Vector3::new(0.0, -0.5, 0.0)),
+            ..MeshInstance::default()
+        }],
+    );
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle() {
+    let mut renderer = headless_renderer();
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("triangle", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle_from_orbit_camera() {
+    let mut renderer = headless_renderer();
+
+    let mut camera = Camera {
+        position: Vector3::new(1.5, 1.0, 1.5),
+        ..Camera::default()
+    };
+    camera.look_at(Vector3::zero());
+    renderer.set_camera(&camera);
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("triangle_from_orbit_camera", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle_directional_light() {
+    let mut renderer = headless_renderer();
+
+    let light = Light::directional(
+        Vector3::new(0.5, 0.0, -1.0),
+        Vector3::new(1.0, 1.0, 1.0),
+        1.0,
+    );
+    renderer.lights_mut().add(light).unwrap();
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("triangle_directional_light", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle_point_lights() {
+    let mut renderer = headless_renderer();
+
+    let lights = renderer.lights_mut();
+    // cleared lights must not leave anything behind in the uniform buffer
+    lights
+        .add(Light::point(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            5.0,
+        ))
+        .unwrap();
+    lights.clear();
+
+    let red = Light::point(
+        Vector3::new(-0.5, 0.0, 0.3),
+        Vector3::new(1.0, 0.2, 0.2),
+        1.0,
+    );
+    lights.add(red).unwrap();
+    let blue = Light::point(
+        Vector3::new(0.5, 0.0, 0.3),
+        Vector3::new(0.2, 0.2, 1.0),
+        1.0,
+    );
+    lights.add(blue).unwrap();
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("triangle_point_lights", &frame, DEFAULT_TOLERANCE);
+}
+
+fn shadow_test_renderer(settings: ShadowSettings) -> HeadlessRenderer {
+    let mut renderer = headless_renderer();
+    renderer.set_shadow_settings(settings).unwrap();
+
+    let mut camera = Camera {
+        position: Vector3::new(0.0, 1.0, 2.5),
+        ..Camera::default()
+    };
+    camera.look_at(Vector3::new(0.0, -0.25, 0.0));
+    renderer.set_camera(&camera);
+
+    let sun = Light::directional(
+        Vector3::new(-0.3, -1.0, -0.6),
+        Vector3::new(1.0, 1.0, 1.0),
+        1.0,
+    );
+    renderer.lights_mut().add(sun).unwrap();
+
+    renderer
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle_shadow_pcf() {
+    let mut renderer = shadow_test_renderer(ShadowSettings::default());
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("triangle_shadow_pcf", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn triangle_shadow_hard_low_resolution() {
+    let settings = ShadowSettings {
+        resolution: 256,
+        pcf_radius: 0,
+        ..ShadowSettings::default()
+    };
+    let mut renderer = shadow_test_renderer(settings);
+
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    assert_golden(
+        "triangle_shadow_hard_low_resolution",
+        &frame,
+        DEFAULT_TOLERANCE,
+    );
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn instanced_triangle_grid() {
+    let mut renderer = headless_renderer();
+
+    let light = Light::directional(
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(1.0, 1.0, 1.0),
+        1.0,
+    );
+    renderer.lights_mut().add(light).unwrap();
+
+    // a thousand triangles in a single draw, each with its own transform and tint
+    const SIDE: usize = 40;
+    let instances: Vec<MeshInstance> = (0..SIDE * 25)
+        .map(|index| {
+            let column = (index % SIDE) as f32 / SIDE as f32;
+            let row = (index / SIDE) as f32 / 25.0;
+            let offset = Vector3::new(column * 2.0 - 1.0, row * 2.0 - 1.0, -1.0);
+
+            MeshInstance {
+                transform: Matrix4::translation(offset)
+                    * Matrix4::scale(Vector3::new(0.05, 0.05, 0.05)),
+                tint: [column, row, 1.0 - column, 1.0],
+            }
+        })
+        .collect();
+
+    let triangle = renderer.create_mesh(&mesh::triangle_vertices()).unwrap();
+    renderer.draw_instanced(&triangle, &instances);
+
+    let frame = renderer.render().unwrap();
+
+    assert_golden("instanced_triangle_grid", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn draws_are_not_repeated() {
+    let mut renderer = headless_renderer();
+
+    let empty = renderer.render().unwrap();
+    draw_demo_scene(&mut renderer);
+    let drawn = renderer.render().unwrap();
+    let next = renderer.render().unwrap();
+
+    assert!(compare(&drawn, &empty, 0).mismatched_pixels > 0);
+    assert_eq!(compare(&next, &empty, 0).mismatched_pixels, 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn scene_hierarchy() {
+    let mut renderer = headless_renderer();
+
+    let triangle = renderer.create_mesh(&mesh::triangle_vertices()).unwrap();
+    let mut scene = Scene::new();
+
+    let sun = scene.add_node("sun", None, Transform::default()).unwrap();
+    scene.node_mut(sun).unwrap().light = Some(Light::directional(
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(1.0, 1.0, 1.0),
+        1.0,
+    ));
+
+    // a rotated and scaled parent: the children must follow both, and the grandchild also its
+    // parent's offset
+    let parent_transform = Transform {
+        rotation: Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), 45.0_f32.to_radians()),
+        scale: Vector3::new(0.5, 0.5, 0.5),
+        ..Transform::default()
+    };
+    let parent = scene.add_node("parent", None, parent_transform).unwrap();
+    scene.node_mut(parent).unwrap().mesh = Some(MeshComponent::new(triangle.clone()));
+
+    let child_transform = Transform::from_translation(Vector3::new(1.0, 0.0, 0.0));
+    let child = scene
+        .add_node("child", Some(parent), child_transform)
+        .unwrap();
+    scene.node_mut(child).unwrap().mesh = Some(MeshComponent {
+        material: Arc::new(Material {
+            base_colour: [1.0, 1.0, 0.0, 1.0],
+            ..Material::default()
+        }),
+        ..MeshComponent::new(triangle.clone())
+    });
+
+    let grandchild_transform = Transform::from_translation(Vector3::new(0.0, 1.0, 0.0));
+    let grandchild = scene
+        .add_node("grandchild", Some(child), grandchild_transform)
+        .unwrap();
+    scene.node_mut(grandchild).unwrap().mesh = Some(MeshComponent {
+        material: Arc::new(Material {
+            base_colour: [0.0, 1.0, 1.0, 1.0],
+            ..Material::default()
+        }),
+        ..MeshComponent::new(triangle)
+    });
+
+    // the cached world matrices must follow a parent moved after a first update
+    scene.update_world_transforms();
+    let moved_transform = Transform {
+        translation: Vector3::new(-0.25, -0.25, 0.0),
+        ..parent_transform
+    };
+    scene.set_transform(parent, moved_transform).unwrap();
+
+    renderer.draw_scene(&mut scene);
+    let frame = renderer.render().unwrap();
+
+    assert_golden("scene_hierarchy", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn empty_scene_draws_nothing() {
+    let mut renderer = headless_renderer();
+    let empty = renderer.render().unwrap();
+
+    let triangle = renderer.create_mesh(&mesh::triangle_vertices()).unwrap();
+    let mut scene = Scene::new();
+    let node = scene
+        .add_node("triangle", None, Transform::default())
+        .unwrap();
+    scene.node_mut(node).unwrap().mesh = Some(MeshComponent::new(triangle));
+    let drawn = render_scene(&mut renderer, &mut scene);
+    assert!(compare(&drawn, &empty, 0).mismatched_pixels > 0);
+
+    // neither the removed node nor the previous frame may show up
+    scene.remove_node(node).unwrap();
+    let after_remove = render_scene(&mut renderer, &mut scene);
+    assert_eq!(compare(&after_remove, &empty, 0).mismatched_pixels, 0);
+
+    let cleared = render_scene(&mut renderer, &mut Scene::new());
+    assert_eq!(compare(&cleared, &empty, 0).mismatched_pixels, 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn materials() {
+    let mut renderer = headless_renderer();
+
+    renderer
+        .lights_mut()
+        .add(Light::directional(
+            Vector3::new(0.0, -1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            1.0,
+        ))
+        .unwrap();
+
+    // 4x4 checker board, written to disk so it goes through the same loading as texture files
+    let checker = image::RgbaImage::from_fn(4, 4, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([64, 64, 255, 255])
+        }
+    });
+    let checker_path = scene_test_dir().join("checker.png");
+    checker.save(&checker_path).unwrap();
+    let checker = renderer.load_texture(&checker_path).unwrap();
+
+    let floor = renderer
+        .create_mesh(&mesh::floor_vertices(2.0, [1.0, 1.0, 1.0]))
+        .unwrap();
+    let triangle = renderer.create_mesh(&mesh::triangle_vertices()).unwrap();
+    let at = |x: f32, y: f32, z: f32| MeshInstance {
+        transform: Matrix4::translation(Vector3::new(x, y, z)),
+        ..MeshInstance::default()
+    };
+
+    let textured = Arc::new(Material {
+        base_colour_texture: Some(checker),
+        shininess: 8.0,
+        ..Material::default()
+    });
+    renderer.draw_with_material(&floor, &textured, &[at(0.0, -0.5, 0.0)]);
+
+    // unaffected by the light, so as bright as its vertex colours
+    let unlit = Arc::new(Material {
+        shader: ShaderProgram::Unlit,
+        ..Material::default()
+    });
+    renderer.draw_with_material(&triangle, &unlit, &[at(-0.6, 0.3, 0.0)]);
+
+    // seen from behind: culled unless double sided, and then lit from its flipped normal
+    let double_sided = Arc::new(Material {
+        base_colour: [1.0, 1.0, 0.0, 1.0],
+        state: RenderState {
+            double_sided: true,
+            ..RenderState::default()
+        },
+        ..Material::default()
+    });
+    let turned = MeshInstance {
+        transform: Matrix4::translation(Vector3::new(0.6, 0.3, 0.0))
+            * Quaternion::from_axis_angle(Vector3::up(), 180.0_f32.to_radians()).to_matrix(),
+        ..MeshInstance::default()
+    };
+    renderer.draw_with_material(&triangle, &double_sided, &[turned]);
+
+    // drawn after every opaque draw, including the ones queued later
+    let glass = Arc::new(Material {
+        base_colour: [1.0, 1.0, 1.0, 0.4],
+        state: RenderState {
+            blend: BlendMode::AlphaBlend,
+            depth_write: false,
+            ..RenderState::default()
+        },
+        ..Material::default()
+    });
+    renderer.draw_with_material(&triangle, &glass, &[at(-0.3, -0.4, 0.5)]);
+
+    let glow = Arc::new(Material {
+        shader: ShaderProgram::Unlit,
+        base_colour: [0.5, 0.2, 0.0, 1.0],
+        state: RenderState {
+            blend: BlendMode::Additive,
+            depth_write: false,
+            ..RenderState::default()
+        },
+        ..Material::default()
+    });
+    renderer.draw_with_material(&triangle, &glow, &[at(0.3, -0.4, 0.5)]);
+
+    // same shader and state as the textured floor, so it reuses its pipeline
+    let plain = Arc::new(Material {
+        base_colour: [0.2, 1.0, 0.2, 1.0],
+        ..Material::default()
+    });
+    renderer.draw_with_material(&triangle, &plain, &[at(0.0, 0.6, -0.5)]);
+
+    let frame = renderer.render().unwrap();
+
+    assert_golden("materials", &frame, DEFAULT_TOLERANCE);
+    assert_eq!(renderer.pipeline_count(), 5);
+}
+
+fn render_scene(renderer: &mut HeadlessRenderer, scene: &mut Scene) -> CapturedFrame {
+    scene.update_world_transforms();
+    if let Some(camera) = scene.camera() {
+        renderer.set_camera(&camera);
+    }
+
+    renderer.draw_scene(scene);
+    renderer.render().unwrap()
+}
+
+fn scene_test_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("scene-tests");
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn scene_file_demo_round_trip() {
+    let mut renderer = headless_renderer();
+
+    let demo_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("scenes")
+        .join("demo.json");
+    let mut scene = renderer.load_scene(&demo_path).unwrap();
+    let frame = render_scene(&mut renderer, &mut scene);
+    assert_golden("scene_file_demo", &frame, DEFAULT_TOLERANCE);
+
+    // saving and loading again must give the same picture
+    let saved_path = scene_test_dir().join("demo_saved.json");
+    scene_file::save_scene(&scene, &saved_path).unwrap();
+    let mut reloaded = renderer.load_scene(&saved_path).unwrap();
+    let frame = render_scene(&mut renderer, &mut reloaded);
+    assert_golden("scene_file_demo", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn resources_are_shared_and_released() {
+    let mut renderer = headless_renderer();
+
+    let pixel_path = scene_test_dir().join("pixel.png");
+    image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]))
+        .save(&pixel_path)
+        .unwrap();
+
+    // the same file and primitive loaded twice are loaded once
+    let texture = renderer.load_texture(&pixel_path).unwrap();
+    let same_texture = renderer.load_texture(&pixel_path).unwrap();
+    assert!(texture == same_texture);
+    let triangle = renderer
+        .resources_mut()
+        .load_mesh("triangle", mesh::MeshSource::Triangle)
+        .unwrap();
+    let same_triangle = renderer
+        .resources_mut()
+        .load_mesh("other name", mesh::MeshSource::Triangle)
+        .unwrap();
+    assert!(triangle == same_triangle);
+
+    let report = renderer.resources().residency();
+    assert_eq!(report.count("mesh"), 1);
+    assert_eq!(report.count("texture"), 1);
+    assert_eq!(report.resources[0].handles, 2);
+    let vertex_size = std::mem::size_of::<mesh::Vertex>() as u64;
+    assert_eq!(
+        report.total_bytes(),
+        3 * vertex_size + 2 * 2 * 4,
+        "{}",
+        report
+    );
+
+    // the queued draw keeps the mesh alive after its handles are gone
+    renderer.draw_instanced(&triangle, &[MeshInstance::default()]);
+    drop((texture, same_texture, triangle, same_triangle));
+    let report = renderer.resources().residency();
+    assert_eq!(report.count("mesh"), 1, "{}", report);
+    assert_eq!(report.resources[0].bytes, 3 * vertex_size, "{}", report);
+    assert_eq!(report.count("texture"), 0, "{}", report);
+
+    // once it has been drawn, the triangle goes too
+    renderer.render().unwrap();
+    let report = renderer.resources().residency();
+    assert_eq!(report.count("mesh"), 0, "{}", report);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn memory_report_counts_each_category() {
+    let mut renderer = headless_renderer();
+
+    draw_demo_scene(&mut renderer);
+    renderer.render().unwrap();
+
+    let report = renderer.memory_report();
+    let vertex_size = std::mem::size_of::<mesh::Vertex>() as u64;
+    // the triangle and the floor, plus the instance data of the frame
+    assert!(
+        report.allocated.get(MemoryCategory::VertexBuffers) > 9 * vertex_size,
+        "{}",
+        report
+    );
+    // the white texture of the materials
+    assert_eq!(
+        report.allocated.get(MemoryCategory::Textures),
+        4,
+        "{}",
+        report
+    );
+    // colour target, depth buffer and shadow map
+    let target_bytes = GOLDEN_WIDTH as u64 * GOLDEN_HEIGHT as u64 * (4 + 2);
+    assert!(
+        report.allocated.get(MemoryCategory::RenderTargets) > target_bytes,
+        "{}",
+        report
+    );
+    assert!(
+        report.allocated.get(MemoryCategory::Staging) > 0,
+        "{}",
+        report
+    );
+
+    assert!(!report.heaps.is_empty());
+    let heap_bytes: u64 = report.heaps.iter().map(|heap| heap.size).sum();
+    assert!(report.allocated.total() < heap_bytes, "{}", report);
+    for heap in &report.heaps {
+        if let Some(budget) = heap.budget {
+            assert!(budget <= heap.size, "{}", report);
+        }
+    }
+}
+
+mod double_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+        #version 450
+
+        layout(local_size_x = 64) in;
+
+        layout(set = 0, binding = 0) buffer Values {
+            uint values[];
+        } buf;
+
+        void main() {
+            uint i = gl_GlobalInvocationID.x;
+            if (i < buf.values.length()) {
+                buf.values[i] *= 2;
+            }
+        }"
+    }
+}
+
+// doubles every value of a storage buffer the CPU can read back
+fn double_dispatch(
+    renderer: &HeadlessRenderer,
+    values: &[u32],
+) -> (ComputeDispatch, Arc<CpuAccessibleBuffer<[u32]>>) {
+    let device = renderer.device.clone();
+    let shader = double_shader::Shader::load(device.clone()).unwrap();
+    let program = ComputeProgram::new(device.clone(), &shader.main_entry_point()).unwrap();
+
+    let buffer =
+        CpuAccessibleBuffer::from_iter(device, BufferUsage::all(), false, values.iter().cloned())
+            .unwrap();
+    let set = PersistentDescriptorSet::start(program.descriptor_set_layout(0).unwrap())
+        .add_buffer(buffer.clone())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let dispatch = ComputeDispatch::covering(
+        program,
+        vec![Arc::new(set)],
+        [values.len() as u32, 1, 1],
+        [64, 1, 1],
+    );
+
+    (dispatch, buffer)
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn compute_standalone() {
+    let renderer = headless_renderer();
+
+    // not a multiple of the workgroup size, the last group is partly idle
+    let values: Vec<u32> = (0..1000).collect();
+    let (dispatch, buffer) = double_dispatch(&renderer, &values);
+    assert_eq!(dispatch.workgroups, [16, 1, 1]);
+
+    renderer.run_compute(&[dispatch]).unwrap();
+
+    let doubled: Vec<u32> = values.iter().map(|value| value * 2).collect();
+    assert_eq!(&*buffer.read().unwrap(), &doubled[..]);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn compute_within_frame() {
+    let mut renderer = headless_renderer();
+
+    let values: Vec<u32> = (0..64).collect();
+    let (dispatch, buffer) = double_dispatch(&renderer, &values);
+    renderer.dispatch_compute(dispatch);
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+
+    let doubled: Vec<u32> = values.iter().map(|value| value * 2).collect();
+    assert_eq!(&*buffer.read().unwrap(), &doubled[..]);
+    // the dispatch leaves the drawing alone
+    assert_golden("triangle", &frame, DEFAULT_TOLERANCE);
+
+    // dispatches run once, like draws
+    renderer.render().unwrap();
+    assert_eq!(&*buffer.read().unwrap(), &doubled[..]);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn device_has_only_the_selected_features() {
+    let renderer = headless_renderer();
+    let physical = renderer.device.physical_device();
+
+    assert_eq!(
+        renderer.device.enabled_features(),
+        &device_features::device_features(physical)
+    );
+    let supported = physical.supported_features();
+    let optional = renderer.optional_features();
+    assert_eq!(optional.fill_mode_non_solid, supported.fill_mode_non_solid);
+    assert_eq!(optional.sampler_anisotropy, supported.sampler_anisotropy);
+    assert_eq!(optional.wide_lines, supported.wide_lines);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn polygon_modes() {
+    let mut renderer = headless_renderer();
+
+    for &emulated in &[false, true] {
+        renderer.set_polygon_modes_emulated(emulated);
+
+        for &mode in &[PolygonMode::Line, PolygonMode::Point] {
+            renderer.set_polygon_mode(mode);
+            draw_demo_scene(&mut renderer);
+            let frame = renderer.render().unwrap();
+
+            let name = format!(
+                "polygon_mode_{:?}{}",
+                mode,
+                if emulated { "_emulated" } else { "" }
+            );
+            assert_golden(&name.to_lowercase(), &frame, DEFAULT_TOLERANCE);
+        }
+    }
+
+    // back to the regular rendering
+    renderer.set_polygon_mode(PolygonMode::Fill);
+    draw_demo_scene(&mut renderer);
+    let frame = renderer.render().unwrap();
+    assert_golden("triangle", &frame, DEFAULT_TOLERANCE);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn debug_draw() {
+    let mut renderer = headless_renderer();
+
+    draw_demo_scene(&mut renderer);
+    let debug = renderer.debug_draw();
+    debug.grid(Vector3::new(0.0, -0.499, 0.0), 4, 0.5, [0.2, 0.2, 0.2, 1.0]);
+    debug.aabb(
+        Vector3::new(-0.5, -0.25, -0.1),
+        Vector3::new(0.5, 0.5, 0.1),
+        [1.0, 1.0, 0.0, 1.0],
+    );
+    debug.sphere(Vector3::new(0.5, 0.5, 0.0), 0.2, [0.0, 1.0, 1.0, 1.0]);
+    // drawn over the triangle
+    debug.depth_test = false;
+    debug.axes(&Matrix4::translation(Vector3::new(0.0, 0.0, -0.5)), 0.5);
+    debug.depth_test = true;
+    assert_eq!(debug.line_count(), 10 + 12 + 3 * 24 + 3);
+
+    let frame = renderer.render().unwrap();
+    assert_golden("debug_draw", &frame, DEFAULT_TOLERANCE);
+
+    // lines added after a render replace the previous ones
+    renderer
+        .debug_draw()
+        .line(Vector3::zero(), Vector3::up(), [1.0; 4]);
+    assert_eq!(renderer.debug_draw().line_count(), 1);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device, run with --ignored"]
+fn overlay() {
+    let mut renderer = headless_renderer();
+
+    draw_demo_scene(&mut renderer);
+    let mut overlay = Overlay::new();
+    let context = overlay.begin_frame([GOLDEN_WIDTH, GOLDEN_HEIGHT], 1.0);
+    egui::Window::new("Overlay")
+        .fixed_pos(egui::pos2(10.0, 10.0))
+        .show(&context, |ui| {
+            ui.label("drawn over the scene");
+            ui.checkbox(&mut true, "checked");
+        });
+    renderer.set_overlay(Some(overlay.end_frame())).unwrap();
+
+    let frame = renderer.render().unwrap();
+    assert_golden("overlay", &frame, DEFAULT_TOLERANCE);
+}
@@ -0,0 +1,761 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
+};
+
+use crate::{config::ConfigFile, error_utils::EngineError};
+
+// Keys that can be named in the input file, by their winit name (A, F1, Space, LShift...)
+const BINDABLE_KEYS: &[VirtualKeyCode] = &[
+    VirtualKeyCode::A,
+    VirtualKeyCode::B,
+    VirtualKeyCode::C,
+    VirtualKeyCode::D,
+    VirtualKeyCode::E,
+    VirtualKeyCode::F,
+    VirtualKeyCode::G,
+    VirtualKeyCode::H,
+    VirtualKeyCode::I,
+    VirtualKeyCode::J,
+    VirtualKeyCode::K,
+    VirtualKeyCode::L,
+    VirtualKeyCode::M,
+    VirtualKeyCode::N,
+    VirtualKeyCode::O,
+    VirtualKeyCode::P,
+    VirtualKeyCode::Q,
+    VirtualKeyCode::R,
+    VirtualKeyCode::S,
+    VirtualKeyCode::T,
+    VirtualKeyCode::U,
+    VirtualKeyCode::V,
+    VirtualKeyCode::W,
+    VirtualKeyCode::X,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::Key0,
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+    VirtualKeyCode::F1,
+    VirtualKeyCode::F2,
+    VirtualKeyCode::F3,
+    VirtualKeyCode::F4,
+    VirtualKeyCode::F5,
+    VirtualKeyCode::F6,
+    VirtualKeyCode::F7,
+    VirtualKeyCode::F8,
+    VirtualKeyCode::F9,
+    VirtualKeyCode::F10,
+    VirtualKeyCode::F11,
+    VirtualKeyCode::F12,
+    VirtualKeyCode::Escape,
+    VirtualKeyCode::Tab,
+    VirtualKeyCode::Space,
+    VirtualKeyCode::Return,
+    VirtualKeyCode::Back,
+    VirtualKeyCode::Delete,
+    VirtualKeyCode::Insert,
+    VirtualKeyCode::Home,
+    VirtualKeyCode::End,
+    VirtualKeyCode::PageUp,
+    VirtualKeyCode::PageDown,
+    VirtualKeyCode::Left,
+    VirtualKeyCode::Right,
+    VirtualKeyCode::Up,
+    VirtualKeyCode::Down,
+    VirtualKeyCode::LShift,
+    VirtualKeyCode::RShift,
+    VirtualKeyCode::LControl,
+    VirtualKeyCode::RControl,
+    VirtualKeyCode::LAlt,
+    VirtualKeyCode::RAlt,
+    VirtualKeyCode::Grave,
+    VirtualKeyCode::Minus,
+    VirtualKeyCode::Equals,
+    VirtualKeyCode::Comma,
+    VirtualKeyCode::Period,
+    VirtualKeyCode::Slash,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// A key or mouse button, plus the modifiers that must be held with it (Ctrl+S).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub source: InputSource,
+    pub modifiers: ModifiersState,
+}
+
+impl Binding {
+    pub fn key(key: VirtualKeyCode) -> Self {
+        Binding {
+            source: InputSource::Key(key),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Binding {
+            source: InputSource::Mouse(button),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    /// Parses names like "F12", "Ctrl+S", "Shift+Alt+Return" or "MouseLeft".
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = ModifiersState::empty();
+        let mut parts: Vec<&str> = text.split('+').map(|part| part.trim()).collect();
+        let name = parts.pop()?;
+
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= ModifiersState::CTRL,
+                "shift" => modifiers |= ModifiersState::SHIFT,
+                "alt" => modifiers |= ModifiersState::ALT,
+                "logo" | "super" => modifiers |= ModifiersState::LOGO,
+                _ => return None,
+            }
+        }
+
+        let source = match name.to_ascii_lowercase().as_str() {
+            "mouseleft" => InputSource::Mouse(MouseButton::Left),
+            "mouseright" => InputSource::Mouse(MouseButton::Right),
+            "mousemiddle" => InputSource::Mouse(MouseButton::Middle),
+            "enter" => InputSource::Key(VirtualKeyCode::Return),
+            "backspace" => InputSource::Key(VirtualKeyCode::Back),
+            _ => InputSource::Key(Self::parse_key(name)?),
+        };
+
+        Some(Binding { source, modifiers })
+    }
+
+    fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+        // digits are named Key0..Key9 by winit, but "1" is what people write
+        let name = if name.len() == 1 && name.chars().all(|c| c.is_ascii_digit()) {
+            format!("Key{}", name)
+        } else {
+            name.to_string()
+        };
+
+        BINDABLE_KEYS
+            .iter()
+            .find(|key| format!("{:?}", key).eq_ignore_ascii_case(&name))
+            .cloned()
+    }
+}
+
+// written the way `parse` reads it back
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in &[
+            (ModifiersState::CTRL, "Ctrl"),
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::LOGO, "Logo"),
+        ] {
+            if self.modifiers.contains(*modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        match self.source {
+            InputSource::Key(key) => write!(f, "{:?}", key),
+            InputSource::Mouse(button) => write!(f, "Mouse{:?}", button),
+        }
+    }
+}
+
+impl InputSource {
+    // the modifier a key sets itself, which is already active when its own press arrives
+    fn own_modifier(self) -> ModifiersState {
+        match self {
+            InputSource::Key(VirtualKeyCode::LShift) | InputSource::Key(VirtualKeyCode::RShift) => {
+                ModifiersState::SHIFT
+            }
+            InputSource::Key(VirtualKeyCode::LControl)
+            | InputSource::Key(VirtualKeyCode::RControl) => ModifiersState::CTRL,
+            InputSource::Key(VirtualKeyCode::LAlt) | InputSource::Key(VirtualKeyCode::RAlt) => {
+                ModifiersState::ALT
+            }
+            InputSource::Key(VirtualKeyCode::LWin) | InputSource::Key(VirtualKeyCode::RWin) => {
+                ModifiersState::LOGO
+            }
+            _ => ModifiersState::empty(),
+        }
+    }
+}
+
+/// Two bindings driving a value from -1 (negative held) to 1 (positive held).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisBinding {
+    pub positive: Binding,
+    pub negative: Binding,
+}
+
+/// Names the things the app reacts to and which bindings trigger them.
+///
+/// Loaded from a file with an [actions] section (`name = binding, binding...`) and an [axes]
+/// section (`name = positive, negative`). Bindings can be changed at runtime.
+#[derive(Debug, Clone, Default)]
+pub struct InputMap {
+    actions: HashMap<String, Vec<Binding>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    pub fn load(path: &Path) -> Result<Self, EngineError> {
+        Self::from_config(&ConfigFile::load(path)?)
+    }
+
+    pub fn from_config(config: &ConfigFile) -> Result<Self, EngineError> {
+        let mut map = InputMap::default();
+
+        if let Some(section) = config.section("actions") {
+            for entry in &section.entries {
+                for text in entry.value.split(',') {
+                    let binding = Binding::parse(text).ok_or_else(|| {
+                        config.entry_error(entry, &format!("unknown binding '{}'", text.trim()))
+                    })?;
+                    map.bind_action(&entry.key, binding);
+                }
+            }
+        }
+
+        if let Some(section) = config.section("axes") {
+            for entry in &section.entries {
+                let bindings: Vec<&str> = entry.value.split(',').collect();
+                if bindings.len() != 2 {
+                    return Err(
+                        config.entry_error(entry, "axes need a positive and a negative binding")
+                    );
+                }
+
+                let mut parsed = Vec::new();
+                for text in bindings {
+                    parsed.push(Binding::parse(text).ok_or_else(|| {
+                        config.entry_error(entry, &format!("unknown binding '{}'", text.trim()))
+                    })?);
+                }
+
+                map.bind_axis(
+                    &entry.key,
+                    AxisBinding {
+                        positive: parsed[0],
+                        negative: parsed[1],
+                    },
+                );
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Loads the file when there is one, so the app still runs without it.
+    pub fn load_or_default(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::defaults();
+        }
+
+        match Self::load(path) {
+            Ok(map) => map,
+            Err(err) => {
+                eprintln!("Using default input bindings: {}", err);
+                Self::defaults()
+            }
+        }
+    }
+
+    pub fn defaults() -> Self {
+        let mut map = InputMap::default();
+
+        map.bind_action("capture_frame", Binding::key(VirtualKeyCode::F12));
+        map.bind_action("toggle_camera_mode", Binding::key(VirtualKeyCode::Tab));
+        map.bind_action("toggle_stats_title", Binding::key(VirtualKeyCode::F3));
+        map.bind_action("cycle_polygon_mode", Binding::key(VirtualKeyCode::F4));
+        map.bind_action("save_scene", Binding::key(VirtualKeyCode::F5));
+        map.bind_action("print_resources", Binding::key(VirtualKeyCode::F6));
+        map.bind_action("print_memory", Binding::key(VirtualKeyCode::F7));
+        map.bind_action("toggle_debug_draw", Binding::key(VirtualKeyCode::F8));
+        map.bind_action("toggle_overlay", Binding::key(VirtualKeyCode::F1));
+        map.bind_action("cycle_vsync_mode", Binding::key(VirtualKeyCode::F9));
+        map.bind_action("toggle_preview_window", Binding::key(VirtualKeyCode::F10));
+        map.bind_action(
+            "toggle_fullscreen",
+            Binding {
+                source: InputSource::Key(VirtualKeyCode::Return),
+                modifiers: ModifiersState::ALT,
+            },
+        );
+        map.bind_action("orbit_rotate", Binding::mouse(MouseButton::Left));
+
+        map.bind_axis(
+            "move_forward",
+            AxisBinding {
+                positive: Binding::key(VirtualKeyCode::W),
+                negative: Binding::key(VirtualKeyCode::S),
+            },
+        );
+        map.bind_axis(
+            "move_right",
+            AxisBinding {
+                positive: Binding::key(VirtualKeyCode::D),
+                negative: Binding::key(VirtualKeyCode::A),
+            },
+        );
+        map.bind_axis(
+            "move_up",
+            AxisBinding {
+                positive: Binding::key(VirtualKeyCode::Space),
+                negative: Binding::key(VirtualKeyCode::LShift),
+            },
+        );
+
+        map
+    }
+
+    pub fn bind_action(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces every binding of `action` with `binding`.
+    pub fn rebind_action(&mut self, action: &str, binding: Binding) {
+        self.actions.insert(action.to_string(), vec![binding]);
+    }
+
+    /// Removes the bindings of `action`, it stays listed so it can be bound again.
+    pub fn unbind_action(&mut self, action: &str) {
+        if let Some(bindings) = self.actions.get_mut(action) {
+            bindings.clear();
+        }
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.insert(axis.to_string(), vec![binding]);
+    }
+
+    pub fn action_bindings(&self, action: &str) -> &[Binding] {
+        self.actions
+            .get(action)
+            .map(|b| b.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    /// Names of the known actions, sorted.
+    pub fn action_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.actions.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Names of the known axes, sorted.
+    pub fn axis_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.axes.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Tracks keyboard and mouse state from winit events and answers action queries.
+///
+/// "Pressed" only lasts for the frame the press happened in: call `end_frame` once the frame has
+/// been processed. Key repeats do not count as new presses.
+///
+/// Actions only fire with exactly their modifiers held, so Ctrl+S does not fire on Ctrl+Shift+S.
+/// Axes only need their modifiers, so moving keeps working while Shift or Ctrl is held.
+pub struct Input {
+    pub map: InputMap,
+
+    modifiers: ModifiersState,
+    held: HashSet<InputSource>,
+    // modifiers are kept with each press, so Ctrl+S still fires if Ctrl is released first
+    pressed: Vec<(InputSource, ModifiersState)>,
+
+    mouse_delta: (f32, f32),
+    scroll_delta: f32,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Input {
+            map,
+            modifiers: ModifiersState::empty(),
+            held: HashSet::new(),
+            pressed: Vec::new(),
+            mouse_delta: (0.0, 0.0),
+            scroll_delta: 0.0,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => self.set_source_state(InputSource::Key(*key), *state),
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set_source_state(InputSource::Mouse(*button), *state)
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly one line per 20 pixels
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            }
+            // losing focus means we will not get the releases
+            WindowEvent::Focused(false) => {
+                self.held.clear();
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => (),
+        }
+    }
+
+    // raw mouse motion keeps working when the cursor is grabbed and does not hit screen borders
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta.0 += delta.0 as f32;
+            self.mouse_delta.1 += delta.1 as f32;
+        }
+    }
+
+    /// Clears the per frame state (presses, mouse motion and scroll).
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+    }
+
+    /// True on the frame one of the action bindings was pressed.
+    pub fn action(&self, action: &str) -> bool {
+        self.map.action_bindings(action).iter().any(|binding| {
+            self.pressed.iter().any(|(source, modifiers)| {
+                *source == binding.source && Self::modifiers_match(binding, *modifiers)
+            })
+        })
+    }
+
+    /// True while one of the action bindings is down.
+    pub fn action_held(&self, action: &str) -> bool {
+        self.map.action_bindings(action).iter().any(|binding| {
+            self.held.contains(&binding.source) && Self::modifiers_match(binding, self.modifiers)
+        })
+    }
+
+    /// From -1 to 1, depending on which side of the axis is held.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let value: f32 = self
+            .map
+            .axis_bindings(axis)
+            .iter()
+            .map(|binding| {
+                let mut value = 0.0;
+                if self.is_axis_held(&binding.positive) {
+                    value += 1.0;
+                }
+                if self.is_axis_held(&binding.negative) {
+                    value -= 1.0;
+                }
+                value
+            })
+            .sum();
+
+        value.clamp(-1.0, 1.0)
+    }
+
+    /// The last binding pressed this frame, used to capture a new binding when rebinding.
+    pub fn last_pressed(&self) -> Option<Binding> {
+        self.pressed.last().map(|(source, modifiers)| Binding {
+            source: *source,
+            modifiers: *modifiers - source.own_modifier(),
+        })
+    }
+
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    fn is_axis_held(&self, binding: &Binding) -> bool {
+        self.held.contains(&binding.source) && self.modifiers.contains(binding.modifiers)
+    }
+
+    fn modifiers_match(binding: &Binding, active: ModifiersState) -> bool {
+        active - binding.source.own_modifier() == binding.modifiers
+    }
+
+    fn set_source_state(&mut self, source: InputSource, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // insert returns false for key repeats
+                if self.held.insert(source) {
+                    self.pressed.push((source, self.modifiers));
+                }
+            }
+            ElementState::Released => {
+                self.held.remove(&source);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl_shift() -> ModifiersState {
+        ModifiersState::CTRL | ModifiersState::SHIFT
+    }
+
+    fn map(source: &str) -> Result<InputMap, EngineError> {
+        InputMap::from_config(&ConfigFile::parse(source, "input.cfg")?)
+    }
+
+    fn load_error(source: &str) -> String {
+        match map(source) {
+            Err(EngineError::ConfigError(message)) => message,
+            other => panic!("not a config error: {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn press(input: &mut Input, source: InputSource, modifiers: ModifiersState) {
+        input.handle_window_event(&WindowEvent::ModifiersChanged(modifiers));
+        input.set_source_state(source, ElementState::Pressed);
+    }
+
+    #[test]
+    fn parses_keys_and_mouse_buttons() {
+        assert_eq!(
+            Binding::parse("F12"),
+            Some(Binding::key(VirtualKeyCode::F12))
+        );
+        assert_eq!(
+            Binding::parse(" space "),
+            Some(Binding::key(VirtualKeyCode::Space))
+        );
+        assert_eq!(
+            Binding::parse("1"),
+            Some(Binding::key(VirtualKeyCode::Key1))
+        );
+        assert_eq!(
+            Binding::parse("Enter"),
+            Some(Binding::key(VirtualKeyCode::Return))
+        );
+        assert_eq!(
+            Binding::parse("Backspace"),
+            Some(Binding::key(VirtualKeyCode::Back))
+        );
+        assert_eq!(
+            Binding::parse("MouseRight"),
+            Some(Binding::mouse(MouseButton::Right))
+        );
+    }
+
+    #[test]
+    fn parses_modifier_combinations() {
+        assert_eq!(
+            Binding::parse("Ctrl+Shift+S"),
+            Some(Binding {
+                source: InputSource::Key(VirtualKeyCode::S),
+                modifiers: ctrl_shift(),
+            })
+        );
+        assert_eq!(
+            Binding::parse("control + alt + super + mouseleft"),
+            Some(Binding {
+                source: InputSource::Mouse(MouseButton::Left),
+                modifiers: ModifiersState::CTRL | ModifiersState::ALT | ModifiersState::LOGO,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(Binding::parse("Hyper+S"), None);
+        assert_eq!(Binding::parse("NotAKey"), None);
+        assert_eq!(Binding::parse("Ctrl+"), None);
+        assert_eq!(Binding::parse(""), None);
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for text in &[
+            "Ctrl+Shift+S",
+            "Alt+Return",
+            "MouseMiddle",
+            "Key7",
+            "Logo+F1",
+        ] {
+            let binding = Binding::parse(text).unwrap();
+            assert_eq!(binding.to_string(), *text);
+            assert_eq!(Binding::parse(&binding.to_string()), Some(binding));
+        }
+    }
+
+    #[test]
+    fn loads_actions_and_axes() {
+        let map = map("[actions]\nsave = Ctrl+S, F5\nsave = F5\n\n[axes]\nzoom = Equals, Minus\n")
+            .unwrap();
+
+        assert_eq!(
+            map.action_bindings("save"),
+            &[
+                Binding::parse("Ctrl+S").unwrap(),
+                Binding::key(VirtualKeyCode::F5)
+            ]
+        );
+        assert_eq!(
+            map.axis_bindings("zoom"),
+            &[AxisBinding {
+                positive: Binding::key(VirtualKeyCode::Equals),
+                negative: Binding::key(VirtualKeyCode::Minus),
+            }]
+        );
+        assert!(map.action_bindings("missing").is_empty());
+        assert_eq!(map.action_names(), vec!["save"]);
+    }
+
+    #[test]
+    fn load_errors_point_at_the_line() {
+        assert_eq!(
+            load_error("[actions]\nsave = F5\nquit = Ctrl+Nope\n"),
+            "input.cfg:3: unknown binding 'Ctrl+Nope'"
+        );
+        assert_eq!(
+            load_error("# axes\n[axes]\nzoom = Equals\n"),
+            "input.cfg:3: axes need a positive and a negative binding"
+        );
+    }
+
+    #[test]
+    fn actions_need_exactly_their_modifiers() {
+        let mut input =
+            Input::new(map("[actions]\nsave = Ctrl+S\nsave_as = Ctrl+Shift+S\n").unwrap());
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::S),
+            ctrl_shift(),
+        );
+
+        assert!(input.action("save_as"));
+        assert!(!input.action("save"));
+        assert!(input.action_held("save_as"));
+        assert!(!input.action_held("save"));
+
+        input.end_frame();
+        assert!(!input.action("save_as"));
+        assert!(input.action_held("save_as"));
+    }
+
+    #[test]
+    fn modifier_keys_do_not_count_as_their_own_modifier() {
+        let mut input = Input::new(InputMap::defaults());
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::LShift),
+            ModifiersState::SHIFT,
+        );
+
+        assert_eq!(
+            input.last_pressed(),
+            Some(Binding::key(VirtualKeyCode::LShift))
+        );
+        assert_eq!(input.axis("move_up"), -1.0);
+    }
+
+    #[test]
+    fn axes_keep_working_with_modifiers_held() {
+        let mut input = Input::new(InputMap::defaults());
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::W),
+            ModifiersState::CTRL,
+        );
+        assert_eq!(input.axis("move_forward"), 1.0);
+
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::S),
+            ModifiersState::CTRL,
+        );
+        assert_eq!(input.axis("move_forward"), 0.0);
+
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert_eq!(input.axis("move_forward"), 0.0);
+        assert!(!input.action_held("orbit_rotate"));
+    }
+
+    #[test]
+    fn key_repeats_are_not_new_presses() {
+        let mut input = Input::new(InputMap::defaults());
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::F12),
+            ModifiersState::empty(),
+        );
+        input.end_frame();
+        press(
+            &mut input,
+            InputSource::Key(VirtualKeyCode::F12),
+            ModifiersState::empty(),
+        );
+
+        assert!(!input.action("capture_frame"));
+        assert_eq!(input.last_pressed(), None);
+    }
+
+    #[test]
+    fn rebinding_replaces_and_unbinding_clears() {
+        let mut map = InputMap::defaults();
+        map.rebind_action("capture_frame", Binding::parse("Ctrl+P").unwrap());
+        assert_eq!(
+            map.action_bindings("capture_frame"),
+            &[Binding::parse("Ctrl+P").unwrap()]
+        );
+
+        map.unbind_action("capture_frame");
+        assert!(map.action_bindings("capture_frame").is_empty());
+        assert!(map.action_names().contains(&"capture_frame"));
+
+        let arrows = AxisBinding {
+            positive: Binding::key(VirtualKeyCode::Up),
+            negative: Binding::key(VirtualKeyCode::Down),
+        };
+        map.rebind_axis("move_forward", arrows);
+        assert_eq!(map.axis_bindings("move_forward"), &[arrows]);
+    }
+}
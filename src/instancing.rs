@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferAccess, CpuBufferPool},
+    device::Device,
+};
+
+use crate::{
+    gpu_memory::{self, MemoryCategory, MemoryUsage},
+    material::{BlendMode, Material},
+    math::Matrix4,
+    resources::MeshHandle,
+};
+
+/// One copy of a mesh drawn by `draw_instanced`.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    pub transform: Matrix4,
+    // multiplies the vertex colours and the material colour, alpha is ignored
+    pub tint: [f32; 4],
+}
+
+impl Default for MeshInstance {
+    fn default() -> Self {
+        MeshInstance {
+            transform: Matrix4::identity(),
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+// Per instance vertex input, matches the model and tint inputs of the vertex shaders
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct InstanceData {
+    model: [[f32; 4]; 4],
+    tint: [f32; 4],
+}
+
+vulkano::impl_vertex!(InstanceData, model, tint);
+
+impl From<&MeshInstance> for InstanceData {
+    fn from(instance: &MeshInstance) -> Self {
+        InstanceData {
+            model: instance.transform.cols,
+            tint: instance.tint,
+        }
+    }
+}
+
+// Mesh vertices and instance data, in the order OneVertexOneInstanceDefinition expects them
+pub(crate) type VertexBuffers = Vec<Arc<dyn BufferAccess + Send + Sync>>;
+
+/// A batch ready to be recorded, with its instance data uploaded.
+pub(crate) struct PreparedDraw {
+    pub vertex_buffers: VertexBuffers,
+    pub material: Arc<Material>,
+}
+
+impl PreparedDraw {
+    // blended materials see through each other, so they are left out of the shadow map
+    pub fn casts_shadow(&self) -> bool {
+        self.material.state.blend == BlendMode::Opaque
+    }
+}
+
+struct DrawBatch {
+    mesh: MeshHandle,
+    material: Arc<Material>,
+    instances: Vec<InstanceData>,
+}
+
+/// Draws queued for the next frame.
+///
+/// A frame draws what was queued since the previous frame and empties the queue, so a frame with
+/// nothing queued draws nothing.
+pub(crate) struct DrawQueue {
+    batches: Vec<DrawBatch>,
+    // a new chunk every frame, so frames in flight keep their own instance data
+    instance_pool: CpuBufferPool<InstanceData>,
+}
+
+impl DrawQueue {
+    pub fn new(device: Arc<Device>) -> Self {
+        DrawQueue {
+            batches: Vec::new(),
+            instance_pool: CpuBufferPool::vertex_buffer(device),
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        mesh: &MeshHandle,
+        material: &Arc<Material>,
+        instances: &[MeshInstance],
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+
+        self.batches.push(DrawBatch {
+            mesh: mesh.clone(),
+            material: material.clone(),
+            instances: instances.iter().map(InstanceData::from).collect(),
+        });
+    }
+
+    /// Takes the queued batches and uploads their instance data, shared by all the passes of
+    /// the frame.
+    ///
+    /// Opaque batches come first so blended ones are drawn over them, each group in the order
+    /// it was queued. Blended batches are not sorted by distance.
+    pub fn prepare(&mut self) -> Vec<PreparedDraw> {
+        let mut draws: Vec<PreparedDraw> = std::mem::take(&mut self.batches)
+            .into_iter()
+            .map(|batch| {
+                let instances = self.instance_pool.chunk(batch.instances).unwrap();
+
+                PreparedDraw {
+                    vertex_buffers: vec![
+                        batch.mesh.vertex_buffer() as Arc<dyn BufferAccess + Send + Sync>,
+                        Arc::new(instances),
+                    ],
+                    material: batch.material,
+                }
+            })
+            .collect();
+        // stable, the queue order is kept within each group
+        draws.sort_by_key(|draw| draw.material.state.blend != BlendMode::Opaque);
+
+        draws
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.add(
+            MemoryCategory::VertexBuffers,
+            gpu_memory::pool_bytes(&self.instance_pool),
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use std::fmt::Write;
+
+// Minimal JSON reader and writer for our data files. Objects keep their keys in file order so a
+// file saved back looks like the one that was loaded.
+
+// arrays and objects nested deeper than this are rejected rather than overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn parse(source: &str) -> Result<JsonValue, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+
+        parser.skip_whitespace();
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unexpected text after the end of the document"));
+        }
+
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "a boolean",
+            JsonValue::Number(_) => "a number",
+            JsonValue::String(_) => "a string",
+            JsonValue::Array(_) => "an array",
+            JsonValue::Object(_) => "an object",
+        }
+    }
+
+    /// Two space indented text, with short arrays of numbers kept on one line.
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0);
+        output.push('\n');
+
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, indent: usize) {
+        match self {
+            JsonValue::Null => output.push_str("null"),
+            JsonValue::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+            JsonValue::Number(value) => write_number(output, *value),
+            JsonValue::String(value) => write_string(output, value),
+            JsonValue::Array(values) if values.is_empty() => output.push_str("[]"),
+            JsonValue::Array(values)
+                if values.len() <= 4
+                    && values.iter().all(|v| matches!(v, JsonValue::Number(_))) =>
+            {
+                output.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        output.push_str(", ");
+                    }
+                    value.write_pretty(output, indent);
+                }
+                output.push(']');
+            }
+            JsonValue::Array(values) => {
+                output.push_str("[\n");
+                for (index, value) in values.iter().enumerate() {
+                    push_indent(output, indent + 1);
+                    value.write_pretty(output, indent + 1);
+                    if index + 1 < values.len() {
+                        output.push(',');
+                    }
+                    output.push('\n');
+                }
+                push_indent(output, indent);
+                output.push(']');
+            }
+            JsonValue::Object(entries) if entries.is_empty() => output.push_str("{}"),
+            JsonValue::Object(entries) => {
+                output.push_str("{\n");
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    push_indent(output, indent + 1);
+                    write_string(output, key);
+                    output.push_str(": ");
+                    value.write_pretty(output, indent + 1);
+                    if index + 1 < entries.len() {
+                        output.push(',');
+                    }
+                    output.push('\n');
+                }
+                push_indent(output, indent);
+                output.push('}');
+            }
+        }
+    }
+}
+
+fn push_indent(output: &mut String, indent: usize) {
+    for _ in 0..indent {
+        output.push_str("  ");
+    }
+}
+
+fn write_number(output: &mut String, value: f64) {
+    if value.is_finite() {
+        write!(output, "{}", value).unwrap();
+    } else {
+        // JSON has no infinity or NaN
+        output.push_str("null");
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    // arrays and objects around the current position
+    depth: usize,
+}
+
+impl Parser {
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        match self.peek() {
+            Some(c @ '{') | Some(c @ '[') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(self.error(&format!(
+                        "arrays and objects nested more than {} deep",
+                        MAX_DEPTH
+                    )));
+                }
+
+                self.depth += 1;
+                let value = if c == '{' {
+                    self.parse_object()
+                } else {
+                    self.parse_array()
+                };
+                self.depth -= 1;
+
+                value
+            }
+            Some('"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some('-') | Some('0'..='9') => self.parse_number(),
+            Some(c) if c.is_alphabetic() => self.parse_literal(),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        self.expect('{')?;
+        let mut entries: Vec<(String, JsonValue)> = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a key in double quotes"));
+            }
+            let key_position = self.position;
+            let key = self.parse_string()?;
+            if entries.iter().any(|(name, _)| *name == key) {
+                self.position = key_position;
+                return Err(self.error(&format!("duplicate key \"{}\"", key)));
+            }
+
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            entries.push((key, value));
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(entries)),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected ',' or '}' after the value"));
+                }
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+
+        loop {
+            self.skip_whitespace();
+            values.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(values)),
+                _ => {
+                    self.position -= 1;
+                    return Err(self.error("expected ',' or ']' after the value"));
+                }
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("unknown escape sequence")),
+                    };
+                    value.push(escaped);
+                }
+                Some('\n') | None => {
+                    // at the end of the line rather than the start of the next one
+                    self.position -= 1;
+                    return Err(self.error("unterminated string"));
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    // surrogate pairs are not needed by our files and come out as the replacement character
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let digits = self.chars.get(self.position..self.position + 4);
+        // from_str_radix alone would take a sign
+        let digits: String = match digits {
+            Some(digits) if digits.iter().all(char::is_ascii_hexdigit) => digits.iter().collect(),
+            _ => return Err(self.error("expected 4 hex digits after \\u")),
+        };
+        let code = u32::from_str_radix(&digits, 16).unwrap();
+        self.position += 4;
+
+        Ok(std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER))
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        let number = if is_json_number(&text) {
+            text.parse::<f64>().ok()
+        } else {
+            None
+        };
+
+        number.map(JsonValue::Number).ok_or_else(|| {
+            self.position = start;
+            self.error(&format!("invalid number '{}'", text))
+        })
+    }
+
+    fn parse_literal(&mut self) -> Result<JsonValue, String> {
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_alphanumeric()) {
+            self.position += 1;
+        }
+
+        let word: String = self.chars[start..self.position].iter().collect();
+        match word.as_str() {
+            "true" => Ok(JsonValue::Bool(true)),
+            "false" => Ok(JsonValue::Bool(false)),
+            "null" => Ok(JsonValue::Null),
+            _ => {
+                self.position = start;
+                Err(self.error(&format!(
+                    "unexpected '{}', strings must be in double quotes",
+                    word
+                )))
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    // "line:column: message" for the current position, both starting at 1
+    fn error(&self, message: &str) -> String {
+        let consumed = &self.chars[..self.position.min(self.chars.len())];
+        let line = consumed.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+
+        format!("{}:{}: {}", line, column, message)
+    }
+}
+
+// the JSON grammar, stricter than f64::from_str: no leading zeros, no '+' and digits on both
+// sides of the '.'
+fn is_json_number(text: &str) -> bool {
+    fn digits(bytes: &[u8]) -> usize {
+        bytes.iter().take_while(|b| b.is_ascii_digit()).count()
+    }
+
+    let mut bytes = text.as_bytes();
+    if bytes.first() == Some(&b'-') {
+        bytes = &bytes[1..];
+    }
+
+    let integer = digits(bytes);
+    if integer == 0 || (integer > 1 && bytes[0] == b'0') {
+        return false;
+    }
+    bytes = &bytes[integer..];
+
+    if bytes.first() == Some(&b'.') {
+        let fraction = digits(&bytes[1..]);
+        if fraction == 0 {
+            return false;
+        }
+        bytes = &bytes[1 + fraction..];
+    }
+
+    if let Some(b'e') | Some(b'E') = bytes.first() {
+        bytes = &bytes[1..];
+        if let Some(b'+') | Some(b'-') = bytes.first() {
+            bytes = &bytes[1..];
+        }
+        let exponent = digits(bytes);
+        if exponent == 0 {
+            return false;
+        }
+        bytes = &bytes[exponent..];
+    }
+
+    bytes.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(entries: &[(&str, JsonValue)]) -> JsonValue {
+        JsonValue::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn round_trip_keeps_the_key_order() {
+        let value = object(&[
+            ("zeta", JsonValue::Null),
+            ("alpha", JsonValue::Bool(true)),
+            (
+                "numbers",
+                JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Number(-2.5)]),
+            ),
+            (
+                "nested",
+                JsonValue::Array(vec![
+                    object(&[("name", JsonValue::String("a \"b\"\n".to_string()))]),
+                    JsonValue::Array(Vec::new()),
+                    object(&[]),
+                ]),
+            ),
+        ]);
+
+        let text = value.to_pretty_string();
+        assert_eq!(JsonValue::parse(&text), Ok(value));
+        assert_eq!(
+            text,
+            "{\n  \"zeta\": null,\n  \"alpha\": true,\n  \"numbers\": [1, -2.5],\n  \"nested\": [\n    {\n      \"name\": \"a \\\"b\\\"\\n\"\n    },\n    [],\n    {}\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            JsonValue::parse(r#""q\" b\\ s\/ \b\f\n\r\t \u0041\u00e9""#),
+            Ok(JsonValue::String(
+                "q\" b\\ s/ \u{8}\u{c}\n\r\t A\u{e9}".to_string()
+            ))
+        );
+
+        let mut written = String::new();
+        write_string(&mut written, "tab\t quote\" \u{1} é");
+        assert_eq!(written, r#""tab\t quote\" \u0001 é""#);
+        assert_eq!(
+            JsonValue::parse(&written),
+            Ok(JsonValue::String("tab\t quote\" \u{1} é".to_string()))
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        for (text, expected) in &[
+            ("0", 0.0),
+            ("-12", -12.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("-2.5E-2", -0.025),
+        ] {
+            assert_eq!(JsonValue::parse(text), Ok(JsonValue::Number(*expected)));
+        }
+
+        // JSON has no NaN or infinity
+        assert_eq!(JsonValue::Number(f64::NAN).to_pretty_string(), "null\n");
+        assert_eq!(JsonValue::Number(0.1).to_pretty_string(), "0.1\n");
+    }
+
+    #[test]
+    fn malformed_input_names_the_position() {
+        let error = |text: &str| JsonValue::parse(text).unwrap_err();
+
+        assert_eq!(error(""), "1:1: unexpected end of file");
+        assert_eq!(error("[1, 2,]"), "1:7: unexpected ']'");
+        assert_eq!(error("[1 2]"), "1:4: expected ',' or ']' after the value");
+        assert_eq!(
+            error("{\"a\": 1 \"b\": 2}"),
+            "1:9: expected ',' or '}' after the value"
+        );
+        assert_eq!(
+            error("{\n  a: 1\n}"),
+            "2:3: expected a key in double quotes"
+        );
+        assert_eq!(error("{\"a\": 1,\n \"a\": 2}"), "2:2: duplicate key \"a\"");
+        assert_eq!(error("\"open\nstring\""), "1:6: unterminated string");
+        assert_eq!(error("[\"open"), "1:7: unterminated string");
+        assert_eq!(error(r#""\x""#), "1:4: unknown escape sequence");
+        assert_eq!(error(r#""\u12""#), "1:4: expected 4 hex digits after \\u");
+        assert_eq!(error("1.2.3"), "1:1: invalid number '1.2.3'");
+        assert_eq!(error(r#""\u+041""#), "1:4: expected 4 hex digits after \\u");
+        assert_eq!(
+            error("[yes]"),
+            "1:2: unexpected 'yes', strings must be in double quotes"
+        );
+        assert_eq!(
+            error("{} {}"),
+            "1:4: unexpected text after the end of the document"
+        );
+    }
+
+    #[test]
+    fn numbers_follow_the_json_grammar() {
+        for text in &[
+            "01", "-01", "1.", "-.5", "-", "1e", "1e+", "1.e3", "--1", "1E5e",
+        ] {
+            assert_eq!(
+                JsonValue::parse(text),
+                Err(format!("1:1: invalid number '{}'", text)),
+                "{} must be rejected",
+                text
+            );
+        }
+        for (text, expected) in &[("-0", 0.0), ("10", 10.0), ("0.5e+1", 5.0), ("2E-0", 2.0)] {
+            assert_eq!(JsonValue::parse(text), Ok(JsonValue::Number(*expected)));
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(JsonValue::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            JsonValue::parse(&nested(100_000)),
+            Err(format!(
+                "1:{}: arrays and objects nested more than {} deep",
+                MAX_DEPTH + 1,
+                MAX_DEPTH
+            ))
+        );
+        assert!(JsonValue::parse(&format!("{}1", "{\"a\":".repeat(MAX_DEPTH + 1))).is_err());
+    }
+
+    #[test]
+    fn get_only_reads_objects() {
+        let value = object(&[("key", JsonValue::Number(1.0))]);
+
+        assert_eq!(value.get("key"), Some(&JsonValue::Number(1.0)));
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(JsonValue::Array(Vec::new()).get("key"), None);
+        assert_eq!(value.type_name(), "an object");
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{error_utils::EngineError, math::Vector3};
+
+// Must match MAX_LIGHTS in the fragment shader
+pub const MAX_LIGHTS: usize = 16;
+
+/// Point light falloff: 1 / (constant + linear * d + quadratic * d²)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Default for Attenuation {
+    // fades out at about 50 units
+    fn default() -> Self {
+        Attenuation {
+            constant: 1.0,
+            linear: 0.09,
+            quadratic: 0.032,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    // lights every surface from the same direction, like the sun
+    Directional {
+        direction: Vector3,
+    },
+    Point {
+        position: Vector3,
+        attenuation: Attenuation,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub colour: Vector3,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vector3, colour: Vector3, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional {
+                direction: direction.normalize(),
+            },
+            colour,
+            intensity,
+        }
+    }
+
+    pub fn point(position: Vector3, colour: Vector3, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Point {
+                position,
+                attenuation: Attenuation::default(),
+            },
+            colour,
+            intensity,
+        }
+    }
+}
+
+// Matches the Light struct of the fragment shader, std140 layout
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub(crate) struct GpuLight {
+    // w is 0 for directional lights (xyz is the direction) and 1 for point lights
+    position: [f32; 4],
+    // rgb colour, intensity in a
+    colour: [f32; 4],
+    // constant, linear, quadratic, unused
+    attenuation: [f32; 4],
+}
+
+// Matches the layout of UboLights in the fragment shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct UboLights {
+    camera_position: [f32; 4],
+    ambient: [f32; 4],
+    light_count: u32,
+    // std140 aligns the array of structs on 16 bytes
+    _padding: [u32; 3],
+    lights: [GpuLight; MAX_LIGHTS],
+}
+
+/// The lights of the frame, refilled from the scene before each frame.
+#[derive(Debug, Clone)]
+pub struct Lights {
+    pub ambient: Vector3,
+    lights: Vec<Light>,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Lights {
+            ambient: Vector3::new(0.1, 0.1, 0.1),
+            lights: Vec::new(),
+        }
+    }
+}
+
+impl Lights {
+    pub fn add(&mut self, light: Light) -> Result<(), EngineError> {
+        if self.lights.len() >= MAX_LIGHTS {
+            return Err(EngineError::LightingError(format!(
+                "Can not add more than {} lights",
+                MAX_LIGHTS
+            )));
+        }
+
+        self.lights.push(light);
+
+        Ok(())
+    }
+
+    /// Removes every light, keeping the ambient colour.
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    /// The first directional light casts the shadows, returns its index in the uniform buffer
+    /// and its direction.
+    pub fn shadow_caster(&self) -> Option<(usize, Vector3)> {
+        self.lights
+            .iter()
+            .enumerate()
+            .find_map(|(index, light)| match light.kind {
+                LightKind::Directional { direction } => Some((index, direction)),
+                LightKind::Point { .. } => None,
+            })
+    }
+
+    pub(crate) fn uniform(&self, camera_position: Vector3) -> UboLights {
+        let mut lights = [GpuLight::default(); MAX_LIGHTS];
+
+        for (gpu_light, light) in lights.iter_mut().zip(&self.lights) {
+            let (position, attenuation) = match light.kind {
+                LightKind::Directional { direction } => {
+                    (vec4(direction, 0.0), [1.0, 0.0, 0.0, 0.0])
+                }
+                LightKind::Point {
+                    position,
+                    attenuation,
+                } => (
+                    vec4(position, 1.0),
+                    [
+                        attenuation.constant,
+                        attenuation.linear,
+                        attenuation.quadratic,
+                        0.0,
+                    ],
+                ),
+            };
+
+            *gpu_light = GpuLight {
+                position,
+                colour: vec4(light.colour, light.intensity),
+                attenuation,
+            };
+        }
+
+        UboLights {
+            camera_position: vec4(camera_position, 1.0),
+            ambient: vec4(self.ambient, 1.0),
+            light_count: self.lights.len() as u32,
+            _padding: [0; 3],
+            lights,
+        }
+    }
+}
+
+fn vec4(v: Vector3, w: f32) -> [f32; 4] {
+    [v.x, v.y, v.z, w]
+}
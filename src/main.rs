@@ -1,42 +1,253 @@
+use std::env;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::Arc;
-use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer},
-    command_buffer::{AutoCommandBufferBuilder, DynamicState},
-    device::Device,
-    framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass},
-    image::{ImageUsage, SwapchainImage},
-    instance::{ApplicationInfo, Instance, PhysicalDevice, Version},
-    pipeline::viewport::Viewport,
-    pipeline::GraphicsPipeline,
-    swapchain::{
-        acquire_next_image, AcquireError, ColorSpace, FullscreenExclusive, PresentMode, Surface,
-        SurfaceTransform, Swapchain, SwapchainCreationError,
-    },
-    sync::{now, FlushError, GpuFuture},
-};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
 };
 
+mod camera;
+mod colour_cycle;
+mod compute;
+mod config;
+mod debug_draw;
+mod debug_names;
+mod device_features;
+mod display_settings;
 mod error_utils;
+mod frame_limiter;
+mod frame_stats;
+#[cfg(test)]
+mod golden;
+mod gpu_memory;
+mod gpu_profiler;
+#[cfg(test)]
+mod headless_renderer;
+#[cfg(test)]
+mod image_tests;
+mod input;
+mod instancing;
+mod json;
+mod lighting;
+mod material;
+mod math;
+mod mesh;
+mod overlay;
+mod overlay_panels;
+mod pipeline_cache;
+mod raw_commands;
+mod render_window;
+mod resources;
+mod scene;
+mod scene_file;
+mod settings;
+mod shadows;
+mod texture;
 mod utilities;
 mod vulkan_renderer;
 
+use camera::{Camera, CameraController, CameraMode};
+use colour_cycle::ColourCycle;
+use debug_draw::DebugDraw;
+use display_settings::{DisplaySettings, WindowMode};
+use error_utils::EngineError;
+use input::{Input, InputMap};
+use instancing::MeshInstance;
+use lighting::{Light, LightKind};
+use math::{Matrix4, Quaternion, Vector3};
+use mesh::MeshSource;
+use overlay::Overlay;
+use overlay_panels::Panels;
+use scene::{CameraComponent, MeshComponent, Scene, Transform};
+use settings::{AppSettings, WindowSettings};
 use vulkan_renderer::VulkanRenderer;
 
+const INPUT_CONFIG_PATH: &str = "input.cfg";
+// when set, the frame timings of the last frames are written there as CSV on exit
+const FRAME_STATS_CSV_ENV_VAR: &str = "FRAME_STATS_CSV";
+const FLOOR_HEIGHT: f32 = -0.5;
+const FLOOR_COLOUR: [f32; 3] = [0.8, 0.8, 0.8];
+// the demo lamp circles in front of the triangle
+const LAMP_ORBIT_CENTER: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+const LAMP_ORBIT_RADIUS: f32 = 0.75;
+// any scene with a node of that name gets it spinning around Z
+const LAMP_PIVOT_NODE: &str = "lamp pivot";
+
+// small triangles turning around the demo scene, drawn with a single instanced draw
+const MARKER_COUNT: usize = 8;
+const MARKER_RING_RADIUS: f32 = 1.5;
+const MARKER_SCALE: f32 = 0.2;
+const BOUNDS_COLOUR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+// where save_scene writes when the scene was not opened from a file
+const DEFAULT_SCENE_PATH: &str = "scene.json";
+// a second window showing the scene from the scene camera
+const PREVIEW_WINDOW_TITLE: &str = "Scene camera";
+
 fn init_window() -> EventLoop<()> {
-    let events_loop = EventLoop::new();
-    events_loop
+    EventLoop::new()
+}
+
+fn screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    PathBuf::from(format!("screenshot_{}.png", timestamp))
+}
+
+// The triangle over a floor, lit by the sun and a lamp, shown when no scene file is given
+fn create_demo_scene(render: &mut VulkanRenderer) -> Result<Scene, EngineError> {
+    let mut scene = Scene::new();
+
+    let triangle = scene.add_node("triangle", None, Transform::default())?;
+    scene.node_mut(triangle)?.mesh = Some(MeshComponent::new(
+        render.load_mesh("triangle", MeshSource::Triangle)?,
+    ));
+
+    let floor_transform = Transform::from_translation(Vector3::new(0.0, FLOOR_HEIGHT, 0.0));
+    let floor = scene.add_node("floor", None, floor_transform)?;
+    let floor_mesh = MeshSource::Floor {
+        half_size: 2.0,
+        colour: FLOOR_COLOUR,
+    };
+    scene.node_mut(floor)?.mesh = Some(MeshComponent::new(render.load_mesh("floor", floor_mesh)?));
+
+    let sun = scene.add_node("sun", None, Transform::default())?;
+    scene.node_mut(sun)?.light = Some(Light::directional(
+        Vector3::new(-0.3, -0.5, -1.0),
+        Vector3::new(1.0, 0.95, 0.9),
+        0.8,
+    ));
+
+    // rotating the pivot moves the lamp along a circle
+    let lamp_pivot = scene.add_node(
+        LAMP_PIVOT_NODE,
+        None,
+        Transform::from_translation(LAMP_ORBIT_CENTER),
+    )?;
+    let lamp_transform = Transform::from_translation(Vector3::new(LAMP_ORBIT_RADIUS, 0.0, 0.0));
+    let lamp = scene.add_node("lamp", Some(lamp_pivot), lamp_transform)?;
+    scene.node_mut(lamp)?.light = Some(Light::point(
+        Vector3::zero(),
+        Vector3::new(0.4, 0.6, 1.0),
+        1.0,
+    ));
+
+    let camera_transform = Transform::from_translation(Camera::default().position);
+    let camera = scene.add_node("camera", None, camera_transform)?;
+    scene.node_mut(camera)?.camera = Some(CameraComponent::default());
+    scene.set_active_camera(Some(camera))?;
+
+    Ok(scene)
+}
+
+// The markers `angle` radians along their ring, standing on the floor and facing its centre
+fn demo_markers(angle: f32) -> Vec<MeshInstance> {
+    (0..MARKER_COUNT)
+        .map(|index| {
+            let phase = index as f32 / MARKER_COUNT as f32 * std::f32::consts::TAU;
+            let along = angle + phase;
+            // the lowest vertex of the triangle is a quarter below its origin
+            let position = Vector3::new(
+                MARKER_RING_RADIUS * along.cos(),
+                FLOOR_HEIGHT + 0.25 * MARKER_SCALE,
+                MARKER_RING_RADIUS * along.sin(),
+            );
+            let rotation = Quaternion::from_axis_angle(Vector3::up(), -along);
+
+            MeshInstance {
+                transform: Matrix4::from_translation_rotation_scale(
+                    position,
+                    rotation,
+                    Vector3::new(MARKER_SCALE, MARKER_SCALE, MARKER_SCALE),
+                ),
+                tint: [
+                    0.5 + 0.5 * phase.cos(),
+                    0.5 + 0.5 * (phase - 2.0).cos(),
+                    0.5 + 0.5 * (phase + 2.0).cos(),
+                    1.0,
+                ],
+            }
+        })
+        .collect()
+}
+
+// Floor grid, world axes, the lights and cameras of the scene and the boxes of its meshes
+fn draw_debug_overlay(debug: &mut DebugDraw, scene: &Scene) {
+    // just above the floor, so it does not fight with it for the depth
+    let grid_centre = Vector3::new(0.0, FLOOR_HEIGHT + 0.001, 0.0);
+    debug.grid(grid_centre, 8, 0.5, [0.2, 0.2, 0.2, 1.0]);
+
+    debug.depth_test = false;
+    debug.axes(&Matrix4::identity(), 0.5);
+
+    for (id, node) in scene.iter() {
+        let world = match scene.world_matrix(id) {
+            Ok(world) => world,
+            Err(_) => continue,
+        };
+
+        if let Some(light) = &node.light {
+            let colour = [light.colour.x, light.colour.y, light.colour.z, 1.0];
+            match light.kind {
+                LightKind::Point { position, .. } => {
+                    debug.sphere(world.transform_point(position), 0.1, colour)
+                }
+                LightKind::Directional { direction } => {
+                    let direction = world.transform_vector(direction).normalize();
+                    debug.line(Vector3::zero(), direction, colour);
+                }
+            }
+        }
+
+        if node.camera.is_some() {
+            debug.axes(&world, 0.25);
+        }
+
+        // the box of the mesh in world space, around its transformed corners
+        if let Some(component) = &node.mesh {
+            let (min, max) = component.mesh.bounds();
+            let corners = (0..8).map(|corner| {
+                let pick = |bit: usize, min: f32, max: f32| {
+                    if corner & bit == 0 {
+                        min
+                    } else {
+                        max
+                    }
+                };
+                world.transform_point(Vector3::new(
+                    pick(1, min.x, max.x),
+                    pick(2, min.y, max.y),
+                    pick(4, min.z, max.z),
+                ))
+            });
+            let (min, max) = mesh::point_bounds(corners);
+            debug.aabb(min, max, BOUNDS_COLOUR);
+        }
+    }
+    debug.depth_test = true;
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let settings = match AppSettings::load(&args) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Invalid settings: {}", err);
+            process::exit(2);
+        }
+    };
+    if settings.show_help {
+        println!("{}", settings::USAGE);
+        return;
+    }
+
     let events_loop = init_window();
 
-    let render = match VulkanRenderer::init(&events_loop) {
+    let mut render = match VulkanRenderer::init(&events_loop, settings.renderer) {
         Ok(value) => value,
         Err(err) => {
             eprintln!("Failed to create vulkano renderer: {}", err);
@@ -44,16 +255,286 @@ fn main() {
         }
     };
 
-    events_loop.run(move |event, _, control_flow| {
+    let scene_path = settings.scene_path;
+    let scene = match &scene_path {
+        Some(path) => render.load_scene(path),
+        None => create_demo_scene(&mut render),
+    };
+    let mut scene = match scene {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("Failed to create the scene: {}", err);
+            process::exit(1);
+        }
+    };
+    // scene files bring their own content, the markers belong to the demo
+    let markers = match &scene_path {
+        Some(_) => None,
+        None => match render.create_mesh(&mesh::triangle_vertices()) {
+            Ok(mesh) => Some(mesh),
+            Err(err) => {
+                eprintln!("Failed to create the markers: {}", err);
+                None
+            }
+        },
+    };
+    // the first step runs on its own, so a device that can not run the shader stops the cycle
+    // here instead of failing every frame
+    let colour_cycle = markers.as_ref().and_then(|markers| {
+        let colour_cycle = ColourCycle::new(render.device.clone(), markers).and_then(|cycle| {
+            render.run_compute(&[cycle.dispatch(0.0)?])?;
+            Ok(cycle)
+        });
+        match colour_cycle {
+            Ok(colour_cycle) => Some(colour_cycle),
+            Err(err) => {
+                eprintln!("Failed to cycle the marker colours: {}", err);
+                None
+            }
+        }
+    });
+    let scene_path = scene_path.unwrap_or_else(|| PathBuf::from(DEFAULT_SCENE_PATH));
+    let lamp_pivot = scene.find(LAMP_PIVOT_NODE);
+    scene.update_world_transforms();
+    let start = Instant::now();
+
+    let mut input = Input::new(InputMap::load_or_default(Path::new(INPUT_CONFIG_PATH)));
+    // the scene camera is where the view starts, the controller moves it from there
+    let mut camera = scene.camera().unwrap_or_default();
+    let mut camera_controller = CameraController::new(CameraMode::Orbit, &camera);
+    let mut last_frame = Instant::now();
+    let mut debug_overlay = false;
+    let mut overlay = Overlay::new();
+    let mut panels = Panels::default();
+    // where toggling fullscreen goes back to
+    let mut fullscreen_mode = WindowMode::Borderless;
+    let mut preview_window = None;
+
+    events_loop.run(move |event, event_loop, control_flow| {
         // *control_flow = ControlFlow::Poll;
 
         match event {
+            // the other windows only show the scene, the input goes to the primary one
+            Event::WindowEvent { window_id, event } if window_id != render.primary_window() => {
+                match event {
+                    WindowEvent::CloseRequested => {
+                        if let Err(err) = render.close_window(window_id) {
+                            eprintln!("Failed to close the window: {}", err);
+                        }
+                        if preview_window == Some(window_id) {
+                            preview_window = None;
+                        }
+                    }
+                    WindowEvent::Resized(_) => {
+                        if let Some(window) = render.render_window_mut(window_id) {
+                            window.resized();
+                        }
+                    }
+                    _ => (),
+                }
+            }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
                 *control_flow = ControlFlow::Exit;
                 println!("The close button was pressed; stopping");
+
+                println!("Frame stats:\n{}", render.primary().frame_stats().summary());
+                if !render.gpu_timings().is_empty() {
+                    println!("GPU timings:");
+                    for timing in render.gpu_timings() {
+                        println!("  {}", timing);
+                    }
+                }
+                if let Ok(path) = env::var(FRAME_STATS_CSV_ENV_VAR) {
+                    if let Err(err) = render.primary().frame_stats().write_csv(Path::new(&path)) {
+                        eprintln!("Failed to write frame stats to {}: {}", path, err);
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => {
+                render.primary_mut().resized();
+            }
+            Event::WindowEvent { event, .. } => {
+                // presses the UI takes do not reach the actions or the camera
+                let taken_by_ui = overlay.handle_window_event(&event);
+                if !taken_by_ui {
+                    input.handle_window_event(&event);
+                }
+            }
+            // the camera does not follow the mouse while it is over the UI
+            Event::DeviceEvent { event, .. } if !overlay.wants_pointer() => {
+                input.handle_device_event(&event);
+            }
+            Event::RedrawEventsCleared => {
+                let now = Instant::now();
+                let delta_time = now.duration_since(last_frame).as_secs_f32();
+                last_frame = now;
+
+                if input.action("toggle_camera_mode") {
+                    camera_controller.toggle_mode(&mut camera, render.primary().window());
+                }
+
+                if input.action("toggle_stats_title") {
+                    let window = render.primary_mut();
+                    window.set_stats_in_title(!window.stats_in_title());
+                }
+
+                if input.action("cycle_polygon_mode") {
+                    render.set_polygon_mode(render.polygon_mode().next());
+                    if render.polygon_modes_emulated() {
+                        println!("Polygon mode: {:?} (emulated)", render.polygon_mode());
+                    } else {
+                        println!("Polygon mode: {:?}", render.polygon_mode());
+                    }
+                }
+
+                if input.action("cycle_vsync_mode") {
+                    let vsync = render.primary().vsync_mode().next();
+                    match render.primary_mut().set_vsync_mode(vsync) {
+                        Ok(()) => println!("Vsync: {:?}", vsync),
+                        Err(err) => eprintln!("Failed to change the vsync mode: {}", err),
+                    }
+                }
+
+                if input.action("toggle_fullscreen") {
+                    let mode = match render.primary().window_mode() {
+                        WindowMode::Windowed => fullscreen_mode,
+                        current => {
+                            fullscreen_mode = current;
+                            WindowMode::Windowed
+                        }
+                    };
+                    match render.primary_mut().set_window_mode(mode) {
+                        Ok(()) => println!("Window mode: {:?}", mode),
+                        Err(err) => eprintln!("Failed to change the window mode: {}", err),
+                    }
+                }
+
+                camera_controller.update(&mut camera, &input, delta_time);
+                render.primary_mut().set_camera(&camera);
+
+                if input.action("print_resources") {
+                    print!("Resident resources: {}", render.resources().residency());
+                }
+
+                if input.action("print_memory") {
+                    print!("GPU memory: {}", render.memory_report());
+                }
+
+                if input.action("save_scene") {
+                    match scene_file::save_scene(&scene, &scene_path) {
+                        Ok(()) => println!("Scene saved to {}", scene_path.display()),
+                        Err(err) => eprintln!("Failed to save the scene: {}", err),
+                    }
+                }
+
+                if let Some(lamp_pivot) = lamp_pivot {
+                    let angle = start.elapsed().as_secs_f32();
+                    let rotation = Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), angle);
+                    if let Err(err) = scene.set_rotation(lamp_pivot, rotation) {
+                        eprintln!("Failed to move the lamp: {}", err);
+                    }
+                }
+
+                render.draw_scene(&mut scene);
+                let marker_instances = demo_markers(start.elapsed().as_secs_f32() * 0.5);
+                if let Some(colour_cycle) = &colour_cycle {
+                    match colour_cycle.dispatch(start.elapsed().as_secs_f32()) {
+                        Ok(dispatch) => render.dispatch_compute(dispatch),
+                        Err(err) => eprintln!("Failed to cycle the marker colours: {}", err),
+                    }
+                }
+                if let Some(markers) = &markers {
+                    render.draw_instanced(markers, &marker_instances);
+                }
+
+                if input.action("toggle_overlay") {
+                    overlay.visible = !overlay.visible;
+                }
+                let overlay_frame = if overlay.visible {
+                    let window = render.primary().window();
+                    let size: [u32; 2] = window.inner_size().into();
+                    let context = overlay.begin_frame(size, window.scale_factor() as f32);
+                    let debug_was_drawn = debug_overlay;
+                    panels.show(
+                        &context,
+                        &mut render,
+                        &mut scene,
+                        &mut input,
+                        &mut debug_overlay,
+                    );
+                    if debug_overlay != debug_was_drawn {
+                        render.debug_draw().clear();
+                    }
+                    Some(overlay.end_frame())
+                } else {
+                    None
+                };
+                let primary_window = render.primary_window();
+                if let Err(err) = render.set_overlay(primary_window, overlay_frame) {
+                    eprintln!("Failed to draw the overlay: {}", err);
+                }
+
+                if input.action("toggle_debug_draw") {
+                    debug_overlay = !debug_overlay;
+                    render.debug_draw().clear();
+                }
+                if debug_overlay {
+                    draw_debug_overlay(render.debug_draw(), &scene);
+                }
+
+                // the capture is the frame of the primary window, drawn instead of it
+                if input.action("capture_frame") {
+                    let path = screenshot_path();
+                    match render.save_frame(&path) {
+                        Ok(()) => println!("Screenshot saved to {}", path.display()),
+                        Err(err) => eprintln!("Failed to save screenshot: {}", err),
+                    }
+                } else if let Err(err) = render.draw() {
+                    eprintln!("Failed to draw frame: {}", err);
+                }
+
+                if input.action("toggle_preview_window") {
+                    preview_window = match preview_window.take() {
+                        Some(id) => {
+                            if let Err(err) = render.close_window(id) {
+                                eprintln!("Failed to close the preview window: {}", err);
+                            }
+                            None
+                        }
+                        None => match render.add_window(
+                            event_loop,
+                            &WindowSettings::titled(PREVIEW_WINDOW_TITLE),
+                            DisplaySettings::default(),
+                        ) {
+                            Ok(id) => Some(id),
+                            Err(err) => {
+                                eprintln!("Failed to open the preview window: {}", err);
+                                None
+                            }
+                        },
+                    };
+                }
+                // the same scene again, from the camera it was saved with
+                if let Some(id) = preview_window {
+                    if let Some(window) = render.render_window_mut(id) {
+                        window.set_camera(&scene.camera().unwrap_or_default());
+                    }
+                    render.draw_scene(&mut scene);
+                    if let Some(markers) = &markers {
+                        render.draw_instanced(markers, &marker_instances);
+                    }
+                    if let Err(err) = render.draw_window(id) {
+                        eprintln!("Failed to draw the preview window: {}", err);
+                    }
+                }
+
+                input.end_frame();
             }
             _ => (),
         }
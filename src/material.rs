@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::CpuBufferPool,
+    descriptor::{
+        descriptor_set::{DescriptorSet, PersistentDescriptorSet},
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    pipeline::GraphicsPipelineAbstract,
+    sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode},
+};
+
+use crate::{
+    debug_names,
+    device_features::OptionalFeatures,
+    error_utils::EngineError,
+    gpu_memory::{self, MemoryCategory, MemoryUsage},
+    resources::TextureHandle,
+    texture::Texture,
+};
+
+// past this, sharper textures are hardly visible and cost more texture reads
+const MAX_ANISOTROPY: f32 = 16.0;
+
+/// The shaders a material is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderProgram {
+    // Blinn-Phong with the scene lights and the shadow map
+    Lit,
+    // colour and texture only, for markers, sky and debug geometry
+    Unlit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    // blended with the alpha of the base colour and texture, drawn after the opaque materials
+    AlphaBlend,
+    // adds to what is behind, for glows and particles
+    Additive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+}
+
+/// Fixed function state of the pipeline drawing a material. Materials with the same state and
+/// shader program share their pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    // which faces are dropped, front faces are clockwise
+    pub cull_mode: CullMode,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    // draws both faces, lighting the back faces with their normal flipped; overrides cull_mode
+    pub double_sided: bool,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState {
+            blend: BlendMode::Opaque,
+            cull_mode: CullMode::Back,
+            depth_test: true,
+            depth_write: true,
+            double_sided: false,
+        }
+    }
+}
+
+impl RenderState {
+    pub fn effective_cull_mode(&self) -> CullMode {
+        if self.double_sided {
+            CullMode::None
+        } else {
+            self.cull_mode
+        }
+    }
+}
+
+/// A shader program with its parameters and render state, shared by the meshes using it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub shader: ShaderProgram,
+
+    // multiplies the vertex colours and the texture, alpha is used by the blend modes
+    pub base_colour: [f32; 4],
+    pub base_colour_texture: Option<TextureHandle>,
+    // added after lighting
+    pub emissive: [f32; 3],
+    pub specular_strength: f32,
+    pub shininess: f32,
+
+    pub state: RenderState,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: "default".to_string(),
+            shader: ShaderProgram::Lit,
+            base_colour: [1.0, 1.0, 1.0, 1.0],
+            base_colour_texture: None,
+            emissive: [0.0, 0.0, 0.0],
+            specular_strength: 0.5,
+            shininess: 32.0,
+            state: RenderState::default(),
+        }
+    }
+}
+
+// Matches the layout of UboMaterial in the fragment shaders
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct UboMaterial {
+    base_colour: [f32; 4],
+    emissive: [f32; 4],
+    specular_strength: f32,
+    shininess: f32,
+    double_sided: u32,
+    _padding: u32,
+}
+
+impl From<&Material> for UboMaterial {
+    fn from(material: &Material) -> Self {
+        let [r, g, b] = material.emissive;
+
+        UboMaterial {
+            base_colour: material.base_colour,
+            emissive: [r, g, b, 0.0],
+            specular_strength: material.specular_strength,
+            shininess: material.shininess,
+            double_sided: material.state.double_sided as u32,
+            _padding: 0,
+        }
+    }
+}
+
+/// What the main pass needs to bind materials: set 1 holds the parameters and the base colour
+/// texture, white for materials without one.
+pub(crate) struct MaterialBindings {
+    uniform_pool: CpuBufferPool<UboMaterial>,
+    white_texture: Arc<Texture>,
+    sampler: Arc<Sampler>,
+}
+
+impl MaterialBindings {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Result<Self, EngineError> {
+        // textures seen at grazing angles, like the floor, stay sharp with anisotropy
+        let max_anisotropy = if OptionalFeatures::enabled_on(&device).sampler_anisotropy {
+            device
+                .physical_device()
+                .limits()
+                .max_sampler_anisotropy()
+                .min(MAX_ANISOTROPY)
+        } else {
+            1.0
+        };
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::Repeat,
+            0.0,
+            max_anisotropy,
+            0.0,
+            0.0,
+        )?;
+        debug_names::name_object(&*sampler, "material sampler");
+
+        Ok(MaterialBindings {
+            uniform_pool: CpuBufferPool::uniform_buffer(device),
+            white_texture: Texture::white(queue)?,
+            sampler,
+        })
+    }
+
+    pub fn descriptor_set(
+        &self,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        material: &Material,
+    ) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let uniform_buffer = self.uniform_pool.next(material.into()).unwrap();
+        let image = match &material.base_colour_texture {
+            Some(texture) => texture.image(),
+            None => self.white_texture.image(),
+        };
+        let layout = pipeline.descriptor_set_layout(1).unwrap();
+
+        Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_buffer(uniform_buffer)
+                .unwrap()
+                .add_sampled_image(image, self.sampler.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        let [width, height] = self.white_texture.dimensions();
+        usage.add(MemoryCategory::Textures, width as u64 * height as u64 * 4);
+        usage.add(
+            MemoryCategory::Staging,
+            gpu_memory::pool_bytes(&self.uniform_pool),
+        );
+    }
+}
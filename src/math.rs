@@ -0,0 +1,491 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vector3 { x, y, z }
+    }
+
+    pub const fn zero() -> Self {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+
+    pub const fn up() -> Self {
+        Vector3::new(0.0, 1.0, 0.0)
+    }
+
+    pub fn dot(self, other: Vector3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vector3) -> Vector3 {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Vector3 {
+        let length = self.length();
+        if length == 0.0 {
+            return self;
+        }
+
+        self * (1.0 / length)
+    }
+}
+
+impl From<[f32; 3]> for Vector3 {
+    fn from(v: [f32; 3]) -> Self {
+        Vector3::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Vector3> for [f32; 3] {
+    fn from(v: Vector3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vector3 {
+    fn add_assign(&mut self, other: Vector3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl SubAssign for Vector3 {
+    fn sub_assign(&mut self, other: Vector3) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<f32> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, scalar: f32) -> Vector3 {
+        Vector3::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+// Unit quaternion describing a rotation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub const fn identity() -> Self {
+        Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+
+    /// Counter clockwise rotation of `angle_radians` around `axis`, looking down the axis.
+    pub fn from_axis_angle(axis: Vector3, angle_radians: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle_radians / 2.0).sin_cos();
+
+        Quaternion {
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+            w: cos,
+        }
+    }
+
+    /// Rotates around X, then Y, then Z.
+    pub fn from_euler(x_radians: f32, y_radians: f32, z_radians: f32) -> Self {
+        let x = Quaternion::from_axis_angle(Vector3::new(1.0, 0.0, 0.0), x_radians);
+        let y = Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), y_radians);
+        let z = Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), z_radians);
+
+        z * y * x
+    }
+
+    pub fn normalize(self) -> Quaternion {
+        let length = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+        if length == 0.0 {
+            return Quaternion::identity();
+        }
+
+        Quaternion {
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+            w: self.w / length,
+        }
+    }
+
+    pub fn to_matrix(self) -> Matrix4 {
+        let Quaternion { x, y, z, w } = self.normalize();
+
+        Matrix4 {
+            cols: [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y + z * w),
+                    2.0 * (x * z - y * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * y - z * w),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z + x * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * z + y * w),
+                    2.0 * (y * z - x * w),
+                    1.0 - 2.0 * (x * x + y * y),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+// Applies `other` first, then `self`
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion {
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        }
+    }
+}
+
+// Column major, as GLSL expects it: cols[column][row]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub const fn identity() -> Self {
+        Matrix4 {
+            cols: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translation(offset: Vector3) -> Self {
+        let mut result = Matrix4::identity();
+        result.cols[3] = [offset.x, offset.y, offset.z, 1.0];
+
+        result
+    }
+
+    pub fn scale(factors: Vector3) -> Self {
+        let mut result = Matrix4::identity();
+        result.cols[0][0] = factors.x;
+        result.cols[1][1] = factors.y;
+        result.cols[2][2] = factors.z;
+
+        result
+    }
+
+    /// Scales, then rotates, then translates.
+    pub fn from_translation_rotation_scale(
+        translation: Vector3,
+        rotation: Quaternion,
+        scale: Vector3,
+    ) -> Self {
+        Matrix4::translation(translation) * rotation.to_matrix() * Matrix4::scale(scale)
+    }
+
+    pub fn transform_point(&self, point: Vector3) -> Vector3 {
+        self.transform(point, 1.0)
+    }
+
+    // ignores the translation
+    pub fn transform_vector(&self, vector: Vector3) -> Vector3 {
+        self.transform(vector, 0.0)
+    }
+
+    pub fn translation_part(&self) -> Vector3 {
+        Vector3::new(self.cols[3][0], self.cols[3][1], self.cols[3][2])
+    }
+
+    fn transform(&self, v: Vector3, w: f32) -> Vector3 {
+        let row = |r: usize| {
+            self.cols[0][r] * v.x
+                + self.cols[1][r] * v.y
+                + self.cols[2][r] * v.z
+                + self.cols[3][r] * w
+        };
+
+        Vector3::new(row(0), row(1), row(2))
+    }
+
+    /// Right handed perspective projection for Vulkan clip space: depth goes from 0 to 1 and
+    /// Y points down, so the Y axis is flipped here to keep world space Y pointing up.
+    pub fn perspective(fov_y_radians: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let focal_length = 1.0 / (fov_y_radians / 2.0).tan();
+
+        let mut result = Matrix4 {
+            cols: [[0.0; 4]; 4],
+        };
+        result.cols[0][0] = focal_length / aspect_ratio;
+        result.cols[1][1] = -focal_length;
+        result.cols[2][2] = far / (near - far);
+        result.cols[2][3] = -1.0;
+        result.cols[3][2] = (near * far) / (near - far);
+
+        result
+    }
+
+    /// Right handed orthographic projection for Vulkan clip space, flipping Y like `perspective`.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        let mut result = Matrix4::identity();
+        result.cols[0][0] = 2.0 / (right - left);
+        result.cols[1][1] = -2.0 / (top - bottom);
+        result.cols[2][2] = 1.0 / (near - far);
+        result.cols[3][0] = -(right + left) / (right - left);
+        result.cols[3][1] = (top + bottom) / (top - bottom);
+        result.cols[3][2] = near / (near - far);
+
+        result
+    }
+
+    /// Right handed view matrix looking from `eye` towards `target`.
+    pub fn look_at(eye: Vector3, target: Vector3, up: Vector3) -> Self {
+        let forward = (target - eye).normalize();
+        let side = forward.cross(up).normalize();
+        let up = side.cross(forward);
+
+        Matrix4 {
+            cols: [
+                [side.x, up.x, -forward.x, 0.0],
+                [side.y, up.y, -forward.y, 0.0],
+                [side.z, up.z, -forward.z, 0.0],
+                [-side.dot(eye), -up.dot(eye), forward.dot(eye), 1.0],
+            ],
+        }
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Matrix4::identity()
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut result = Matrix4 {
+            cols: [[0.0; 4]; 4],
+        };
+
+        for col in 0..4 {
+            for row in 0..4 {
+                result.cols[col][row] =
+                    (0..4).map(|k| self.cols[k][row] * other.cols[col][k]).sum();
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_matrix_near(actual: Matrix4, expected: Matrix4) {
+        let near = actual
+            .cols
+            .iter()
+            .flatten()
+            .zip(expected.cols.iter().flatten())
+            .all(|(a, e)| (a - e).abs() < EPSILON);
+        assert!(near, "{:?} is not {:?}", actual, expected);
+    }
+
+    fn assert_vector_near(actual: Vector3, expected: Vector3) {
+        assert!(
+            (actual - expected).length() < EPSILON,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // clip space position after the perspective divide
+    fn project(matrix: &Matrix4, point: Vector3) -> Vector3 {
+        let clip = |r: usize| {
+            matrix.cols[0][r] * point.x
+                + matrix.cols[1][r] * point.y
+                + matrix.cols[2][r] * point.z
+                + matrix.cols[3][r]
+        };
+        let w = clip(3);
+
+        Vector3::new(clip(0) / w, clip(1) / w, clip(2) / w)
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let transform = Matrix4::from_translation_rotation_scale(
+            Vector3::new(1.0, 2.0, 3.0),
+            Quaternion::from_euler(0.3, -0.7, 1.1),
+            Vector3::new(2.0, 0.5, 1.5),
+        );
+
+        assert_eq!(Matrix4::identity() * transform, transform);
+        assert_eq!(transform * Matrix4::identity(), transform);
+        assert_eq!(
+            Matrix4::identity().transform_point(Vector3::new(4.0, 5.0, 6.0)),
+            Vector3::new(4.0, 5.0, 6.0)
+        );
+    }
+
+    #[test]
+    fn translation_round_trip() {
+        let offset = Vector3::new(1.5, -2.0, 0.25);
+        let point = Vector3::new(3.0, 4.0, 5.0);
+
+        let moved = Matrix4::translation(offset).transform_point(point);
+        assert_eq!(moved, point + offset);
+        assert_eq!(Matrix4::translation(-offset).transform_point(moved), point);
+        assert_matrix_near(
+            Matrix4::translation(offset) * Matrix4::translation(-offset),
+            Matrix4::identity(),
+        );
+        assert_eq!(Matrix4::translation(offset).translation_part(), offset);
+        // directions are not moved
+        assert_eq!(Matrix4::translation(offset).transform_vector(point), point);
+    }
+
+    #[test]
+    fn perspective_depth_goes_from_zero_at_near_to_one_at_far() {
+        let projection = Matrix4::perspective(60.0_f32.to_radians(), 1.5, 0.1, 100.0);
+
+        assert!(project(&projection, Vector3::new(0.0, 0.0, -0.1)).z.abs() < EPSILON);
+        assert!((project(&projection, Vector3::new(0.0, 0.0, -100.0)).z - 1.0).abs() < EPSILON);
+        let middle = project(&projection, Vector3::new(0.0, 0.0, -10.0)).z;
+        assert!(middle > 0.0 && middle < 1.0);
+    }
+
+    #[test]
+    fn perspective_flips_y_for_vulkan() {
+        let fov_y = 90.0_f32.to_radians();
+        let projection = Matrix4::perspective(fov_y, 2.0, 0.1, 100.0);
+
+        // the top edge of the frustum at depth 1 ends at the top of the screen, which is -1
+        let top = project(&projection, Vector3::new(0.0, 1.0, -1.0));
+        assert!((top.y + 1.0).abs() < EPSILON);
+        // the aspect ratio widens the frustum horizontally
+        let right = project(&projection, Vector3::new(2.0, 0.0, -1.0));
+        assert!((right.x - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn orthographic_maps_the_box_to_clip_space() {
+        let projection = Matrix4::orthographic(-2.0, 2.0, -1.0, 1.0, 0.5, 10.0);
+
+        assert_vector_near(
+            projection.transform_point(Vector3::new(-2.0, 1.0, -0.5)),
+            Vector3::new(-1.0, -1.0, 0.0),
+        );
+        assert_vector_near(
+            projection.transform_point(Vector3::new(2.0, -1.0, -10.0)),
+            Vector3::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn look_at_moves_the_target_in_front_of_the_eye() {
+        let eye = Vector3::new(3.0, 2.0, 1.0);
+        let view = Matrix4::look_at(eye, Vector3::zero(), Vector3::up());
+
+        assert_vector_near(view.transform_point(eye), Vector3::zero());
+        assert_vector_near(
+            view.transform_point(Vector3::zero()),
+            Vector3::new(0.0, 0.0, -eye.length()),
+        );
+    }
+
+    #[test]
+    fn quaternion_rotates_counter_clockwise() {
+        let rotation = Quaternion::from_axis_angle(Vector3::up(), std::f32::consts::FRAC_PI_2);
+
+        assert_vector_near(
+            rotation
+                .to_matrix()
+                .transform_vector(Vector3::new(1.0, 0.0, 0.0)),
+            Vector3::new(0.0, 0.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn quaternion_product_applies_the_right_side_first() {
+        let x = Quaternion::from_axis_angle(Vector3::new(1.0, 0.0, 0.0), 0.4);
+        let y = Quaternion::from_axis_angle(Vector3::up(), -1.2);
+
+        assert_matrix_near((y * x).to_matrix(), y.to_matrix() * x.to_matrix());
+        assert_matrix_near(
+            Quaternion::from_euler(0.4, -1.2, 0.0).to_matrix(),
+            (y * x).to_matrix(),
+        );
+    }
+}
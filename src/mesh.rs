@@ -0,0 +1,443 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
+    device::Device,
+};
+
+use crate::{debug_names, error_utils::EngineError, math::Vector3};
+
+// repr(C) so compute shaders can address the fields of vertex buffers
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub colour: [f32; 3],
+    // texture coordinates, (0, 0) is the top left corner of the texture
+    pub uv: [f32; 2],
+}
+
+vulkano::impl_vertex!(Vertex, position, normal, colour, uv);
+
+impl Vertex {
+    pub const fn new(position: [f32; 3], normal: [f32; 3], colour: [f32; 3]) -> Self {
+        Vertex {
+            position,
+            normal,
+            colour,
+            uv: [0.0, 0.0],
+        }
+    }
+
+    pub const fn with_uv(self, uv: [f32; 2]) -> Self {
+        Vertex { uv, ..self }
+    }
+}
+
+/// Where the vertices of a mesh come from, kept so scenes can be saved back to disk.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshSource {
+    Triangle,
+    Floor { half_size: f32, colour: [f32; 3] },
+    // Wavefront OBJ file
+    File(PathBuf),
+    // vertices given directly to `create_mesh`
+    Vertices,
+}
+
+impl MeshSource {
+    pub fn vertices(&self) -> Result<Vec<Vertex>, EngineError> {
+        match self {
+            MeshSource::Triangle => Ok(triangle_vertices()),
+            MeshSource::Floor { half_size, colour } => Ok(floor_vertices(*half_size, *colour)),
+            MeshSource::File(path) => load_obj(path),
+            MeshSource::Vertices => Err(EngineError::MeshLoadError(
+                "Meshes created from vertices can not be loaded again".to_string(),
+            )),
+        }
+    }
+}
+
+/// Triangle list geometry uploaded to the GPU, drawn with `draw_instanced`.
+pub struct Mesh {
+    name: String,
+    source: MeshSource,
+    vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    bounds: (Vector3, Vector3),
+}
+
+impl Mesh {
+    pub fn new(device: Arc<Device>, vertices: &[Vertex]) -> Result<Arc<Self>, EngineError> {
+        Self::with_source(device, "", MeshSource::Vertices, vertices)
+    }
+
+    pub fn from_source(
+        device: Arc<Device>,
+        name: &str,
+        source: MeshSource,
+    ) -> Result<Arc<Self>, EngineError> {
+        let vertices = source.vertices()?;
+
+        Self::with_source(device, name, source, &vertices)
+    }
+
+    fn with_source(
+        device: Arc<Device>,
+        name: &str,
+        source: MeshSource,
+        vertices: &[Vertex],
+    ) -> Result<Arc<Self>, EngineError> {
+        // compute shaders may write the vertices, see `ColourCycle`
+        let usage = BufferUsage {
+            vertex_buffer: true,
+            storage_buffer: true,
+            ..BufferUsage::none()
+        };
+        let vertex_buffer =
+            CpuAccessibleBuffer::from_iter(device, usage, false, vertices.iter().cloned())?;
+        debug_names::name_buffer(&*vertex_buffer, &format!("{} vertices", name));
+
+        Ok(Arc::new(Mesh {
+            name: name.to_string(),
+            source,
+            vertex_buffer,
+            bounds: point_bounds(vertices.iter().map(|vertex| vertex.position.into())),
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &MeshSource {
+        &self.source
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_buffer.len()
+    }
+
+    /// Opposite corners of the box around the vertices, in the space of the mesh.
+    pub fn bounds(&self) -> (Vector3, Vector3) {
+        self.bounds
+    }
+
+    pub(crate) fn vertex_buffer(&self) -> Arc<CpuAccessibleBuffer<[Vertex]>> {
+        self.vertex_buffer.clone()
+    }
+}
+
+/// Smallest and largest coordinates of the points, the origin twice without points.
+pub fn point_bounds(points: impl IntoIterator<Item = Vector3>) -> (Vector3, Vector3) {
+    let mut points = points.into_iter();
+    let first = match points.next() {
+        Some(point) => point,
+        None => return (Vector3::zero(), Vector3::zero()),
+    };
+
+    points.fold((first, first), |(min, max), position| {
+        (
+            Vector3::new(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            ),
+            Vector3::new(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            ),
+        )
+    })
+}
+
+pub fn triangle_vertices() -> Vec<Vertex> {
+    vec![
+        Vertex::new([-0.5, -0.25, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]).with_uv([0.0, 1.0]),
+        Vertex::new([0.0, 0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]).with_uv([0.5, 0.0]),
+        Vertex::new([0.25, -0.1, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]).with_uv([1.0, 1.0]),
+    ]
+}
+
+/// Square facing up, centered on the origin. Front faces are clockwise, like the triangle. The
+/// texture covers the whole square once.
+pub fn floor_vertices(half_size: f32, colour: [f32; 3]) -> Vec<Vertex> {
+    let up = [0.0, 1.0, 0.0];
+    let corner = |x: f32, z: f32| {
+        Vertex::new([x * half_size, 0.0, z * half_size], up, colour)
+            .with_uv([(x + 1.0) * 0.5, (z + 1.0) * 0.5])
+    };
+
+    vec![
+        corner(-1.0, -1.0),
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, 1.0),
+    ]
+}
+
+/// Reads the triangles of a Wavefront OBJ file: positions, texture coordinates, normals and
+/// polygon faces, which are split into triangle fans. Faces without normals get flat ones and
+/// vertices are white.
+pub fn load_obj(path: &Path) -> Result<Vec<Vertex>, EngineError> {
+    let source = fs::read_to_string(path).map_err(|err| {
+        EngineError::MeshLoadError(format!("Could not read {}: {}", path.display(), err))
+    })?;
+
+    parse_obj(&source, &path.display().to_string())
+}
+
+/// `load_obj` for text already in memory, `origin` names it in error messages.
+pub fn parse_obj(source: &str, origin: &str) -> Result<Vec<Vertex>, EngineError> {
+    let error = |line: usize, message: String| {
+        EngineError::MeshLoadError(format!("{}:{}: {}", origin, line, message))
+    };
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut vertices = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut parts = line.split_whitespace();
+
+        match parts.next() {
+            Some("v") | Some("vn") => {
+                let values: Vec<f32> = parts
+                    .take(3)
+                    .map(|part| part.parse::<f32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| error(line_number, format!("invalid number: {}", err)))?;
+                if values.len() != 3 {
+                    return Err(error(line_number, "expected 3 coordinates".to_string()));
+                }
+
+                let value = [values[0], values[1], values[2]];
+                if line.starts_with("vn") {
+                    normals.push(value);
+                } else {
+                    positions.push(value);
+                }
+            }
+            Some("vt") => {
+                let values: Vec<f32> = parts
+                    .take(2)
+                    .map(|part| part.parse::<f32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| error(line_number, format!("invalid number: {}", err)))?;
+                if values.is_empty() {
+                    return Err(error(
+                        line_number,
+                        "expected texture coordinates".to_string(),
+                    ));
+                }
+
+                // OBJ puts v = 0 at the bottom of the image, Vulkan at the top
+                let v = values.get(1).copied().unwrap_or(0.0);
+                uvs.push([values[0], 1.0 - v]);
+            }
+            Some("f") => {
+                // v, v/vt, v//vn or v/vt/vn, indices start at 1 and negative ones count from
+                // the end
+                let corners = parts
+                    .map(|corner| {
+                        let mut indices = corner.split('/');
+                        let position = obj_index(indices.next(), positions.len())?;
+                        let uv = match indices.next() {
+                            Some(text) if !text.is_empty() => {
+                                Some(obj_index(Some(text), uvs.len())?)
+                            }
+                            _ => None,
+                        };
+                        let normal = match indices.next() {
+                            Some(text) if !text.is_empty() => {
+                                Some(obj_index(Some(text), normals.len())?)
+                            }
+                            _ => None,
+                        };
+
+                        Some((position, uv, normal))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| error(line_number, format!("invalid face '{}'", line)))?;
+
+                if corners.len() < 3 {
+                    return Err(error(
+                        line_number,
+                        "faces need at least 3 vertices".to_string(),
+                    ));
+                }
+
+                for i in 1..corners.len() - 1 {
+                    // OBJ front faces are counter clockwise, ours are clockwise
+                    let triangle = [corners[0], corners[i + 1], corners[i]];
+                    let flat_normal = face_normal(
+                        positions[triangle[0].0],
+                        positions[triangle[1].0],
+                        positions[triangle[2].0],
+                    );
+
+                    for &(position, uv, normal) in &triangle {
+                        let normal = normal.map_or(flat_normal, |normal| normals[normal]);
+                        let uv = uv.map_or([0.0, 0.0], |uv| uvs[uv]);
+                        vertices.push(
+                            Vertex::new(positions[position], normal, [1.0, 1.0, 1.0]).with_uv(uv),
+                        );
+                    }
+                }
+            }
+            // groups, materials and comments are not used
+            _ => {}
+        }
+    }
+
+    if vertices.is_empty() {
+        return Err(EngineError::MeshLoadError(format!(
+            "{} has no faces",
+            origin
+        )));
+    }
+
+    Ok(vertices)
+}
+
+fn obj_index(text: Option<&str>, count: usize) -> Option<usize> {
+    let index = text?.parse::<i64>().ok()?;
+    let index = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+
+    if index >= 0 && (index as usize) < count {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+// normal of a clockwise triangle
+fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let (a, b, c) = (Vector3::from(a), Vector3::from(b), Vector3::from(c));
+    let normal = (c - a).cross(b - a).normalize();
+
+    [normal.x, normal.y, normal.z]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    fn positions(vertices: &[Vertex]) -> Vec<[f32; 3]> {
+        vertices.iter().map(|vertex| vertex.position).collect()
+    }
+
+    fn error(source: &str) -> String {
+        match parse_obj(source, "test.obj") {
+            Err(EngineError::MeshLoadError(message)) => message,
+            other => panic!("not a mesh load error: {:?}", other.map(|v| v.len())),
+        }
+    }
+
+    #[test]
+    fn polygons_are_split_into_clockwise_fans() {
+        let vertices = parse_obj(&format!("# square\n{}f 1 2 3 4\n", SQUARE), "test.obj").unwrap();
+
+        assert_eq!(
+            positions(&vertices),
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+            ]
+        );
+        // counter clockwise seen from +Z in the file, so it faces +Z
+        for vertex in &vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+            assert_eq!(vertex.colour, [1.0, 1.0, 1.0]);
+            assert_eq!(vertex.uv, [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let absolute = parse_obj(&format!("{}f 2 3 4\n", SQUARE), "test.obj").unwrap();
+        let relative = parse_obj(&format!("{}f -3 -2 -1\n", SQUARE), "test.obj").unwrap();
+
+        assert_eq!(positions(&relative), positions(&absolute));
+    }
+
+    #[test]
+    fn texture_coordinates_and_normals() {
+        let source = format!(
+            "{}vt 0.25 0.75\nvn 0 1 0\nf 1/1/1 2/1/1 3/1/1\nf 1//1 3//1 4//1\nf 1/1 3/1 4/1\n",
+            SQUARE
+        );
+        let vertices = parse_obj(&source, "test.obj").unwrap();
+
+        assert_eq!(vertices.len(), 9);
+        // v is flipped, Vulkan has the top of the image at 0
+        assert_eq!(vertices[0].uv, [0.25, 0.25]);
+        assert_eq!(vertices[0].normal, [0.0, 1.0, 0.0]);
+        assert_eq!(vertices[3].uv, [0.0, 0.0]);
+        assert_eq!(vertices[3].normal, [0.0, 1.0, 0.0]);
+        assert_eq!(vertices[6].uv, [0.25, 0.25]);
+        assert_eq!(vertices[6].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        assert_eq!(
+            error(&format!("{}f 1 2 5\n", SQUARE)),
+            "test.obj:5: invalid face 'f 1 2 5'"
+        );
+        // indices start at 1
+        assert_eq!(
+            error(&format!("{}f 0 1 2\n", SQUARE)),
+            "test.obj:5: invalid face 'f 0 1 2'"
+        );
+        assert_eq!(
+            error(&format!("{}f -5 1 2\n", SQUARE)),
+            "test.obj:5: invalid face 'f -5 1 2'"
+        );
+        assert_eq!(
+            error(&format!("{}vt 0 0\nf 1/2 2/1 3/1\n", SQUARE)),
+            "test.obj:6: invalid face 'f 1/2 2/1 3/1'"
+        );
+        assert_eq!(
+            error(&format!("{}f 1//1 2//1 3//1\n", SQUARE)),
+            "test.obj:5: invalid face 'f 1//1 2//1 3//1'"
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert_eq!(
+            error(&format!("{}f 1 2\n", SQUARE)),
+            "test.obj:5: faces need at least 3 vertices"
+        );
+        assert_eq!(error("v 1 2\n"), "test.obj:1: expected 3 coordinates");
+        assert!(error("v 1 x 3\n").starts_with("test.obj:1: invalid number"));
+        assert_eq!(error("\nvt\n"), "test.obj:2: expected texture coordinates");
+        assert_eq!(error(SQUARE), "test.obj has no faces");
+    }
+
+    #[test]
+    fn bounds_cover_every_point() {
+        let (min, max) = point_bounds(triangle_vertices().iter().map(|v| v.position.into()));
+        assert_eq!(min, Vector3::new(-0.5, -0.25, 0.0));
+        assert_eq!(max, Vector3::new(0.25, 0.5, 0.0));
+
+        assert_eq!(point_bounds(Vec::new()), (Vector3::zero(), Vector3::zero()));
+    }
+}
@@ -0,0 +1,622 @@
+use std::sync::Arc;
+use std::time::Instant;
+use vulkano::{
+    buffer::{BufferUsage, CpuBufferPool},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet},
+    device::{Device, Queue},
+    format::{ClearValue, Format},
+    framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass},
+    image::{Dimensions, ImageViewAccess, ImmutableImage},
+    pipeline::{
+        blend::{AttachmentBlend, BlendFactor},
+        viewport::{Scissor, Viewport},
+        GraphicsPipeline, GraphicsPipelineAbstract,
+    },
+    sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode},
+    sync::GpuFuture,
+};
+use winit::event::{
+    ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::{
+    debug_names, display_settings,
+    error_utils::EngineError,
+    gpu_memory::{self, MemoryCategory, MemoryUsage},
+};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 450
+
+        layout(push_constant) uniform PushConstants {
+            vec2 screenSize;
+        } pushConstants;
+
+        layout(location = 0) in vec2 position;
+        layout(location = 1) in vec2 uv;
+        layout(location = 2) in vec4 colour;
+
+        layout(location = 0) out vec2 fragUv;
+        layout(location = 1) out vec4 fragColour;
+
+        void main() {
+            // egui points start at the top left corner, like Vulkan's clip space
+            gl_Position = vec4(2.0 * position / pushConstants.screenSize - 1.0, 0.0, 1.0);
+            fragUv = uv;
+            fragColour = colour;
+        }"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        layout(set = 0, binding = 0) uniform sampler2D fontTexture;
+
+        layout(location = 0) in vec2 fragUv;
+        layout(location = 1) in vec4 fragColour;
+
+        layout(location = 0) out vec4 f_color;
+
+        void main() {
+            // the font texture only has coverage, and colours are premultiplied
+            f_color = fragColour * texture(fontTexture, fragUv).r;
+        }"
+    }
+}
+
+// a line of mouse wheel scrolls this many points
+const SCROLL_LINE_HEIGHT: f32 = 50.0;
+
+// Matches the layout of PushConstants in the vertex shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct OverlayPushConstants {
+    screen_size: [f32; 2],
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+#[repr(C)]
+struct OverlayVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    colour: [f32; 4],
+}
+
+vulkano::impl_vertex!(OverlayVertex, position, uv, colour);
+
+/// egui user interface drawn over the scene.
+///
+/// Window events are fed with `handle_window_event`, then each frame the panels are built
+/// between `begin_frame` and `end_frame`, and the result handed to the renderer.
+pub struct Overlay {
+    context: egui::CtxRef,
+    // events gathered since the last frame
+    input: egui::RawInput,
+    start: Instant,
+    pixels_per_point: f32,
+    pointer_position: egui::Pos2,
+    modifiers: egui::Modifiers,
+    pub visible: bool,
+}
+
+/// What egui produced for a frame, ready to be drawn by the renderer.
+pub struct OverlayFrame {
+    meshes: Vec<egui::ClippedMesh>,
+    texture: Arc<egui::Texture>,
+    pixels_per_point: f32,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            context: egui::CtxRef::default(),
+            input: egui::RawInput::default(),
+            start: Instant::now(),
+            pixels_per_point: 1.0,
+            pointer_position: egui::Pos2::ZERO,
+            modifiers: egui::Modifiers::default(),
+            visible: true,
+        }
+    }
+
+    /// Whether the pointer is over a panel or dragging something in it, so the camera should
+    /// leave the mouse alone.
+    pub fn wants_pointer(&self) -> bool {
+        self.visible && (self.context.wants_pointer_input() || self.context.is_pointer_over_area())
+    }
+
+    /// Whether a text field has the focus, so key presses are not actions.
+    pub fn wants_keyboard(&self) -> bool {
+        self.visible && self.context.wants_keyboard_input()
+    }
+
+    /// Passes the event to egui. Returns true when the UI takes it and the rest of the
+    /// application should ignore it. Releases are never taken, so nothing stays held down.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_position = egui::pos2(
+                    position.x as f32 / self.pixels_per_point,
+                    position.y as f32 / self.pixels_per_point,
+                );
+                self.input
+                    .events
+                    .push(egui::Event::PointerMoved(self.pointer_position));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                let wanted = self.wants_pointer();
+                if let Some(button) = pointer_button(*button) {
+                    self.input.events.push(egui::Event::PointerButton {
+                        pos: self.pointer_position,
+                        button,
+                        pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                pressed && wanted
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * SCROLL_LINE_HEIGHT,
+                    MouseScrollDelta::PixelDelta(position) => {
+                        egui::vec2(position.x as f32, position.y as f32) / self.pixels_per_point
+                    }
+                };
+                self.input.scroll_delta += delta;
+                self.wants_pointer()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = modifiers(*state);
+                false
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                if character.is_control() {
+                    return false;
+                }
+                self.input
+                    .events
+                    .push(egui::Event::Text(character.to_string()));
+                self.wants_keyboard()
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let pressed = input.state == ElementState::Pressed;
+                let wanted = self.wants_keyboard();
+                if let Some(key) = input.virtual_keycode.and_then(key) {
+                    self.input.events.push(egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                pressed && wanted
+            }
+            _ => false,
+        }
+    }
+
+    /// Starts a frame of `screen_size` pixels. Panels are added to the returned context until
+    /// `end_frame`.
+    pub fn begin_frame(&mut self, screen_size: [u32; 2], pixels_per_point: f32) -> egui::CtxRef {
+        self.pixels_per_point = pixels_per_point;
+
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(screen_size[0] as f32, screen_size[1] as f32) / pixels_per_point,
+        ));
+        input.pixels_per_point = Some(pixels_per_point);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+
+        self.context.begin_frame(input);
+        self.context.clone()
+    }
+
+    pub fn end_frame(&mut self) -> OverlayFrame {
+        let (_output, shapes) = self.context.end_frame();
+
+        OverlayFrame {
+            meshes: self.context.tessellate(shapes),
+            texture: self.context.texture(),
+            pixels_per_point: self.pixels_per_point,
+        }
+    }
+}
+
+fn pointer_button(button: MouseButton) -> Option<egui::PointerButton> {
+    match button {
+        MouseButton::Left => Some(egui::PointerButton::Primary),
+        MouseButton::Right => Some(egui::PointerButton::Secondary),
+        MouseButton::Middle => Some(egui::PointerButton::Middle),
+        MouseButton::Other(_) => None,
+    }
+}
+
+fn modifiers(state: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: if cfg!(target_os = "macos") {
+            state.logo()
+        } else {
+            state.ctrl()
+        },
+    }
+}
+
+// the keys egui knows about, for text fields and keyboard navigation
+fn key(keycode: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+
+    let key = match keycode {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Key::Num0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Key::Num1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Key::Num2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Key::Num3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Key::Num4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Key::Num5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Key::Num6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Key::Num7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Key::Num8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Key::Num9,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Draws `OverlayFrame`s in a render pass of their own, after the main pass, loading what the
+/// scene left in the colour image.
+pub(crate) struct OverlayRenderer {
+    queue: Arc<Queue>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    sampler: Arc<Sampler>,
+    // egui colours are sRGB, they are decoded first when the target expects linear ones
+    linear_colours: bool,
+    // uploaded again whenever egui adds glyphs to it
+    font_texture: Option<(u64, Arc<ImmutableImage<Format>>)>,
+    font_set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    vertex_pool: CpuBufferPool<OverlayVertex>,
+    index_pool: CpuBufferPool<u32>,
+}
+
+impl OverlayRenderer {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        format: Format,
+        presentable: bool,
+    ) -> Result<Self, EngineError> {
+        let render_pass = Self::create_render_pass(device.clone(), format, presentable)?;
+        let pipeline = Self::create_pipeline(device.clone(), render_pass.clone())?;
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )?;
+        debug_names::name_render_pass(&*render_pass, "overlay render pass");
+        debug_names::name_graphics_pipeline(&*pipeline, "overlay pipeline");
+        debug_names::name_object(&*sampler, "overlay font sampler");
+
+        Ok(OverlayRenderer {
+            queue,
+            render_pass,
+            pipeline,
+            sampler,
+            linear_colours: display_settings::expects_linear_output(format),
+            font_texture: None,
+            font_set: None,
+            vertex_pool: CpuBufferPool::vertex_buffer(device.clone()),
+            index_pool: CpuBufferPool::new(device, BufferUsage::index_buffer()),
+        })
+    }
+
+    /// Framebuffer over a colour image the main pass rendered to.
+    pub fn framebuffer<I>(&self, image: I) -> Arc<dyn FramebufferAbstract + Send + Sync>
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        Arc::new(
+            Framebuffer::start(self.render_pass.clone())
+                .add(image)
+                .unwrap()
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Uploads the font texture when egui changed it since the last frame. Blocks until the
+    /// upload is done.
+    pub fn prepare(&mut self, frame: &OverlayFrame) -> Result<(), EngineError> {
+        let texture = &frame.texture;
+        if let Some((version, _)) = &self.font_texture {
+            if *version == texture.version {
+                return Ok(());
+            }
+        }
+
+        let (image, upload) = ImmutableImage::from_iter(
+            texture.pixels.iter().cloned(),
+            Dimensions::Dim2d {
+                width: texture.width as u32,
+                height: texture.height as u32,
+            },
+            Format::R8Unorm,
+            self.queue.clone(),
+        )?;
+        upload.then_signal_fence_and_flush()?.wait(None)?;
+        debug_names::name_image(&*image, "overlay font");
+
+        let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+        let set = PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(image.clone(), self.sampler.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        self.font_texture = Some((texture.version, image));
+        self.font_set = Some(Arc::new(set));
+
+        Ok(())
+    }
+
+    /// Records the overlay pass. `prepare` must have been called with the same frame.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+        frame: &OverlayFrame,
+    ) -> Result<(), EngineError> {
+        let font_set = match &self.font_set {
+            Some(set) => set.clone(),
+            None => {
+                return Err(EngineError::VulkanValidationError(String::from(
+                    "The overlay font texture was not uploaded",
+                )))
+            }
+        };
+
+        let [width, height] = [framebuffer.width(), framebuffer.height()];
+        let viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [width as f32, height as f32],
+            depth_range: 0.0..1.0,
+        };
+        let push_constants = OverlayPushConstants {
+            screen_size: [
+                width as f32 / frame.pixels_per_point,
+                height as f32 / frame.pixels_per_point,
+            ],
+        };
+
+        builder
+            .begin_render_pass(framebuffer, false, vec![ClearValue::None])
+            .unwrap();
+
+        for egui::ClippedMesh(clip_rect, mesh) in &frame.meshes {
+            // no user textures are registered, everything samples the font texture
+            if mesh.texture_id != egui::TextureId::Egui || mesh.indices.is_empty() {
+                continue;
+            }
+
+            let scissor = match scissor(*clip_rect, frame.pixels_per_point, [width, height]) {
+                Some(scissor) => scissor,
+                None => continue,
+            };
+            let dynamic_state = DynamicState {
+                viewports: Some(vec![viewport.clone()]),
+                scissors: Some(vec![scissor]),
+                ..DynamicState::none()
+            };
+
+            let linear_colours = self.linear_colours;
+            let vertices = self
+                .vertex_pool
+                .chunk(mesh.vertices.iter().map(|vertex| OverlayVertex {
+                    position: [vertex.pos.x, vertex.pos.y],
+                    uv: [vertex.uv.x, vertex.uv.y],
+                    colour: colour(vertex.color, linear_colours),
+                }))
+                .unwrap();
+            let indices = self.index_pool.chunk(mesh.indices.iter().cloned()).unwrap();
+
+            builder
+                .draw_indexed(
+                    self.pipeline.clone(),
+                    &dynamic_state,
+                    vec![Arc::new(vertices)],
+                    indices,
+                    font_set.clone(),
+                    push_constants,
+                )
+                .unwrap();
+        }
+
+        builder.end_render_pass().unwrap();
+
+        Ok(())
+    }
+
+    pub fn memory_usage(&self, usage: &mut MemoryUsage) {
+        if let Some((_, image)) = &self.font_texture {
+            usage.add(
+                MemoryCategory::Textures,
+                gpu_memory::image_bytes(image.dimensions().width_height(), Format::R8Unorm),
+            );
+        }
+        usage.add(
+            MemoryCategory::VertexBuffers,
+            gpu_memory::pool_bytes(&self.vertex_pool) + gpu_memory::pool_bytes(&self.index_pool),
+        );
+    }
+
+    // same final layouts as the main render pass
+    fn create_render_pass(
+        device: Arc<Device>,
+        format: Format,
+        presentable: bool,
+    ) -> Result<Arc<dyn RenderPassAbstract + Send + Sync>, EngineError> {
+        macro_rules! overlay_render_pass {
+            ($layout:path) => {
+                Arc::new(
+                    vulkano::single_pass_renderpass!(device.clone(),
+                        attachments: {
+                            color: {
+                                load: Load,
+                                store: Store,
+                                format: format,
+                                samples: 1,
+                                initial_layout: $layout,
+                                final_layout: $layout,
+                            }
+                        },
+                        pass: {
+                            color: [color],
+                            depth_stencil: {}
+                        }
+                    )
+                    .unwrap(),
+                )
+            };
+        }
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = if presentable {
+            overlay_render_pass!(ImageLayout::PresentSrc)
+        } else {
+            overlay_render_pass!(ImageLayout::ColorAttachmentOptimal)
+        };
+
+        Ok(render_pass)
+    }
+
+    fn create_pipeline(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
+        let vertex_shader = vertex_shader::Shader::load(device.clone())?;
+        let fragment_shader = fragment_shader::Shader::load(device.clone())?;
+
+        // egui colours are premultiplied by their alpha
+        let blend = AttachmentBlend {
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_source: BlendFactor::OneMinusDstAlpha,
+            alpha_destination: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        };
+
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_single_buffer::<OverlayVertex>()
+            .vertex_shader(vertex_shader.main_entry_point(), ())
+            .triangle_list()
+            .viewports_scissors_dynamic(1)
+            .fragment_shader(fragment_shader.main_entry_point(), ())
+            .cull_mode_disabled()
+            .blend_collective(blend)
+            .render_pass(Subpass::from(render_pass, 0).unwrap())
+            .build(device)?;
+
+        Ok(Arc::new(pipeline))
+    }
+}
+
+fn colour(colour: egui::Color32, linear: bool) -> [f32; 4] {
+    if linear {
+        egui::Rgba::from(colour).to_array()
+    } else {
+        let [r, g, b, a] = colour.to_array();
+        [
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        ]
+    }
+}
+
+// clip rectangle in points to pixels, None when nothing of it is on screen
+fn scissor(clip_rect: egui::Rect, pixels_per_point: f32, size: [u32; 2]) -> Option<Scissor> {
+    let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+    let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+    let max_x = ((clip_rect.max.x * pixels_per_point).round().max(0.0) as u32).min(size[0]);
+    let max_y = ((clip_rect.max.y * pixels_per_point).round().max(0.0) as u32).min(size[1]);
+
+    if max_x <= min_x || max_y <= min_y {
+        return None;
+    }
+
+    Some(Scissor {
+        origin: [min_x as i32, min_y as i32],
+        dimensions: [max_x - min_x, max_y - min_y],
+    })
+}
@@ -0,0 +1,510 @@
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    display_settings::{VsyncMode, WindowMode},
+    input::{AxisBinding, Binding, Input, InputSource},
+    lighting::LightKind,
+    pipeline_cache::PolygonMode,
+    scene::{NodeId, Scene},
+    vulkan_renderer::VulkanRenderer,
+};
+
+// offered when the frame limit is turned on
+const DEFAULT_FRAME_LIMIT: f32 = 60.0;
+const SHADOW_RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+
+// what the next press is bound to, an axis takes two presses
+enum Rebinding {
+    Action(String),
+    AxisPositive(String),
+    AxisNegative(String, Binding),
+}
+
+/// The built-in windows of the overlay, and what they remember between frames.
+#[derive(Default)]
+pub struct Panels {
+    selected_node: Option<NodeId>,
+    rebinding: Option<Rebinding>,
+}
+
+impl Panels {
+    /// Adds the stats, settings, controls and scene windows to the frame. `debug_draw` is the flag
+    /// main checks before drawing the debug lines.
+    pub fn show(
+        &mut self,
+        context: &egui::CtxRef,
+        render: &mut VulkanRenderer,
+        scene: &mut Scene,
+        input: &mut Input,
+        debug_draw: &mut bool,
+    ) {
+        stats_window(context, render);
+        settings_window(context, render, debug_draw);
+        self.controls_window(context, input);
+        self.scene_window(context, scene);
+    }
+
+    fn controls_window(&mut self, context: &egui::CtxRef, input: &mut Input) {
+        // presses taken by the UI never reach the input, so the button click is not captured
+        if let Some(binding) = input.last_pressed() {
+            self.rebinding = match self.rebinding.take() {
+                _ if binding.source == InputSource::Key(VirtualKeyCode::Escape) => None,
+                Some(Rebinding::Action(action)) => {
+                    input.map.rebind_action(&action, binding);
+                    None
+                }
+                Some(Rebinding::AxisPositive(axis)) => Some(Rebinding::AxisNegative(axis, binding)),
+                Some(Rebinding::AxisNegative(axis, positive)) => {
+                    input.map.rebind_axis(
+                        &axis,
+                        AxisBinding {
+                            positive,
+                            negative: binding,
+                        },
+                    );
+                    None
+                }
+                None => None,
+            };
+        }
+
+        egui::Window::new("Controls")
+            .default_pos(egui::pos2(10.0, 560.0))
+            .show(context, |ui| {
+                match &self.rebinding {
+                    Some(Rebinding::Action(action)) => {
+                        ui.label(format!(
+                            "Press the new binding of {}, Escape cancels",
+                            action
+                        ));
+                    }
+                    Some(Rebinding::AxisPositive(axis)) => {
+                        ui.label(format!(
+                            "Press the positive side of {}, Escape cancels",
+                            axis
+                        ));
+                    }
+                    Some(Rebinding::AxisNegative(axis, _)) => {
+                        ui.label(format!(
+                            "Press the negative side of {}, Escape cancels",
+                            axis
+                        ));
+                    }
+                    None => (),
+                }
+
+                let mut rebinding = None;
+                egui::Grid::new("actions").show(ui, |ui| {
+                    for action in input.map.action_names() {
+                        ui.label(action);
+                        ui.label(join(input.map.action_bindings(action)));
+                        if ui.button("Rebind").clicked() {
+                            rebinding = Some(Rebinding::Action(action.to_string()));
+                        }
+                        ui.end_row();
+                    }
+                    for axis in input.map.axis_names() {
+                        ui.label(axis);
+                        let bindings: Vec<String> = input
+                            .map
+                            .axis_bindings(axis)
+                            .iter()
+                            .map(|binding| format!("{} / {}", binding.positive, binding.negative))
+                            .collect();
+                        ui.label(bindings.join(", "));
+                        if ui.button("Rebind").clicked() {
+                            rebinding = Some(Rebinding::AxisPositive(axis.to_string()));
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                let mut unbind = None;
+                ui.collapsing("Unbind", |ui| {
+                    for action in input.map.action_names() {
+                        if !input.map.action_bindings(action).is_empty()
+                            && ui.button(action).clicked()
+                        {
+                            unbind = Some(action.to_string());
+                        }
+                    }
+                });
+                if let Some(action) = unbind {
+                    input.map.unbind_action(&action);
+                }
+                if rebinding.is_some() {
+                    self.rebinding = rebinding;
+                }
+            });
+    }
+
+    fn scene_window(&mut self, context: &egui::CtxRef, scene: &mut Scene) {
+        // removed nodes can not stay selected
+        if let Some(id) = self.selected_node {
+            if scene.node(id).is_err() {
+                self.selected_node = None;
+            }
+        }
+
+        egui::Window::new("Scene")
+            .default_pos(egui::pos2(10.0, 320.0))
+            .show(context, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for &root in scene.roots() {
+                            self.node_tree(ui, scene, root);
+                        }
+                    });
+
+                if let Some(id) = self.selected_node {
+                    ui.separator();
+                    node_inspector(ui, scene, id);
+                }
+            });
+    }
+
+    fn node_tree(&mut self, ui: &mut egui::Ui, scene: &Scene, id: NodeId) {
+        let node = match scene.node(id) {
+            Ok(node) => node,
+            Err(_) => return,
+        };
+        let selected = self.selected_node == Some(id);
+
+        if node.children().is_empty() {
+            if ui.selectable_label(selected, &node.name).clicked() {
+                self.selected_node = Some(id);
+            }
+            return;
+        }
+
+        let response = egui::CollapsingHeader::new(&node.name)
+            .id_source(id)
+            .default_open(true)
+            .selectable(true)
+            .selected(selected)
+            .show(ui, |ui| {
+                for &child in node.children() {
+                    self.node_tree(ui, scene, child);
+                }
+            });
+        if response.header_response.clicked() {
+            self.selected_node = Some(id);
+        }
+    }
+}
+
+fn stats_window(context: &egui::CtxRef, render: &VulkanRenderer) {
+    egui::Window::new("Stats")
+        .default_pos(egui::pos2(10.0, 10.0))
+        .show(context, |ui| {
+            let summary = render.primary().frame_stats().summary();
+            ui.label(format!(
+                "{:.1} FPS, {:.1} FPS 1% low",
+                summary.average_fps, summary.one_percent_low_fps
+            ));
+            ui.label(format!(
+                "frame {:.3} ms, acquire {:.3} ms, present {:.3} ms",
+                summary.frame.average_ms, summary.acquire.average_ms, summary.present.average_ms
+            ));
+
+            if !render.gpu_timings().is_empty() {
+                ui.collapsing("GPU timings", |ui| {
+                    for timing in render.gpu_timings() {
+                        ui.monospace(timing.to_string());
+                    }
+                });
+            }
+
+            // the closures only run when the sections are open
+            ui.collapsing("Memory", |ui| {
+                ui.monospace(render.memory_report().to_string());
+            });
+            ui.collapsing("Resources", |ui| {
+                ui.monospace(render.resources().residency().to_string());
+            });
+        });
+}
+
+fn settings_window(context: &egui::CtxRef, render: &mut VulkanRenderer, debug_draw: &mut bool) {
+    egui::Window::new("Settings")
+        .default_pos(egui::pos2(10.0, 200.0))
+        .show(context, |ui| {
+            let mut polygon_mode = render.polygon_mode();
+            ui.horizontal(|ui| {
+                ui.label("Polygons");
+                ui.radio_value(&mut polygon_mode, PolygonMode::Fill, "fill");
+                ui.radio_value(&mut polygon_mode, PolygonMode::Line, "wireframe");
+                ui.radio_value(&mut polygon_mode, PolygonMode::Point, "points");
+            });
+            if polygon_mode != render.polygon_mode() {
+                render.set_polygon_mode(polygon_mode);
+            }
+            if render.optional_features().fill_mode_non_solid {
+                let mut emulated = render.polygon_modes_emulated();
+                ui.checkbox(&mut emulated, "emulate wireframe and points");
+                if emulated != render.polygon_modes_emulated() {
+                    render.set_polygon_modes_emulated(emulated);
+                }
+            } else {
+                ui.label("wireframe and points are emulated on this device");
+            }
+            ui.label(format!("{} pipelines built", render.pipeline_count()));
+
+            let mut samples = render.msaa_samples();
+            ui.horizontal(|ui| {
+                ui.label("MSAA");
+                for samples_option in render.supported_msaa_samples() {
+                    let label = if samples_option == 1 {
+                        "off".to_string()
+                    } else {
+                        format!("{}x", samples_option)
+                    };
+                    ui.radio_value(&mut samples, samples_option, label);
+                }
+            });
+            if samples != render.msaa_samples() {
+                if let Err(err) = render.set_msaa_samples(samples) {
+                    eprintln!("Failed to change the MSAA samples: {}", err);
+                }
+            }
+
+            let mut shadows = *render.shadow_settings();
+            ui.collapsing("Shadows", |ui| {
+                egui::ComboBox::from_label("Resolution")
+                    .selected_text(shadows.resolution.to_string())
+                    .show_ui(ui, |ui| {
+                        for &resolution in &SHADOW_RESOLUTIONS {
+                            ui.selectable_value(
+                                &mut shadows.resolution,
+                                resolution,
+                                resolution.to_string(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut shadows.depth_bias, 0.0..=0.01)
+                        .logarithmic(true)
+                        .text("depth bias"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut shadows.slope_bias, 0.0..=0.05)
+                        .logarithmic(true)
+                        .text("slope bias"),
+                );
+                ui.add(egui::Slider::new(&mut shadows.pcf_radius, 0..=3).text("PCF radius"));
+                ui.add(egui::Slider::new(&mut shadows.extent, 1.0..=50.0).text("extent"));
+            });
+            if shadows != *render.shadow_settings() {
+                if let Err(err) = render.set_shadow_settings(shadows) {
+                    eprintln!("Failed to change the shadow settings: {}", err);
+                }
+            }
+
+            let debug_lines = render.debug_draw().line_count();
+            ui.horizontal(|ui| {
+                ui.checkbox(debug_draw, "Debug lines");
+                if *debug_draw {
+                    ui.label(format!("{} lines", debug_lines));
+                }
+            });
+            if *debug_draw && render.optional_features().wide_lines {
+                ui.add(
+                    egui::Slider::new(&mut render.debug_draw().line_width, 1.0..=8.0)
+                        .text("line width"),
+                );
+            }
+
+            // the display settings are those of the window showing the overlay
+            let window = render.primary_mut();
+            let mut stats_in_title = window.stats_in_title();
+            if ui.checkbox(&mut stats_in_title, "Stats in title").changed() {
+                window.set_stats_in_title(stats_in_title);
+            }
+
+            let mut vsync = window.vsync_mode();
+            ui.horizontal(|ui| {
+                ui.label("Vsync");
+                for &mode in &VsyncMode::ALL {
+                    ui.radio_value(&mut vsync, mode, format!("{:?}", mode));
+                }
+            });
+            if vsync != window.vsync_mode() {
+                if let Err(err) = window.set_vsync_mode(vsync) {
+                    eprintln!("Failed to change the vsync mode: {}", err);
+                }
+            }
+            ui.label(format!("Present mode: {:?}", window.present_mode()));
+
+            let mut limited = window.frame_limit().is_some();
+            let mut fps = window.frame_limit().unwrap_or(DEFAULT_FRAME_LIMIT);
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut limited, "Frame limit");
+                ui.add_enabled(
+                    limited,
+                    egui::DragValue::new(&mut fps)
+                        .clamp_range(10.0..=1000.0)
+                        .suffix(" FPS"),
+                );
+            });
+            let limit = if limited { Some(fps) } else { None };
+            if limit != window.frame_limit() {
+                window.set_frame_limit(limit);
+            }
+
+            ui.label(format!("Surface format: {:?}", window.surface_format()));
+
+            let mut window_mode = window.window_mode();
+            ui.horizontal(|ui| {
+                ui.label("Window");
+                for &mode in &WindowMode::ALL {
+                    ui.radio_value(&mut window_mode, mode, format!("{:?}", mode));
+                }
+            });
+            if window_mode != window.window_mode() {
+                if let Err(err) = window.set_window_mode(window_mode) {
+                    eprintln!("Failed to change the window mode: {}", err);
+                }
+            }
+
+            let monitors = window.monitors();
+            let monitor_name = |index: Option<usize>| match index.and_then(|i| monitors.get(i)) {
+                Some(monitor) => monitor.name().unwrap_or_else(|| "unnamed".to_string()),
+                None => "current".to_string(),
+            };
+            let mut monitor = window.monitor();
+            egui::ComboBox::from_label("Fullscreen monitor")
+                .selected_text(monitor_name(monitor))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut monitor, None, monitor_name(None));
+                    for index in 0..monitors.len() {
+                        ui.selectable_value(&mut monitor, Some(index), monitor_name(Some(index)));
+                    }
+                });
+            if monitor != window.monitor() {
+                if let Err(err) = window.set_monitor(monitor) {
+                    eprintln!("Failed to change the monitor: {}", err);
+                }
+            }
+        });
+}
+
+fn join(bindings: &[Binding]) -> String {
+    let names: Vec<String> = bindings.iter().map(|binding| binding.to_string()).collect();
+    if names.is_empty() {
+        "unbound".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+fn node_inspector(ui: &mut egui::Ui, scene: &mut Scene, id: NodeId) {
+    let node = match scene.node(id) {
+        Ok(node) => node,
+        Err(_) => return,
+    };
+    let name = node.name.clone();
+    ui.heading(&name);
+
+    let mut transform = *node.transform();
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Translation");
+        for value in &mut [
+            &mut transform.translation.x,
+            &mut transform.translation.y,
+            &mut transform.translation.z,
+        ] {
+            changed |= ui.add(egui::DragValue::new(*value).speed(0.01)).changed();
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Scale");
+        for value in &mut [
+            &mut transform.scale.x,
+            &mut transform.scale.y,
+            &mut transform.scale.z,
+        ] {
+            changed |= ui
+                .add(
+                    egui::DragValue::new(*value)
+                        .speed(0.01)
+                        .clamp_range(0.001..=100.0),
+                )
+                .changed();
+        }
+    });
+    if changed {
+        if let Err(err) = scene.set_transform(id, transform) {
+            eprintln!("Failed to move {}: {}", name, err);
+        }
+    }
+
+    let current_parent = scene.node(id).ok().and_then(|node| node.parent());
+    let mut parent = current_parent;
+    let node_name = |node: Option<NodeId>| match node.and_then(|node| scene.node(node).ok()) {
+        Some(node) => node.name.clone(),
+        None => "none".to_string(),
+    };
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Parent")
+            .selected_text(node_name(parent))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut parent, None, "none");
+                for (other, node) in scene.iter().filter(|(other, _)| *other != id) {
+                    ui.selectable_value(&mut parent, Some(other), &node.name);
+                }
+            });
+        remove = ui.button("Remove").clicked();
+    });
+    if parent != current_parent {
+        let parent_name = node_name(parent);
+        if let Err(err) = scene.set_parent(id, parent) {
+            eprintln!("Failed to move {} to {}: {}", name, parent_name, err);
+        }
+    }
+    if remove {
+        if let Err(err) = scene.remove_node(id) {
+            eprintln!("Failed to remove {}: {}", name, err);
+        }
+    }
+
+    let node = match scene.node_mut(id) {
+        Ok(node) => node,
+        Err(_) => return,
+    };
+
+    if let Some(mesh) = &node.mesh {
+        ui.label(format!(
+            "Mesh with material {:?} ({:?})",
+            mesh.material.name, mesh.material.shader
+        ));
+    }
+
+    if let Some(light) = &mut node.light {
+        ui.label(match light.kind {
+            LightKind::Directional { .. } => "Directional light",
+            LightKind::Point { .. } => "Point light",
+        });
+        ui.add(egui::Slider::new(&mut light.intensity, 0.0..=5.0).text("intensity"));
+        let mut colour = [light.colour.x, light.colour.y, light.colour.z];
+        ui.horizontal(|ui| {
+            ui.label("Colour");
+            if ui.color_edit_button_rgb(&mut colour).changed() {
+                light.colour.x = colour[0];
+                light.colour.y = colour[1];
+                light.colour.z = colour[2];
+            }
+        });
+    }
+
+    if let Some(camera) = &node.camera {
+        ui.label(format!(
+            "Camera, {:.0}° vertical field of view, {} to {}",
+            camera.fov_y.to_degrees(),
+            camera.near,
+            camera.far
+        ));
+    }
+}
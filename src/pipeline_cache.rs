@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::{
+    device::Device,
+    format::Format,
+    framebuffer::{RenderPassAbstract, Subpass},
+    pipeline::{
+        blend::{AttachmentBlend, BlendFactor},
+        depth_stencil::{Compare, DepthStencil},
+        vertex::OneVertexOneInstanceDefinition,
+        GraphicsPipeline, GraphicsPipelineAbstract,
+    },
+};
+
+use crate::{
+    debug_names,
+    device_features::OptionalFeatures,
+    display_settings,
+    error_utils::EngineError,
+    instancing::InstanceData,
+    material::{BlendMode, CullMode, RenderState, ShaderProgram},
+    mesh::Vertex,
+};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+        #version 450
+
+        layout(set = 0, binding = 0) uniform UboViewProjection {
+            mat4 projection;
+            mat4 view;
+        } uboViewProjection;
+
+        layout(location = 0) in vec3 position;
+        layout(location = 1) in vec3 normal;
+        layout(location = 2) in vec3 colour;
+        layout(location = 3) in vec2 uv;
+        // per instance
+        layout(location = 4) in mat4 model;
+        layout(location = 8) in vec4 tint;
+
+        layout(location = 0) out vec3 fragColour;
+        layout(location = 1) out vec3 fragPosition;
+        layout(location = 2) out vec3 fragNormal;
+        layout(location = 3) out vec2 fragUv;
+        // only read by the wireframe fallback
+        layout(location = 4) out vec3 fragBarycentric;
+
+        void main() {
+            vec4 worldPosition = model * vec4(position, 1.0);
+            gl_Position = uboViewProjection.projection * uboViewProjection.view * worldPosition;
+            // point lists are drawn 1 pixel wide
+            gl_PointSize = 1.0;
+            fragColour = colour * tint.rgb;
+            fragPosition = worldPosition.xyz;
+            // keeps normals perpendicular to the surface under non uniform scaling
+            fragNormal = mat3(transpose(inverse(model))) * normal;
+            fragUv = uv;
+            // meshes have no index buffer, so every three vertices make a triangle
+            fragBarycentric = vec3(0.0);
+            fragBarycentric[gl_VertexIndex % 3] = 1.0;
+        }"
+    }
+}
+
+mod lit_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        #define MAX_LIGHTS 16
+
+        struct Light {
+            vec4 position;    // w = 0: directional, xyz is the direction the light travels
+            vec4 colour;      // intensity in a
+            vec4 attenuation; // constant, linear, quadratic
+        };
+
+        layout(set = 0, binding = 1) uniform UboLights {
+            vec4 cameraPosition;
+            vec4 ambient;
+            uint lightCount;
+            Light lights[MAX_LIGHTS];
+        } uboLights;
+
+        layout(set = 0, binding = 2) uniform UboShadow {
+            mat4 lightViewProjection;
+            float depthBias;
+            float slopeBias;
+            int pcfRadius;
+            int casterIndex;
+        } uboShadow;
+
+        layout(set = 0, binding = 3) uniform sampler2D shadowMap;
+
+        layout(set = 1, binding = 0) uniform UboMaterial {
+            vec4 baseColour;
+            vec4 emissive;
+            float specularStrength;
+            float shininess;
+            uint doubleSided;
+        } uboMaterial;
+
+        layout(set = 1, binding = 1) uniform sampler2D baseColourTexture;
+
+        layout(location = 0) in vec3 fragColour;
+        layout(location = 1) in vec3 fragPosition;
+        layout(location = 2) in vec3 fragNormal;
+        layout(location = 3) in vec2 fragUv;
+
+        layout(location = 0) out vec4 f_color;
+
+        // set for colour attachments that store what they are given, UNORM formats the display
+        // reads as sRGB
+        layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+        vec3 encodeSrgb(vec3 linear) {
+            linear = clamp(linear, 0.0, 1.0);
+            vec3 low = linear * 12.92;
+            vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+            return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+        }
+
+        // 0 when the shadow casting light is fully blocked, 1 when it is not, in between on
+        // the edges thanks to the percentage closer filtering
+        float lightVisibility(vec3 normal, vec3 lightDirection) {
+            vec4 lightSpace = uboShadow.lightViewProjection * vec4(fragPosition, 1.0);
+            vec3 projected = lightSpace.xyz / lightSpace.w;
+            if (projected.z > 1.0) {
+                return 1.0;
+            }
+
+            vec2 uv = projected.xy * 0.5 + 0.5;
+            float bias = max(uboShadow.slopeBias * (1.0 - dot(normal, lightDirection)), uboShadow.depthBias);
+            vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0));
+
+            float visible = 0.0;
+            for (int x = -uboShadow.pcfRadius; x <= uboShadow.pcfRadius; x++) {
+                for (int y = -uboShadow.pcfRadius; y <= uboShadow.pcfRadius; y++) {
+                    float closest = texture(shadowMap, uv + vec2(x, y) * texelSize).r;
+                    visible += projected.z - bias > closest ? 0.0 : 1.0;
+                }
+            }
+
+            float kernelWidth = float(uboShadow.pcfRadius * 2 + 1);
+            return visible / (kernelWidth * kernelWidth);
+        }
+
+        // Blinn-Phong
+        void main() {
+            vec3 normal = normalize(fragNormal);
+            if (uboMaterial.doubleSided != 0u && !gl_FrontFacing) {
+                normal = -normal;
+            }
+            vec3 viewDirection = normalize(uboLights.cameraPosition.xyz - fragPosition);
+
+            vec3 diffuse = uboLights.ambient.rgb;
+            vec3 specular = vec3(0.0);
+
+            for (uint i = 0u; i < uboLights.lightCount; i++) {
+                Light light = uboLights.lights[i];
+
+                vec3 lightDirection;
+                float attenuation = 1.0;
+                if (light.position.w == 0.0) {
+                    lightDirection = normalize(-light.position.xyz);
+                } else {
+                    vec3 toLight = light.position.xyz - fragPosition;
+                    float distance = length(toLight);
+                    lightDirection = toLight / distance;
+                    attenuation = 1.0 / (light.attenuation.x
+                        + light.attenuation.y * distance
+                        + light.attenuation.z * distance * distance);
+                }
+
+                if (int(i) == uboShadow.casterIndex) {
+                    attenuation *= lightVisibility(normal, lightDirection);
+                }
+
+                vec3 radiance = light.colour.rgb * light.colour.a * attenuation;
+                float lambert = max(dot(normal, lightDirection), 0.0);
+                diffuse += radiance * lambert;
+
+                if (lambert > 0.0) {
+                    vec3 halfway = normalize(lightDirection + viewDirection);
+                    specular += radiance * uboMaterial.specularStrength
+                        * pow(max(dot(normal, halfway), 0.0), uboMaterial.shininess);
+                }
+            }
+
+            vec4 albedo = uboMaterial.baseColour * texture(baseColourTexture, fragUv);
+            vec3 colour = fragColour * albedo.rgb * diffuse + specular + uboMaterial.emissive.rgb;
+            f_color = vec4(colour, albedo.a);
+
+            if (ENCODE_SRGB) {
+                f_color.rgb = encodeSrgb(f_color.rgb);
+            }
+        }"
+    }
+}
+
+mod unlit_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        layout(set = 1, binding = 0) uniform UboMaterial {
+            vec4 baseColour;
+            vec4 emissive;
+            float specularStrength;
+            float shininess;
+            uint doubleSided;
+        } uboMaterial;
+
+        layout(set = 1, binding = 1) uniform sampler2D baseColourTexture;
+
+        layout(location = 0) in vec3 fragColour;
+        layout(location = 3) in vec2 fragUv;
+
+        layout(location = 0) out vec4 f_color;
+
+        // as in the lit shader
+        layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+        vec3 encodeSrgb(vec3 linear) {
+            linear = clamp(linear, 0.0, 1.0);
+            vec3 low = linear * 12.92;
+            vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+            return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+        }
+
+        void main() {
+            vec4 albedo = uboMaterial.baseColour * texture(baseColourTexture, fragUv);
+            f_color = vec4(fragColour * albedo.rgb + uboMaterial.emissive.rgb, albedo.a);
+
+            if (ENCODE_SRGB) {
+                f_color.rgb = encodeSrgb(f_color.rgb);
+            }
+        }"
+    }
+}
+
+// Draws the edges of the triangles in place of PolygonMode::Line on devices without
+// fill_mode_non_solid
+mod wireframe_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        layout(set = 1, binding = 0) uniform UboMaterial {
+            vec4 baseColour;
+            vec4 emissive;
+            float specularStrength;
+            float shininess;
+            uint doubleSided;
+        } uboMaterial;
+
+        layout(set = 1, binding = 1) uniform sampler2D baseColourTexture;
+
+        layout(location = 0) in vec3 fragColour;
+        layout(location = 3) in vec2 fragUv;
+        layout(location = 4) in vec3 fragBarycentric;
+
+        layout(location = 0) out vec4 f_color;
+
+        // as in the lit shader
+        layout(constant_id = 0) const bool ENCODE_SRGB = false;
+
+        vec3 encodeSrgb(vec3 linear) {
+            linear = clamp(linear, 0.0, 1.0);
+            vec3 low = linear * 12.92;
+            vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+            return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+        }
+
+        void main() {
+            // distance to the closest edge in pixels, whatever the size of the triangle on screen
+            vec3 pixels = fragBarycentric / fwidth(fragBarycentric);
+            if (min(min(pixels.x, pixels.y), pixels.z) > 1.0) {
+                discard;
+            }
+
+            vec4 albedo = uboMaterial.baseColour * texture(baseColourTexture, fragUv);
+            f_color = vec4(fragColour * albedo.rgb + uboMaterial.emissive.rgb, 1.0);
+
+            if (ENCODE_SRGB) {
+                f_color.rgb = encodeSrgb(f_color.rgb);
+            }
+        }"
+    }
+}
+
+/// How the main pass rasterizes triangles, a debug view switched at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolygonMode {
+    Fill,
+    // the edges of the triangles
+    Line,
+    // the vertices
+    Point,
+}
+
+impl PolygonMode {
+    pub fn next(self) -> Self {
+        match self {
+            PolygonMode::Fill => PolygonMode::Line,
+            PolygonMode::Line => PolygonMode::Point,
+            PolygonMode::Point => PolygonMode::Fill,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: ShaderProgram,
+    state: RenderState,
+    polygon_mode: PolygonMode,
+}
+
+/// Main pass pipelines, built the first time a shader program and render state combination is
+/// drawn and shared by every material using it afterwards.
+pub(crate) struct PipelineCache {
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+
+    vertex_shader: vertex_shader::Shader,
+    lit_fragment_shader: lit_fragment_shader::Shader,
+    unlit_fragment_shader: unlit_fragment_shader::Shader,
+    wireframe_fragment_shader: wireframe_fragment_shader::Shader,
+
+    polygon_mode: PolygonMode,
+    // fill_mode_non_solid is enabled, otherwise line and point modes are emulated
+    native_polygon_modes: bool,
+    // ENCODE_SRGB of the fragment shaders, for colour formats that do not encode themselves
+    encode_srgb: u32,
+
+    pipelines: HashMap<PipelineKey, Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+}
+
+impl PipelineCache {
+    /// Pipelines drawing to `render_pass`, whose colour attachment has `format`.
+    pub fn new(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        format: Format,
+    ) -> Result<Self, EngineError> {
+        Ok(PipelineCache {
+            vertex_shader: vertex_shader::Shader::load(device.clone())?,
+            lit_fragment_shader: lit_fragment_shader::Shader::load(device.clone())?,
+            unlit_fragment_shader: unlit_fragment_shader::Shader::load(device.clone())?,
+            wireframe_fragment_shader: wireframe_fragment_shader::Shader::load(device.clone())?,
+            polygon_mode: PolygonMode::Fill,
+            native_polygon_modes: OptionalFeatures::enabled_on(&device).fill_mode_non_solid,
+            encode_srgb: !display_settings::expects_linear_output(format) as u32,
+            device,
+            render_pass,
+            pipelines: HashMap::new(),
+        })
+    }
+
+    pub fn get(
+        &mut self,
+        shader: ShaderProgram,
+        state: RenderState,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
+        let key = PipelineKey {
+            shader,
+            state,
+            polygon_mode: self.polygon_mode,
+        };
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = self.create_pipeline(key)?;
+        debug_names::name_graphics_pipeline(&*pipeline, &format!("{:?}", key));
+        self.pipelines.insert(key, pipeline.clone());
+
+        Ok(pipeline)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn polygon_mode(&self) -> PolygonMode {
+        self.polygon_mode
+    }
+
+    /// Pipelines built for a mode are kept, switching back to it is free.
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.polygon_mode = polygon_mode;
+    }
+
+    /// Whether line and point modes are drawn with a wireframe shader and point lists because
+    /// the device lacks fill_mode_non_solid.
+    pub fn polygon_modes_emulated(&self) -> bool {
+        !self.native_polygon_modes
+    }
+
+    /// Builds the pipelines for another render pass from now on, when its samples changed. The
+    /// colour format must stay the same.
+    pub fn set_render_pass(&mut self, render_pass: Arc<dyn RenderPassAbstract + Send + Sync>) {
+        self.render_pass = render_pass;
+        self.pipelines.clear();
+    }
+
+    /// Emulates line and point modes even when the device has fill_mode_non_solid, to check the
+    /// fallback. Rebuilds the pipelines.
+    pub fn set_polygon_modes_emulated(&mut self, emulated: bool) {
+        self.native_polygon_modes =
+            !emulated && OptionalFeatures::enabled_on(&self.device).fill_mode_non_solid;
+        self.pipelines.clear();
+    }
+
+    /// The program whose set 0 layout the pipelines of `shader` have in the current mode, the
+    /// wireframe shader only takes the camera like the unlit one.
+    pub fn frame_set_program(&self, shader: ShaderProgram) -> ShaderProgram {
+        if self.wireframe_fallback(self.polygon_mode) {
+            ShaderProgram::Unlit
+        } else {
+            shader
+        }
+    }
+
+    fn wireframe_fallback(&self, polygon_mode: PolygonMode) -> bool {
+        polygon_mode == PolygonMode::Line && !self.native_polygon_modes
+    }
+
+    fn create_pipeline(
+        &self,
+        key: PipelineKey,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
+        let state = key.state;
+
+        let blend = match state.blend {
+            BlendMode::Opaque => AttachmentBlend::pass_through(),
+            BlendMode::AlphaBlend => AttachmentBlend {
+                alpha_source: BlendFactor::One,
+                alpha_destination: BlendFactor::Zero,
+                ..AttachmentBlend::alpha_blending()
+            },
+            BlendMode::Additive => AttachmentBlend {
+                color_source: BlendFactor::One,
+                color_destination: BlendFactor::One,
+                alpha_source: BlendFactor::Zero,
+                alpha_destination: BlendFactor::One,
+                ..AttachmentBlend::alpha_blending()
+            },
+        };
+
+        let depth_stencil = DepthStencil {
+            depth_compare: if state.depth_test {
+                Compare::Less
+            } else {
+                Compare::Always
+            },
+            depth_write: state.depth_write,
+            ..DepthStencil::simple_depth_test()
+        };
+
+        let builder = GraphicsPipeline::start()
+            .vertex_input(OneVertexOneInstanceDefinition::<Vertex, InstanceData>::new())
+            .vertex_shader(self.vertex_shader.main_entry_point(), ())
+            .viewports_dynamic_scissors_irrelevant(1)
+            // rasterizerCreateInfo.frontFace = VK_FRONT_FACE_CLOCKWISE
+            .front_face_clockwise()
+            .depth_stencil(depth_stencil)
+            .blend_collective(blend)
+            .sample_shading_disabled()
+            .render_pass(Subpass::from(self.render_pass.clone(), 0).unwrap());
+
+        let builder = match state.effective_cull_mode() {
+            CullMode::None => builder.cull_mode_disabled(),
+            CullMode::Front => builder.cull_mode_front(),
+            CullMode::Back => builder.cull_mode_back(),
+        };
+
+        // without fill_mode_non_solid, points are drawn as a point list of the same vertices
+        // and lines by the wireframe shader
+        let builder = match (key.polygon_mode, self.native_polygon_modes) {
+            (PolygonMode::Fill, _) => builder.triangle_list().polygon_mode_fill(),
+            (PolygonMode::Line, true) => builder.triangle_list().polygon_mode_line(),
+            (PolygonMode::Point, true) => builder.triangle_list().polygon_mode_point(),
+            (PolygonMode::Line, false) => builder.triangle_list().polygon_mode_fill(),
+            (PolygonMode::Point, false) => builder.point_list().polygon_mode_fill(),
+        };
+
+        if self.wireframe_fallback(key.polygon_mode) {
+            let pipeline = builder
+                .fragment_shader(
+                    self.wireframe_fragment_shader.main_entry_point(),
+                    wireframe_fragment_shader::SpecializationConstants {
+                        ENCODE_SRGB: self.encode_srgb,
+                    },
+                )
+                .build(self.device.clone())?;
+
+            return Ok(Arc::new(pipeline));
+        }
+
+        let pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync> = match key.shader {
+            ShaderProgram::Lit => Arc::new(
+                builder
+                    .fragment_shader(
+                        self.lit_fragment_shader.main_entry_point(),
+                        lit_fragment_shader::SpecializationConstants {
+                            ENCODE_SRGB: self.encode_srgb,
+                        },
+                    )
+                    .build(self.device.clone())?,
+            ),
+            ShaderProgram::Unlit => Arc::new(
+                builder
+                    .fragment_shader(
+                        self.unlit_fragment_shader.main_entry_point(),
+                        unlit_fragment_shader::SpecializationConstants {
+                            ENCODE_SRGB: self.encode_srgb,
+                        },
+                    )
+                    .build(self.device.clone())?,
+            ),
+        };
+
+        Ok(pipeline)
+    }
+}
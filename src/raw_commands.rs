@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::BufferAccess,
+    command_buffer::{
+        pool::standard::{StandardCommandPoolAlloc, StandardCommandPoolBuilder},
+        sys::{Flags, Kind, UnsafeCommandBuffer, UnsafeCommandBufferBuilder},
+        CommandBuffer, CommandBufferExecError,
+    },
+    device::{Device, DeviceOwned, Queue},
+    image::{ImageAccess, ImageLayout},
+    sync::{AccessCheckError, AccessFlagBits, GpuFuture, PipelineStages},
+};
+
+use crate::error_utils::EngineError;
+
+/// Command buffer holding commands that `AutoCommandBufferBuilder` does not expose yet, like
+/// timestamp writes. It is submitted between the regular command buffers of a frame with
+/// `then_execute`, so it must not touch any buffer or image: vulkano would not know to
+/// synchronize them.
+pub struct RawCommandBuffer {
+    inner: UnsafeCommandBuffer<StandardCommandPoolAlloc>,
+}
+
+impl RawCommandBuffer {
+    /// Records a one time submit command buffer for the family of `queue`.
+    ///
+    /// # Safety
+    ///
+    /// The commands recorded by `record` must not use buffers or images, and must be valid
+    /// outside of a render pass.
+    pub unsafe fn record<F>(
+        device: &Arc<Device>,
+        queue: &Queue,
+        record: F,
+    ) -> Result<Self, EngineError>
+    where
+        F: FnOnce(&mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>),
+    {
+        let pool = Device::standard_command_pool(device, queue.family());
+        let mut builder =
+            UnsafeCommandBufferBuilder::new(&pool, Kind::primary(), Flags::OneTimeSubmit)?;
+
+        record(&mut builder);
+
+        Ok(RawCommandBuffer {
+            inner: builder.build()?,
+        })
+    }
+}
+
+unsafe impl DeviceOwned for RawCommandBuffer {
+    fn device(&self) -> &Arc<Device> {
+        self.inner.device()
+    }
+}
+
+// With no resources there is nothing to lock, and every access check is left to the futures
+// submitted before this command buffer.
+unsafe impl CommandBuffer for RawCommandBuffer {
+    type PoolAlloc = StandardCommandPoolAlloc;
+
+    fn inner(&self) -> &UnsafeCommandBuffer<StandardCommandPoolAlloc> {
+        &self.inner
+    }
+
+    fn lock_submit(
+        &self,
+        _future: &dyn GpuFuture,
+        _queue: &Queue,
+    ) -> Result<(), CommandBufferExecError> {
+        Ok(())
+    }
+
+    unsafe fn unlock(&self) {}
+
+    fn check_buffer_access(
+        &self,
+        _buffer: &dyn BufferAccess,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+
+    fn check_image_access(
+        &self,
+        _image: &dyn ImageAccess,
+        _layout: ImageLayout,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+}
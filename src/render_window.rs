@@ -0,0 +1,609 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use vulkano::{
+    command_buffer::DynamicState,
+    device::{Device, Queue},
+    format::Format,
+    framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass},
+    image::{AttachmentImage, ImageUsage, SwapchainImage},
+    pipeline::viewport::Viewport,
+    swapchain::{
+        Capabilities, ColorSpace, PresentMode, Surface, SurfaceTransform, Swapchain,
+        SwapchainCreationError,
+    },
+    sync::SharingMode,
+};
+use winit::{
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window, WindowId},
+};
+
+use crate::{
+    camera::Camera,
+    debug_names,
+    display_settings::{self, DisplaySettings, VsyncMode, WindowMode},
+    error_utils::EngineError,
+    frame_limiter::FrameLimiter,
+    frame_stats::{FrameStats, FrameTimings, DEFAULT_HISTORY},
+    gpu_profiler::GpuRegionTiming,
+    overlay::{OverlayFrame, OverlayRenderer},
+    vulkan_renderer::DEPTH_FORMAT,
+};
+
+// updating the title every frame is expensive on some window managers, and unreadable anyway
+const TITLE_STATS_INTERVAL: Duration = Duration::from_millis(500);
+
+type SwapchainAndImages = (Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>);
+
+/// What the renderer keeps for each of its windows: the surface, its swapchain and
+/// framebuffers, and the camera and settings of what is shown in it. The device, pipelines and
+/// resources are shared by all of them.
+pub struct RenderWindow {
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    presentation_queue: Arc<Queue>,
+
+    title: String,
+    display_settings: DisplaySettings,
+    // vulkano swapchains do not tell theirs
+    colour_space: ColorSpace,
+    swapchain: Arc<Swapchain<Window>>,
+    swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+    framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    overlay_framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    dynamic_state: DynamicState,
+    recreate_swapchain: bool,
+
+    camera: Camera,
+    // drawn over the next frame only
+    overlay: Option<OverlayFrame>,
+
+    frame_stats: FrameStats,
+    // only waits when the present mode does not pace frames
+    frame_limiter: FrameLimiter,
+    last_frame_start: Option<Instant>,
+    stats_in_title: bool,
+    // None refreshes the title with the next frame
+    last_title_update: Option<Instant>,
+
+    // must live to keep working
+    surface: Arc<Surface<Window>>,
+}
+
+impl RenderWindow {
+    /// Creates the swapchain of `surface`. The framebuffers come once the render pass exists,
+    /// with `build_framebuffers`.
+    pub(crate) fn new(
+        device: Arc<Device>,
+        graphics_queue: Arc<Queue>,
+        presentation_queue: Arc<Queue>,
+        surface: Arc<Surface<Window>>,
+        settings: DisplaySettings,
+        (surface_format, colour_space): (Format, ColorSpace),
+    ) -> Result<Self, EngineError> {
+        let (swapchain, images) = Self::create_swapchain(
+            &surface,
+            &device,
+            &graphics_queue,
+            &presentation_queue,
+            (surface_format, colour_space),
+            &settings,
+        )?;
+
+        Ok(RenderWindow {
+            device,
+            graphics_queue,
+            presentation_queue,
+            title: String::new(),
+            frame_limiter: FrameLimiter::new(settings.frame_limit),
+            display_settings: settings,
+            colour_space,
+            swapchain,
+            swapchain_images: images,
+            framebuffers: Vec::new(),
+            overlay_framebuffers: Vec::new(),
+            dynamic_state: DynamicState::none(),
+            recreate_swapchain: false,
+            camera: Camera::default(),
+            overlay: None,
+            frame_stats: FrameStats::new(DEFAULT_HISTORY),
+            last_frame_start: None,
+            stats_in_title: false,
+            last_title_update: None,
+            surface,
+        })
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.surface.window().id()
+    }
+
+    pub fn window(&self) -> &Window {
+        self.surface.window()
+    }
+
+    pub fn resized(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = *camera;
+    }
+
+    pub(crate) fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.surface.window().set_title(title);
+    }
+
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.swapchain.present_mode()
+    }
+
+    pub fn surface_format(&self) -> Format {
+        self.swapchain.format()
+    }
+
+    pub fn vsync_mode(&self) -> VsyncMode {
+        self.display_settings.vsync
+    }
+
+    /// Switches the present mode from the next frame, falling back along the mode's list when
+    /// the surface does not support it.
+    pub fn set_vsync_mode(&mut self, vsync: VsyncMode) -> Result<(), EngineError> {
+        self.check_swapchain_rebuildable("present mode")?;
+
+        self.display_settings.vsync = vsync;
+        self.recreate_swapchain = true;
+
+        Ok(())
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        self.display_settings.window_mode
+    }
+
+    /// Moves the window in or out of fullscreen on the chosen monitor, the swapchain follows on
+    /// the next frame.
+    pub fn set_window_mode(&mut self, mode: WindowMode) -> Result<(), EngineError> {
+        if mode.fullscreen_exclusive() != self.swapchain.fullscreen_exclusive() {
+            self.check_swapchain_rebuildable("window mode")?;
+        }
+
+        let fullscreen = match mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(self.fullscreen_monitor())),
+            WindowMode::Exclusive => Some(Fullscreen::Exclusive(self.fullscreen_video_mode()?)),
+        };
+        self.surface.window().set_fullscreen(fullscreen);
+        self.display_settings.window_mode = mode;
+        self.recreate_swapchain = true;
+
+        Ok(())
+    }
+
+    pub fn monitors(&self) -> Vec<MonitorHandle> {
+        self.surface.window().available_monitors().collect()
+    }
+
+    pub fn monitor(&self) -> Option<usize> {
+        self.display_settings.monitor
+    }
+
+    /// Picks the monitor of the fullscreen modes by its index in `monitors`, None uses the one
+    /// the window is on. A fullscreen window moves over right away.
+    pub fn set_monitor(&mut self, index: Option<usize>) -> Result<(), EngineError> {
+        self.display_settings.monitor = index;
+
+        match self.display_settings.window_mode {
+            WindowMode::Windowed => Ok(()),
+            mode => self.set_window_mode(mode),
+        }
+    }
+
+    pub fn frame_limit(&self) -> Option<f32> {
+        self.frame_limiter.limit()
+    }
+
+    /// Caps the frames per second of the present modes the display does not pace, None
+    /// removes the cap.
+    pub fn set_frame_limit(&mut self, fps: Option<f32>) {
+        self.frame_limiter.set_limit(fps);
+        self.display_settings.frame_limit = self.frame_limiter.limit();
+    }
+
+    pub fn stats_in_title(&self) -> bool {
+        self.stats_in_title
+    }
+
+    /// Shows FPS and frame time in the window title, refreshed a couple of times per second.
+    pub fn set_stats_in_title(&mut self, show: bool) {
+        self.stats_in_title = show;
+        self.last_title_update = None;
+
+        if !show {
+            self.surface.window().set_title(&self.title);
+        }
+    }
+
+    fn fullscreen_monitor(&self) -> MonitorHandle {
+        let window = self.surface.window();
+
+        self.display_settings
+            .monitor
+            .and_then(|index| window.available_monitors().nth(index))
+            .unwrap_or_else(|| window.current_monitor())
+    }
+
+    // the largest, fastest and deepest mode matching what the settings ask for
+    fn fullscreen_video_mode(&self) -> Result<VideoMode, EngineError> {
+        let monitor = self.fullscreen_monitor();
+        let request = self.display_settings.video_mode;
+
+        monitor
+            .video_modes()
+            .filter(|mode| {
+                let size: [u32; 2] = mode.size().into();
+                (request.size.is_none() || request.size == Some(size))
+                    && (request.refresh_rate.is_none()
+                        || request.refresh_rate == Some(mode.refresh_rate()))
+            })
+            .max_by_key(|mode| {
+                let size = mode.size();
+                (
+                    size.width * size.height,
+                    mode.refresh_rate(),
+                    mode.bit_depth(),
+                )
+            })
+            .ok_or_else(|| {
+                EngineError::VulkanValidationError(format!(
+                    "{:?} has no video mode matching {:?}",
+                    monitor.name(),
+                    request
+                ))
+            })
+    }
+
+    // see recreate_swapchain
+    fn check_swapchain_rebuildable(&self, what: &str) -> Result<(), EngineError> {
+        if self.colour_space != ColorSpace::SrgbNonLinear {
+            return Err(EngineError::VulkanValidationError(format!(
+                "The {} can not be changed with the {:?} colour space",
+                what, self.colour_space
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn swapchain(&self) -> &Arc<Swapchain<Window>> {
+        &self.swapchain
+    }
+
+    pub(crate) fn swapchain_images(&self) -> &[Arc<SwapchainImage<Window>>] {
+        &self.swapchain_images
+    }
+
+    pub(crate) fn framebuffer(
+        &self,
+        image_index: usize,
+    ) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        self.framebuffers[image_index].clone()
+    }
+
+    pub(crate) fn overlay_framebuffer(
+        &self,
+        image_index: usize,
+    ) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        self.overlay_framebuffers[image_index].clone()
+    }
+
+    pub(crate) fn dynamic_state(&self) -> &DynamicState {
+        &self.dynamic_state
+    }
+
+    pub(crate) fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub(crate) fn set_overlay(&mut self, overlay: Option<OverlayFrame>) {
+        self.overlay = overlay;
+    }
+
+    pub(crate) fn take_overlay(&mut self) -> Option<OverlayFrame> {
+        self.overlay.take()
+    }
+
+    pub(crate) fn supports_capture(&self) -> bool {
+        self.surface
+            .capabilities(self.device.physical_device())
+            .map(|capabilities| capabilities.supported_usage_flags.transfer_source)
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn needs_recreation(&self) -> bool {
+        self.recreate_swapchain
+    }
+
+    pub(crate) fn mark_out_of_date(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    /// Waits for the frame limit, and gives how long it has been since the previous frame
+    /// started; None for the first frame, which has nothing to be compared with.
+    pub(crate) fn begin_frame(&mut self) -> Option<Duration> {
+        if !display_settings::is_display_paced(self.swapchain.present_mode()) {
+            self.frame_limiter.wait();
+        }
+
+        let frame_start = Instant::now();
+        let frame_time = self
+            .last_frame_start
+            .map(|last| frame_start.duration_since(last));
+        self.last_frame_start = Some(frame_start);
+
+        frame_time
+    }
+
+    pub(crate) fn end_frame(
+        &mut self,
+        timings: Option<FrameTimings>,
+        gpu_timings: &[GpuRegionTiming],
+    ) {
+        if let Some(timings) = timings {
+            self.frame_stats.record(timings);
+        }
+
+        let title_outdated = match self.last_title_update {
+            Some(last) => last.elapsed() >= TITLE_STATS_INTERVAL,
+            None => true,
+        };
+        if self.stats_in_title && title_outdated {
+            self.update_title_stats(gpu_timings);
+        }
+    }
+
+    fn update_title_stats(&mut self, gpu_timings: &[GpuRegionTiming]) {
+        let summary = self.frame_stats.summary();
+
+        let mut title = format!(
+            "{} - {:.0} FPS ({:.2} ms, 1% low {:.0} FPS)",
+            self.title, summary.average_fps, summary.frame.average_ms, summary.one_percent_low_fps
+        );
+        for timing in gpu_timings {
+            title.push_str(&format!(
+                ", GPU {} {:.2} ms",
+                timing.name, timing.average_ms
+            ));
+        }
+
+        self.surface.window().set_title(&title);
+        self.last_title_update = Some(Instant::now());
+    }
+
+    /// Rebuilds the swapchain for the current size and settings of the window, then its
+    /// framebuffers. Returns false when the window can not have a swapchain right now.
+    pub(crate) fn recreate_swapchain(
+        &mut self,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        overlay_renderer: &OverlayRenderer,
+    ) -> Result<bool, EngineError> {
+        let dimensions: [u32; 2] = self.surface.window().inner_size().into();
+
+        let capabilities = self.surface.capabilities(self.device.physical_device())?;
+        let present_mode = self
+            .display_settings
+            .vsync
+            .choose_present_mode(capabilities.present_modes);
+        let fullscreen_exclusive = self.display_settings.window_mode.fullscreen_exclusive();
+        let result = if present_mode == self.swapchain.present_mode()
+            && fullscreen_exclusive == self.swapchain.fullscreen_exclusive()
+        {
+            self.swapchain.recreate_with_dimensions(dimensions)
+        } else {
+            println!("Present mode: {:?}", present_mode);
+            // the only way vulkano lets us change the present mode or fullscreen exclusivity of a
+            // swapchain; it always asks for the sRGB colour space, so set_vsync_mode and
+            // set_window_mode refuse other colour spaces
+            Swapchain::with_old_swapchain(
+                self.device.clone(),
+                self.surface.clone(),
+                self.swapchain.num_images(),
+                self.swapchain.format(),
+                dimensions,
+                1,
+                Self::swapchain_usage(&capabilities),
+                Self::sharing_mode(&self.graphics_queue, &self.presentation_queue),
+                self.swapchain.transform(),
+                self.swapchain.composite_alpha(),
+                present_mode,
+                fullscreen_exclusive,
+                self.swapchain.clipped(),
+                ColorSpace::SrgbNonLinear,
+                self.swapchain.clone(),
+            )
+        };
+
+        let (swapchain, images) = match result {
+            Ok(result) => result,
+            // happens while the user is resizing the window, we just try again next frame
+            Err(SwapchainCreationError::UnsupportedDimensions) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        self.swapchain = swapchain;
+        self.swapchain_images = images;
+        self.build_framebuffers(render_pass, overlay_renderer)?;
+        self.recreate_swapchain = false;
+
+        Ok(true)
+    }
+
+    pub(crate) fn build_framebuffers(
+        &mut self,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        overlay_renderer: &OverlayRenderer,
+    ) -> Result<(), EngineError> {
+        let dimensions = self.swapchain_images[0].dimensions();
+        let format = self.swapchain.format();
+        let samples = Subpass::from(render_pass.clone(), 0)
+            .and_then(|subpass| subpass.num_samples())
+            .unwrap_or(1);
+        // frames are rendered one after the other on the same queue, so they share the depth buffer
+        // and the multisampled colour
+        let depth_buffer = AttachmentImage::transient_multisampled(
+            self.device.clone(),
+            dimensions,
+            samples,
+            DEPTH_FORMAT,
+        )?;
+        let multisampled_colour = if samples > 1 {
+            Some(AttachmentImage::transient_multisampled(
+                self.device.clone(),
+                dimensions,
+                samples,
+                format,
+            )?)
+        } else {
+            None
+        };
+        debug_names::name_image(&*depth_buffer, &format!("{} depth", self.title));
+        if let Some(multisampled_colour) = &multisampled_colour {
+            let name = format!("{} multisampled colour", self.title);
+            debug_names::name_image(&**multisampled_colour, &name);
+        }
+        for (index, image) in self.swapchain_images.iter().enumerate() {
+            let name = format!("{} swapchain image {}", self.title, index);
+            debug_names::name_image(&**image, &name);
+        }
+
+        let viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+            depth_range: 0.0..1.0,
+        };
+        self.dynamic_state.viewports = Some(vec![viewport]);
+
+        self.framebuffers = self
+            .swapchain_images
+            .iter()
+            .map(|image| match &multisampled_colour {
+                Some(multisampled_colour) => Arc::new(
+                    Framebuffer::start(render_pass.clone())
+                        .add(multisampled_colour.clone())
+                        .unwrap()
+                        .add(image.clone())
+                        .unwrap()
+                        .add(depth_buffer.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                )
+                    as Arc<dyn FramebufferAbstract + Send + Sync>,
+                None => Arc::new(
+                    Framebuffer::start(render_pass.clone())
+                        .add(image.clone())
+                        .unwrap()
+                        .add(depth_buffer.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                ),
+            })
+            .collect();
+        self.overlay_framebuffers = self
+            .swapchain_images
+            .iter()
+            .map(|image| overlay_renderer.framebuffer(image.clone()))
+            .collect();
+
+        Ok(())
+    }
+
+    fn create_swapchain(
+        surface: &Arc<Surface<Window>>,
+        device: &Arc<Device>,
+        graphics_queue: &Arc<Queue>,
+        presentation_queue: &Arc<Queue>,
+        (surface_format, color_space): (Format, ColorSpace),
+        settings: &DisplaySettings,
+    ) -> Result<SwapchainAndImages, EngineError> {
+        let (swapchain, images) = {
+            let surface_capabilities = surface.capabilities(device.physical_device())?;
+
+            let presentation_mode = settings
+                .vsync
+                .choose_present_mode(surface_capabilities.present_modes);
+            println!("Present mode: {:?}", presentation_mode);
+
+            let mut image_count: u32 = surface_capabilities.min_image_count + 1;
+            if let Some(max_image_count) = surface_capabilities.max_image_count {
+                image_count = std::cmp::min(image_count, max_image_count)
+            }
+
+            // Opaque (VK_COMPOSITE_ALPHA_OPAQUE_BIT_KHR) is the first element, if available, in the iter() implementation
+            let alpha = surface_capabilities
+                .supported_composite_alpha
+                .iter()
+                .next()
+                .unwrap();
+
+            // VkExtent2D is created inside swapchain creation and uses dimensions values to be built
+            let mut dimensions: [u32; 2] = surface.window().inner_size().into();
+            dimensions[0] = std::cmp::max(
+                surface_capabilities.min_image_extent[0],
+                std::cmp::min(surface_capabilities.max_image_extent[0], dimensions[0]),
+            );
+            dimensions[1] = std::cmp::max(
+                surface_capabilities.min_image_extent[1],
+                std::cmp::min(surface_capabilities.max_image_extent[1], dimensions[1]),
+            );
+
+            let sharing_mode = Self::sharing_mode(graphics_queue, presentation_queue);
+            let image_usage = Self::swapchain_usage(&surface_capabilities);
+
+            Swapchain::new(
+                device.clone(),
+                surface.clone(),
+                image_count,
+                surface_format,
+                dimensions,
+                1,
+                image_usage,
+                sharing_mode,
+                SurfaceTransform::Identity,
+                alpha,
+                presentation_mode,
+                settings.window_mode.fullscreen_exclusive(),
+                true,
+                color_space,
+            )?
+        };
+
+        Ok((swapchain, images))
+    }
+
+    fn sharing_mode(graphics_queue: &Arc<Queue>, presentation_queue: &Arc<Queue>) -> SharingMode {
+        let graphics_family_id = graphics_queue.family().id();
+        let presentation_family_id = presentation_queue.family().id();
+
+        if graphics_family_id == presentation_family_id {
+            SharingMode::Exclusive
+        } else {
+            SharingMode::Concurrent(vec![graphics_family_id, presentation_family_id])
+        }
+    }
+
+    // transfer source lets us copy the presented image back to the host (screenshots)
+    fn swapchain_usage(capabilities: &Capabilities) -> ImageUsage {
+        ImageUsage {
+            color_attachment: true,
+            transfer_source: capabilities.supported_usage_flags.transfer_source,
+            ..ImageUsage::none()
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::device::Queue;
+
+use crate::{
+    error_utils::EngineError,
+    gpu_memory::{MemoryCategory, MemoryUsage},
+    mesh::{Mesh, MeshSource, Vertex},
+    texture::Texture,
+};
+
+/// Shared reference to a resource owned by `Resources`. Cloning is cheap, and the resource stays
+/// resident while at least one handle to it exists.
+pub struct Handle<T> {
+    resource: Arc<T>,
+}
+
+pub type MeshHandle = Handle<Mesh>;
+pub type TextureHandle = Handle<Texture>;
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            resource: self.resource.clone(),
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+// two handles are equal when they point to the same resource
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.resource, &other.resource)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.resource.fmt(f)
+    }
+}
+
+// What the residency report shows of a resource
+trait Resident {
+    const KIND: &'static str;
+
+    fn label(&self) -> String;
+    fn size_bytes(&self) -> u64;
+}
+
+impl Resident for Mesh {
+    const KIND: &'static str = "mesh";
+
+    fn label(&self) -> String {
+        match self.source() {
+            MeshSource::File(path) => path.display().to_string(),
+            _ if !self.name().is_empty() => self.name().to_string(),
+            source => format!("{:?}", source),
+        }
+    }
+
+    fn size_bytes(&self) -> u64 {
+        (self.vertex_count() * std::mem::size_of::<Vertex>()) as u64
+    }
+}
+
+impl Resident for Texture {
+    const KIND: &'static str = "texture";
+
+    fn label(&self) -> String {
+        match self.path() {
+            Some(path) => path.display().to_string(),
+            None => "generated".to_string(),
+        }
+    }
+
+    fn size_bytes(&self) -> u64 {
+        let [width, height] = self.dimensions();
+        // every texture is stored as RGBA8
+        width as u64 * height as u64 * 4
+    }
+}
+
+struct Entry<K, T> {
+    // what the resource was loaded from, None for resources created in code
+    key: Option<K>,
+    resource: Arc<T>,
+    // set once no handle is left, the frame the resource stopped being referenced
+    retired_at: Option<u64>,
+}
+
+struct ResourceTable<K, T> {
+    entries: Vec<Entry<K, T>>,
+}
+
+impl<K: PartialEq, T: Resident> ResourceTable<K, T> {
+    fn new() -> Self {
+        ResourceTable {
+            entries: Vec::new(),
+        }
+    }
+
+    // a retired resource asked for again is revived instead of loaded a second time
+    fn find(&mut self, key: &K) -> Option<Handle<T>> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.key.as_ref() == Some(key))?;
+        entry.retired_at = None;
+
+        Some(Handle {
+            resource: entry.resource.clone(),
+        })
+    }
+
+    fn insert(&mut self, key: Option<K>, resource: Arc<T>) -> Handle<T> {
+        self.entries.push(Entry {
+            key,
+            resource: resource.clone(),
+            retired_at: None,
+        });
+
+        Handle { resource }
+    }
+
+    // Resources without handles are retired, and freed once the frames that may still use them
+    // are done.
+    fn collect(&mut self, frame: u64, frames_in_flight: u64) {
+        for entry in &mut self.entries {
+            let referenced = Arc::strong_count(&entry.resource) > 1;
+            if referenced {
+                entry.retired_at = None;
+            } else if entry.retired_at.is_none() {
+                entry.retired_at = Some(frame);
+            }
+        }
+
+        self.entries.retain(|entry| match entry.retired_at {
+            Some(retired_at) => retired_at + frames_in_flight > frame,
+            None => true,
+        });
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| entry.resource.size_bytes())
+            .sum()
+    }
+
+    fn report(&self, report: &mut ResidencyReport) {
+        for entry in &self.entries {
+            report.resources.push(ResidentResource {
+                kind: T::KIND,
+                label: entry.resource.label(),
+                bytes: entry.resource.size_bytes(),
+                // the table's own reference is not counted
+                handles: Arc::strong_count(&entry.resource) - 1,
+                pending_release: entry.retired_at.is_some(),
+            });
+        }
+    }
+}
+
+/// Owns the meshes and textures of the renderer.
+///
+/// Loading the same file or primitive twice gives the same resource. A resource nobody holds a
+/// handle to any more is freed once the frames in flight when it was dropped are done, since
+/// their command buffers may still read it.
+pub struct Resources {
+    queue: Arc<Queue>,
+    meshes: ResourceTable<MeshSource, Mesh>,
+    textures: ResourceTable<PathBuf, Texture>,
+    frame: u64,
+    frames_in_flight: u64,
+}
+
+impl Resources {
+    pub fn new(queue: Arc<Queue>, frames_in_flight: u64) -> Self {
+        Resources {
+            queue,
+            meshes: ResourceTable::new(),
+            textures: ResourceTable::new(),
+            frame: 0,
+            frames_in_flight,
+        }
+    }
+
+    /// Creates a mesh from a primitive or a file. A source already loaded gives the existing
+    /// mesh, with the name it was first loaded with.
+    pub fn load_mesh(&mut self, name: &str, source: MeshSource) -> Result<MeshHandle, EngineError> {
+        let key = match &source {
+            MeshSource::File(path) => MeshSource::File(canonical_path(path)),
+            // vertices given in code are never shared
+            MeshSource::Vertices => {
+                return Err(EngineError::MeshLoadError(
+                    "Meshes created from vertices must be added with create_mesh".to_string(),
+                ))
+            }
+            source => source.clone(),
+        };
+
+        if let Some(mesh) = self.meshes.find(&key) {
+            return Ok(mesh);
+        }
+
+        let mesh = Mesh::from_source(self.queue.device().clone(), name, source)?;
+
+        Ok(self.meshes.insert(Some(key), mesh))
+    }
+
+    pub fn create_mesh(&mut self, vertices: &[Vertex]) -> Result<MeshHandle, EngineError> {
+        let mesh = Mesh::new(self.queue.device().clone(), vertices)?;
+
+        Ok(self.meshes.insert(None, mesh))
+    }
+
+    /// Loads an image file as a texture, or gives the one already loaded from that file.
+    pub fn load_texture(&mut self, path: &Path) -> Result<TextureHandle, EngineError> {
+        let key = canonical_path(path);
+        if let Some(texture) = self.textures.find(&key) {
+            return Ok(texture);
+        }
+
+        let texture = Texture::load(self.queue.clone(), path)?;
+
+        Ok(self.textures.insert(Some(key), texture))
+    }
+
+    /// Called once a frame has been submitted, frees what is no longer used.
+    pub(crate) fn end_frame(&mut self) {
+        self.meshes.collect(self.frame, self.frames_in_flight);
+        self.textures.collect(self.frame, self.frames_in_flight);
+        self.frame += 1;
+    }
+
+    pub(crate) fn set_frames_in_flight(&mut self, frames_in_flight: u64) {
+        self.frames_in_flight = frames_in_flight;
+    }
+
+    /// What is loaded right now, including what is waiting for frames in flight to be freed.
+    pub fn residency(&self) -> ResidencyReport {
+        let mut report = ResidencyReport::default();
+        self.meshes.report(&mut report);
+        self.textures.report(&mut report);
+
+        report
+    }
+
+    // resources waiting for frames in flight still hold their memory, so they are counted
+    pub(crate) fn memory_usage(&self, usage: &mut MemoryUsage) {
+        usage.add(MemoryCategory::VertexBuffers, self.meshes.total_bytes());
+        usage.add(MemoryCategory::Textures, self.textures.total_bytes());
+    }
+}
+
+// the same file reached through different paths is loaded once
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Debug, Clone)]
+pub struct ResidentResource {
+    pub kind: &'static str,
+    pub label: String,
+    pub bytes: u64,
+    pub handles: usize,
+    // no handle is left, freed once the frames in flight are done
+    pub pending_release: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResidencyReport {
+    pub resources: Vec<ResidentResource>,
+}
+
+impl ResidencyReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.resources.iter().map(|resource| resource.bytes).sum()
+    }
+
+    pub fn count(&self, kind: &str) -> usize {
+        self.resources
+            .iter()
+            .filter(|resource| resource.kind == kind)
+            .count()
+    }
+}
+
+impl fmt::Display for ResidencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} meshes and {} textures, {:.1} KiB",
+            self.count(Mesh::KIND),
+            self.count(Texture::KIND),
+            self.total_bytes() as f64 / 1024.0
+        )?;
+        for resource in &self.resources {
+            write!(
+                f,
+                "  {:<8} {:<32} {:>10.1} KiB, {} handles",
+                resource.kind,
+                resource.label,
+                resource.bytes as f64 / 1024.0,
+                resource.handles
+            )?;
+            if resource.pending_release {
+                write!(f, ", waiting for frames in flight")?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blob(u64);
+
+    impl Resident for Blob {
+        const KIND: &'static str = "blob";
+
+        fn label(&self) -> String {
+            "blob".to_string()
+        }
+
+        fn size_bytes(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn dropped_resources_wait_for_the_frames_of_every_window() {
+        // two windows with three swapchain images each
+        let frames_in_flight = 6;
+        let mut table = ResourceTable::new();
+        let handle = table.insert(None::<PathBuf>, Arc::new(Blob(16)));
+        table.collect(0, frames_in_flight);
+        assert_eq!(table.total_bytes(), 16);
+
+        drop(handle);
+        for frame in 1..=frames_in_flight {
+            table.collect(frame, frames_in_flight);
+            assert_eq!(table.total_bytes(), 16, "freed after frame {}", frame);
+        }
+        table.collect(frames_in_flight + 1, frames_in_flight);
+        assert_eq!(table.total_bytes(), 0);
+    }
+
+    #[test]
+    fn retired_resources_are_revived_by_key() {
+        let mut table = ResourceTable::new();
+        drop(table.insert(Some(PathBuf::from("a.png")), Arc::new(Blob(4))));
+        table.collect(0, 2);
+
+        let revived = table.find(&PathBuf::from("a.png")).unwrap();
+        table.collect(5, 2);
+        assert_eq!(table.total_bytes(), 4);
+        assert!(table.find(&PathBuf::from("b.png")).is_none());
+
+        drop(revived);
+        table.collect(6, 2);
+        table.collect(8, 2);
+        assert_eq!(table.total_bytes(), 0);
+    }
+}
@@ -0,0 +1,624 @@
+use std::sync::Arc;
+
+use crate::{
+    camera::Camera,
+    error_utils::EngineError,
+    instancing::{DrawQueue, MeshInstance},
+    lighting::{Light, LightKind, Lights, MAX_LIGHTS},
+    material::Material,
+    math::{Matrix4, Quaternion, Vector3},
+    resources::MeshHandle,
+};
+
+/// Slot of the node, and which node in that slot: ids of removed nodes stay invalid after
+/// their slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+// generation counts the nodes that have been in the slot
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+/// Position, orientation and size of a node relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Quaternion,
+    pub scale: Vector3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3) -> Self {
+        Transform {
+            translation,
+            ..Transform::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4 {
+        Matrix4::from_translation_rotation_scale(self.translation, self.rotation, self.scale)
+    }
+}
+
+/// Mesh drawn at the node's world transform.
+#[derive(Clone)]
+pub struct MeshComponent {
+    pub mesh: MeshHandle,
+    pub material: Arc<Material>,
+}
+
+impl MeshComponent {
+    pub fn new(mesh: MeshHandle) -> Self {
+        MeshComponent {
+            mesh,
+            material: Arc::new(Material::default()),
+        }
+    }
+}
+
+/// Perspective camera looking down the node's -Z axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraComponent {
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for CameraComponent {
+    fn default() -> Self {
+        let camera = Camera::default();
+
+        CameraComponent {
+            fov_y: camera.fov_y,
+            near: camera.near,
+            far: camera.far,
+        }
+    }
+}
+
+pub struct Node {
+    pub name: String,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+
+    transform: Transform,
+    // parent world matrix * local matrix, only valid when not dirty
+    world: Matrix4,
+    dirty: bool,
+
+    pub mesh: Option<MeshComponent>,
+    // point light positions and directional light directions are relative to the node
+    pub light: Option<Light>,
+    pub camera: Option<CameraComponent>,
+}
+
+impl Node {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+/// Hierarchy of nodes, each with a transform relative to its parent and optional components.
+///
+/// World matrices are cached and only recomputed for nodes whose transform, or one of whose
+/// ancestors' transform, changed since the last `update_world_transforms`.
+pub struct Scene {
+    // replaces the renderer's ambient light, like the lights of the scene
+    pub ambient: Vector3,
+
+    // removed nodes leave a hole so the ids of the others stay valid
+    nodes: Vec<Slot>,
+    free_slots: Vec<usize>,
+    roots: Vec<NodeId>,
+    active_camera: Option<NodeId>,
+    // lights past MAX_LIGHTS are left out every frame, but only reported once
+    light_limit_reported: bool,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            ambient: Lights::default().ambient,
+            nodes: Vec::new(),
+            free_slots: Vec::new(),
+            roots: Vec::new(),
+            active_camera: None,
+            light_limit_reported: false,
+        }
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene::default()
+    }
+
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        transform: Transform,
+    ) -> Result<NodeId, EngineError> {
+        if let Some(parent) = parent {
+            self.node(parent)?;
+        }
+
+        let node = Node {
+            name: name.to_string(),
+            parent,
+            children: Vec::new(),
+            transform,
+            world: Matrix4::identity(),
+            dirty: true,
+            mesh: None,
+            light: None,
+            camera: None,
+        };
+
+        let id = match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.nodes[index];
+                slot.generation += 1;
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.nodes.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: self.nodes.len() - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+
+        Ok(id)
+    }
+
+    /// Removes the node and all of its descendants, their ids stay invalid from then on.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), EngineError> {
+        let parent = self.node(id)?.parent;
+        self.detach(id, parent)?;
+
+        let mut to_remove = vec![id];
+        while let Some(id) = to_remove.pop() {
+            if let Some(node) = self.slot_mut(id).and_then(|slot| slot.node.take()) {
+                to_remove.extend(node.children);
+                self.free_slots.push(id.index);
+            }
+            if self.active_camera == Some(id) {
+                self.active_camera = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the node under a new parent, or to the root with `None`, keeping its local
+    /// transform.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), EngineError> {
+        if let Some(new_parent) = parent {
+            // walking up from the new parent must not reach the node, or it would own itself
+            let mut ancestor = Some(new_parent);
+            while let Some(current) = ancestor {
+                if current == id {
+                    return Err(EngineError::SceneError(format!(
+                        "Node {:?} can not become a child of its descendant {:?}",
+                        id, new_parent
+                    )));
+                }
+                ancestor = self.node(current)?.parent;
+            }
+        }
+
+        let old_parent = self.node(id)?.parent;
+        self.detach(id, old_parent)?;
+
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.node_mut(id)?;
+        node.parent = parent;
+        node.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn node(&self, id: NodeId) -> Result<&Node, EngineError> {
+        self.nodes
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+            .ok_or_else(|| EngineError::SceneError(format!("Unknown node {:?}", id)))
+    }
+
+    /// The components of the node can be changed through the returned reference, the transform
+    /// goes through `set_transform` so the world matrices get updated.
+    pub fn node_mut(&mut self, id: NodeId) -> Result<&mut Node, EngineError> {
+        self.slot_mut(id)
+            .and_then(|slot| slot.node.as_mut())
+            .ok_or_else(|| EngineError::SceneError(format!("Unknown node {:?}", id)))
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.iter()
+            .find(|(_, node)| node.name == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate().filter_map(|(index, slot)| {
+            let id = NodeId {
+                index,
+                generation: slot.generation,
+            };
+            slot.node.as_ref().map(|node| (id, node))
+        })
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) -> Result<(), EngineError> {
+        let node = self.node_mut(id)?;
+        node.transform = transform;
+        node.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn set_rotation(&mut self, id: NodeId, rotation: Quaternion) -> Result<(), EngineError> {
+        let transform = Transform {
+            rotation,
+            ..self.node(id)?.transform
+        };
+
+        self.set_transform(id, transform)
+    }
+
+    pub fn active_camera(&self) -> Option<NodeId> {
+        self.active_camera
+    }
+
+    pub fn set_active_camera(&mut self, id: Option<NodeId>) -> Result<(), EngineError> {
+        if let Some(id) = id {
+            if self.node(id)?.camera.is_none() {
+                return Err(EngineError::SceneError(format!(
+                    "Node {:?} has no camera component",
+                    id
+                )));
+            }
+        }
+
+        self.active_camera = id;
+        Ok(())
+    }
+
+    /// World matrix as of the last `update_world_transforms`.
+    pub fn world_matrix(&self, id: NodeId) -> Result<Matrix4, EngineError> {
+        Ok(self.node(id)?.world)
+    }
+
+    /// Recomputes the world matrix of every node that moved, along with its descendants.
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(NodeId, Matrix4, bool)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = match self.node_mut(id) {
+                Ok(node) => node,
+                Err(_) => continue,
+            };
+
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+            }
+
+            let world = node.world;
+            stack.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|&child| (child, world, changed)),
+            );
+        }
+    }
+
+    /// The active camera placed at its node's world transform.
+    pub fn camera(&self) -> Option<Camera> {
+        let node = self.node(self.active_camera?).ok()?;
+        let component = node.camera?;
+
+        let mut camera = Camera {
+            position: node.world.translation_part(),
+            fov_y: component.fov_y,
+            near: component.near,
+            far: component.far,
+            ..Camera::default()
+        };
+        let forward = node.world.transform_vector(Vector3::new(0.0, 0.0, -1.0));
+        camera.look_at(camera.position + forward);
+
+        Some(camera)
+    }
+
+    /// Queues every mesh of the scene, one instanced draw per mesh and material, and replaces
+    /// the lights with the ones of the scene. Updates the world transforms first.
+    ///
+    /// Only the first `MAX_LIGHTS` lights are used, with a warning the first time there are more.
+    pub(crate) fn submit(&mut self, draw_queue: &mut DrawQueue, lights: &mut Lights) {
+        self.update_world_transforms();
+        self.replace_lights(lights);
+
+        let mut batches: Vec<(&MeshComponent, Vec<MeshInstance>)> = Vec::new();
+        for (_, node) in self.iter() {
+            if let Some(component) = &node.mesh {
+                let instance = MeshInstance {
+                    transform: node.world,
+                    ..MeshInstance::default()
+                };
+
+                match batches.iter_mut().find(|(batch, _)| {
+                    batch.mesh == component.mesh
+                        && Arc::ptr_eq(&batch.material, &component.material)
+                }) {
+                    Some((_, instances)) => instances.push(instance),
+                    None => batches.push((component, vec![instance])),
+                }
+            }
+        }
+
+        for (component, instances) in &batches {
+            draw_queue.push(&component.mesh, &component.material, instances);
+        }
+    }
+
+    // the world transforms must be up to date
+    fn replace_lights(&mut self, lights: &mut Lights) {
+        lights.clear();
+        lights.ambient = self.ambient;
+
+        let mut left_out = 0;
+        for (_, node) in self.iter() {
+            if let Some(light) = &node.light {
+                if lights.add(world_light(light, &node.world)).is_err() {
+                    left_out += 1;
+                }
+            }
+        }
+
+        if left_out > 0 && !self.light_limit_reported {
+            eprintln!(
+                "The scene has {} lights, only the first {} are used",
+                MAX_LIGHTS + left_out,
+                MAX_LIGHTS
+            );
+            self.light_limit_reported = true;
+        }
+    }
+
+    fn slot_mut(&mut self, id: NodeId) -> Option<&mut Slot> {
+        self.nodes
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+    }
+
+    // the node is removed from its parent's children, or from the roots
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), EngineError> {
+        let siblings = match parent {
+            Some(parent) => &mut self.node_mut(parent)?.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+
+        Ok(())
+    }
+}
+
+fn world_light(light: &Light, world: &Matrix4) -> Light {
+    let kind = match light.kind {
+        LightKind::Directional { direction } => LightKind::Directional {
+            direction: world.transform_vector(direction).normalize(),
+        },
+        LightKind::Point {
+            position,
+            attenuation,
+        } => LightKind::Point {
+            position: world.transform_point(position),
+            attenuation,
+        },
+    };
+
+    Light { kind, ..*light }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Vector3, expected: Vector3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn removed_ids_stay_invalid_when_the_slot_is_reused() {
+        let mut scene = Scene::new();
+        let first = scene.add_node("first", None, Transform::default()).unwrap();
+        scene.remove_node(first).unwrap();
+
+        let second = scene
+            .add_node("second", None, Transform::default())
+            .unwrap();
+
+        assert_eq!(second.index, first.index);
+        assert!(scene.node(first).is_err());
+        assert!(scene.node_mut(first).is_err());
+        assert!(scene.remove_node(first).is_err());
+        assert_eq!(scene.node(second).unwrap().name, "second");
+        assert_eq!(scene.roots(), &[second]);
+    }
+
+    #[test]
+    fn remove_node_takes_the_descendants() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None, Transform::default()).unwrap();
+        let child = scene
+            .add_node("child", Some(root), Transform::default())
+            .unwrap();
+        let grandchild = scene
+            .add_node("grandchild", Some(child), Transform::default())
+            .unwrap();
+        scene.node_mut(grandchild).unwrap().camera = Some(CameraComponent::default());
+        scene.set_active_camera(Some(grandchild)).unwrap();
+
+        scene.remove_node(child).unwrap();
+
+        assert!(scene.node(root).unwrap().children().is_empty());
+        assert!(scene.node(grandchild).is_err());
+        assert_eq!(scene.active_camera(), None);
+        assert_eq!(scene.iter().count(), 1);
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None, Transform::default()).unwrap();
+        let child = scene
+            .add_node("child", Some(root), Transform::default())
+            .unwrap();
+
+        assert!(scene.set_parent(root, Some(child)).is_err());
+        assert!(scene.set_parent(root, Some(root)).is_err());
+
+        scene.set_parent(child, None).unwrap();
+        assert_eq!(scene.node(child).unwrap().parent(), None);
+        assert_eq!(scene.roots(), &[root, child]);
+        assert!(scene.node(root).unwrap().children().is_empty());
+    }
+
+    #[test]
+    fn world_transforms_follow_the_parents() {
+        let mut scene = Scene::new();
+        let parent_transform = Transform {
+            translation: Vector3::new(1.0, 0.0, 0.0),
+            rotation: Quaternion::from_axis_angle(Vector3::up(), std::f32::consts::FRAC_PI_2),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+        };
+        let parent = scene.add_node("parent", None, parent_transform).unwrap();
+        let child_transform = Transform::from_translation(Vector3::new(1.0, 0.0, 0.0));
+        let child = scene
+            .add_node("child", Some(parent), child_transform)
+            .unwrap();
+
+        scene.update_world_transforms();
+        let origin = |scene: &Scene| {
+            scene
+                .world_matrix(child)
+                .unwrap()
+                .transform_point(Vector3::zero())
+        };
+        assert_near(origin(&scene), Vector3::new(1.0, 0.0, -2.0));
+
+        // moving the parent after the first update also moves the cached child
+        let moved = Transform {
+            translation: Vector3::new(0.0, 3.0, 0.0),
+            ..parent_transform
+        };
+        scene.set_transform(parent, moved).unwrap();
+        scene.update_world_transforms();
+        assert_near(origin(&scene), Vector3::new(0.0, 3.0, -2.0));
+
+        // reparenting keeps the local transform
+        scene.set_parent(child, None).unwrap();
+        scene.update_world_transforms();
+        assert_near(origin(&scene), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn camera_looks_down_the_node_forward_axis() {
+        let mut scene = Scene::new();
+        assert!(scene.camera().is_none());
+
+        let transform = Transform {
+            translation: Vector3::new(0.0, 1.0, 5.0),
+            rotation: Quaternion::from_axis_angle(Vector3::up(), std::f32::consts::FRAC_PI_2),
+            ..Transform::default()
+        };
+        let node = scene.add_node("camera", None, transform).unwrap();
+        assert!(scene.set_active_camera(Some(node)).is_err());
+
+        scene.node_mut(node).unwrap().camera = Some(CameraComponent::default());
+        scene.set_active_camera(Some(node)).unwrap();
+        scene.update_world_transforms();
+        let camera = scene.camera().unwrap();
+
+        assert_near(camera.position, Vector3::new(0.0, 1.0, 5.0));
+        assert_near(camera.forward(), Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn lights_past_the_limit_are_left_out() {
+        let lamp = Light::point(Vector3::zero(), Vector3::new(1.0, 1.0, 1.0), 1.0);
+        let mut scene = Scene::new();
+        for index in 0..=MAX_LIGHTS {
+            let node = scene
+                .add_node(&format!("lamp {}", index), None, Transform::default())
+                .unwrap();
+            scene.node_mut(node).unwrap().light = Some(lamp);
+        }
+        scene.update_world_transforms();
+
+        let mut lights = Lights::default();
+        scene.replace_lights(&mut lights);
+
+        assert!(scene.light_limit_reported);
+        // already holds the first MAX_LIGHTS
+        assert!(lights.add(lamp).is_err());
+    }
+}
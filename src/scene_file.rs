@@ -0,0 +1,1350 @@
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+    error_utils::EngineError,
+    json::JsonValue,
+    lighting::{Attenuation, Light, LightKind, MAX_LIGHTS},
+    material::{BlendMode, CullMode, Material, ShaderProgram},
+    math::{Quaternion, Vector3},
+    mesh::MeshSource,
+    resources::{MeshHandle, Resources},
+    scene::{CameraComponent, MeshComponent, NodeId, Scene, Transform},
+};
+
+// Scene files are JSON documents:
+//
+//   {
+//     "ambient": [0.1, 0.1, 0.1],
+//     "meshes": {
+//       "floor": { "primitive": "floor", "half_size": 2, "colour": [0.8, 0.8, 0.8] },
+//       "teapot": { "file": "teapot.obj" }
+//     },
+//     "materials": {
+//       "red": { "base_colour": [1, 0, 0, 1] },
+//       "glass": { "base_colour": [0.8, 0.9, 1, 0.3], "blend": "alpha", "double_sided": true },
+//       "crate": { "texture": "crate.png", "shininess": 8 }
+//     },
+//     "active_camera": "camera",
+//     "nodes": [
+//       { "name": "floor", "mesh": "floor", "translation": [0, -0.5, 0] },
+//       { "name": "pivot", "rotation_degrees": [0, 0, 45], "children": [
+//         { "name": "lamp", "translation": [1, 0, 0],
+//           "light": { "type": "point", "colour": [1, 1, 1], "intensity": 1 } }
+//       ] },
+//       { "name": "camera", "translation": [0, 0, 2], "camera": { "fov_y_degrees": 45 } }
+//     ]
+//   }
+//
+// Primitives are "triangle" and "floor", mesh files are Wavefront OBJ, relative to the scene file.
+// Materials take "shader" ("lit" or "unlit"), "base_colour", "texture" (relative to the scene
+// file), "emissive", "specular_strength", "shininess", "blend" ("opaque", "alpha" or "additive"),
+// "cull" ("none", "front" or "back"), "depth_test", "depth_write" and "double_sided", all
+// optional. Rotations are either "rotation": [x, y, z, w] (a quaternion) or "rotation_degrees": [x, y, z]
+// (applied around X, then Y, then Z). Everything but "nodes" is optional. A scene has at most
+// MAX_LIGHTS lights.
+
+const SCENE_KEYS: &[&str] = &["ambient", "meshes", "materials", "active_camera", "nodes"];
+const NODE_KEYS: &[&str] = &[
+    "name",
+    "translation",
+    "rotation",
+    "rotation_degrees",
+    "scale",
+    "mesh",
+    "material",
+    "light",
+    "camera",
+    "children",
+];
+const MATERIAL_KEYS: &[&str] = &[
+    "shader",
+    "base_colour",
+    "texture",
+    "emissive",
+    "specular_strength",
+    "shininess",
+    "blend",
+    "cull",
+    "depth_test",
+    "depth_write",
+    "double_sided",
+];
+
+const SHADERS: &[(&str, ShaderProgram)] =
+    &[("lit", ShaderProgram::Lit), ("unlit", ShaderProgram::Unlit)];
+const BLEND_MODES: &[(&str, BlendMode)] = &[
+    ("opaque", BlendMode::Opaque),
+    ("alpha", BlendMode::AlphaBlend),
+    ("additive", BlendMode::Additive),
+];
+const CULL_MODES: &[(&str, CullMode)] = &[
+    ("none", CullMode::None),
+    ("front", CullMode::Front),
+    ("back", CullMode::Back),
+];
+
+/// Reads a scene file, loading its meshes and textures through `resources`. The whole file is
+/// checked before anything is loaded.
+pub fn load_scene(resources: &mut Resources, path: &Path) -> Result<Scene, EngineError> {
+    let source = fs::read_to_string(path).map_err(|err| {
+        EngineError::SceneError(format!("Could not read {}: {}", path.display(), err))
+    })?;
+    let origin = path.display().to_string();
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse_scene(&source, &origin, base_dir)?.load(resources, &origin)
+}
+
+/// Writes the scene to `path`. Fails when one of its meshes was created from vertices in code,
+/// or one of its textures was not loaded from a file, since there is nothing the file could point
+/// to.
+pub fn save_scene(scene: &Scene, path: &Path) -> Result<(), EngineError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let document = SceneWriter::new(scene, base_dir).write()?;
+
+    fs::write(path, document.to_pretty_string()).map_err(|err| {
+        EngineError::SceneError(format!("Could not write {}: {}", path.display(), err))
+    })
+}
+
+// A value of the document with the path leading to it, for error messages such as
+// "scene.json: nodes[2].light.colour: expected an array of 3 numbers, found a string"
+#[derive(Clone, Copy)]
+struct Field<'a> {
+    value: &'a JsonValue,
+    path: &'a str,
+    origin: &'a str,
+}
+
+impl<'a> Field<'a> {
+    fn error(&self, message: &str) -> EngineError {
+        error_at(self.origin, self.path, message)
+    }
+
+    fn expected(&self, what: &str) -> EngineError {
+        self.error(&format!(
+            "expected {}, found {}",
+            what,
+            self.value.type_name()
+        ))
+    }
+
+    fn entries(
+        &self,
+        allowed_keys: Option<&[&str]>,
+    ) -> Result<&'a [(String, JsonValue)], EngineError> {
+        let entries = match self.value {
+            JsonValue::Object(entries) => entries,
+            _ => return Err(self.expected("an object")),
+        };
+
+        if let Some(allowed_keys) = allowed_keys {
+            for (key, _) in entries {
+                if !allowed_keys.contains(&key.as_str()) {
+                    return Err(self.error(&format!(
+                        "unknown key \"{}\", expected one of: {}",
+                        key,
+                        allowed_keys.join(", ")
+                    )));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn as_f32(&self) -> Result<f32, EngineError> {
+        match self.value {
+            JsonValue::Number(value) => Ok(*value as f32),
+            _ => Err(self.expected("a number")),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, EngineError> {
+        match self.value {
+            JsonValue::Bool(value) => Ok(*value),
+            _ => Err(self.expected("a boolean")),
+        }
+    }
+
+    // one of the names of `choices`
+    fn as_choice<T: Copy>(&self, choices: &[(&str, T)]) -> Result<T, EngineError> {
+        let name = self.as_str()?;
+
+        choices
+            .iter()
+            .find(|(choice, _)| *choice == name)
+            .map(|&(_, value)| value)
+            .ok_or_else(|| {
+                let names: Vec<String> = choices
+                    .iter()
+                    .map(|(choice, _)| format!("\"{}\"", choice))
+                    .collect();
+                self.error(&format!(
+                    "unknown value \"{}\", expected one of: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+
+    fn as_str(&self) -> Result<&'a str, EngineError> {
+        match self.value {
+            JsonValue::String(value) => Ok(value),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    fn as_array(&self) -> Result<&'a [JsonValue], EngineError> {
+        match self.value {
+            JsonValue::Array(values) => Ok(values),
+            _ => Err(self.expected("an array")),
+        }
+    }
+
+    fn as_numbers(&self, count: usize) -> Result<Vec<f32>, EngineError> {
+        let what = format!("an array of {} numbers", count);
+        let values = self.as_array().map_err(|_| self.expected(&what))?;
+
+        let numbers: Option<Vec<f32>> = values
+            .iter()
+            .map(|value| match value {
+                JsonValue::Number(number) => Some(*number as f32),
+                _ => None,
+            })
+            .collect();
+
+        match numbers {
+            Some(numbers) if numbers.len() == count => Ok(numbers),
+            _ => Err(self.error(&format!("expected {}", what))),
+        }
+    }
+
+    fn as_vector3(&self) -> Result<Vector3, EngineError> {
+        let v = self.as_numbers(3)?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+
+    // alpha defaults to 1
+    fn as_colour(&self) -> Result<[f32; 4], EngineError> {
+        match self.as_numbers(4) {
+            Ok(v) => Ok([v[0], v[1], v[2], v[3]]),
+            Err(_) => {
+                let v = self
+                    .as_numbers(3)
+                    .map_err(|_| self.error("expected an array of 3 or 4 numbers"))?;
+                Ok([v[0], v[1], v[2], 1.0])
+            }
+        }
+    }
+}
+
+fn error_at(origin: &str, path: &str, message: &str) -> EngineError {
+    if path.is_empty() {
+        EngineError::SceneError(format!("{}: {}", origin, message))
+    } else {
+        EngineError::SceneError(format!("{}: {}: {}", origin, path, message))
+    }
+}
+
+// Checks a scene file and converts it, with the files it points to relative to `base_dir`
+fn parse_scene(
+    source: &str,
+    origin: &str,
+    base_dir: &Path,
+) -> Result<SceneDescription, EngineError> {
+    let document = JsonValue::parse(source)
+        .map_err(|err| EngineError::SceneError(format!("{}:{}", origin, err)))?;
+    let parser = Parser {
+        origin,
+        base_dir,
+        light_count: Cell::new(0),
+    };
+
+    parser.parse(&document)
+}
+
+// A checked scene file with nothing loaded yet, so a mistake anywhere in the file is reported
+// before any mesh or texture is created
+struct SceneDescription {
+    ambient: Option<Vector3>,
+    meshes: Vec<MeshDescription>,
+    materials: Vec<MaterialDescription>,
+    nodes: Vec<NodeDescription>,
+    // a node known to have a camera
+    active_camera: Option<String>,
+}
+
+// the paths of the values in the file are kept for the errors of loading them
+struct MeshDescription {
+    name: String,
+    source: MeshSource,
+    path: String,
+}
+
+struct MaterialDescription {
+    material: Material,
+    texture: Option<(PathBuf, String)>,
+}
+
+struct NodeDescription {
+    name: String,
+    transform: Transform,
+    // indices in the meshes and materials of the description
+    mesh: Option<usize>,
+    material: Option<usize>,
+    light: Option<Light>,
+    camera: Option<CameraComponent>,
+    children: Vec<NodeDescription>,
+}
+
+impl SceneDescription {
+    fn load(self, resources: &mut Resources, origin: &str) -> Result<Scene, EngineError> {
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                resources
+                    .load_mesh(&mesh.name, mesh.source.clone())
+                    .map_err(|err| error_at(origin, &mesh.path, &err.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let materials = self
+            .materials
+            .iter()
+            .map(|description| {
+                let mut material = description.material.clone();
+                if let Some((file, path)) = &description.texture {
+                    let texture = resources
+                        .load_texture(file)
+                        .map_err(|err| error_at(origin, path, &err.to_string()))?;
+                    material.base_colour_texture = Some(texture);
+                }
+
+                Ok(Arc::new(material))
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+
+        self.build(&meshes, &materials)
+    }
+
+    // `meshes` and `materials` are loaded from the ones of the description, in the same order
+    fn build(
+        &self,
+        meshes: &[MeshHandle],
+        materials: &[Arc<Material>],
+    ) -> Result<Scene, EngineError> {
+        let mut scene = Scene::new();
+        if let Some(ambient) = self.ambient {
+            scene.ambient = ambient;
+        }
+
+        for node in &self.nodes {
+            node.add_to(&mut scene, None, meshes, materials)?;
+        }
+
+        let active_camera = match &self.active_camera {
+            Some(name) => scene.find(name),
+            None => scene
+                .iter()
+                .find(|(_, node)| node.camera.is_some())
+                .map(|(id, _)| id),
+        };
+        scene.set_active_camera(active_camera)?;
+
+        Ok(scene)
+    }
+}
+
+impl NodeDescription {
+    fn add_to(
+        &self,
+        scene: &mut Scene,
+        parent: Option<NodeId>,
+        meshes: &[MeshHandle],
+        materials: &[Arc<Material>],
+    ) -> Result<(), EngineError> {
+        let id = scene.add_node(&self.name, parent, self.transform)?;
+
+        let node = scene.node_mut(id)?;
+        if let Some(mesh) = self.mesh {
+            let mut component = MeshComponent::new(meshes[mesh].clone());
+            if let Some(material) = self.material {
+                component.material = materials[material].clone();
+            }
+            node.mesh = Some(component);
+        }
+        node.light = self.light;
+        node.camera = self.camera;
+
+        for child in &self.children {
+            child.add_to(scene, Some(id), meshes, materials)?;
+        }
+
+        Ok(())
+    }
+}
+
+// the first node named `name` in the order they are added to the scene, which is the order
+// `Scene::find` looks in
+fn find_node<'a>(nodes: &'a [NodeDescription], name: &str) -> Option<&'a NodeDescription> {
+    nodes.iter().find_map(|node| {
+        if node.name == name {
+            Some(node)
+        } else {
+            find_node(&node.children, name)
+        }
+    })
+}
+
+struct Parser<'a> {
+    origin: &'a str,
+    base_dir: &'a Path,
+    // lights seen so far, the renderer only has room for MAX_LIGHTS
+    light_count: Cell<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn field<'b>(&'b self, value: &'b JsonValue, path: &'b str) -> Field<'b> {
+        Field {
+            value,
+            path,
+            origin: self.origin,
+        }
+    }
+
+    fn parse(&self, document: &JsonValue) -> Result<SceneDescription, EngineError> {
+        let root = self.field(document, "");
+        root.entries(Some(SCENE_KEYS))?;
+
+        let ambient = match document.get("ambient") {
+            Some(ambient) => Some(self.field(ambient, "ambient").as_vector3()?),
+            None => None,
+        };
+
+        let meshes = match document.get("meshes") {
+            Some(meshes) => self.parse_meshes(meshes)?,
+            None => Vec::new(),
+        };
+        let materials = match document.get("materials") {
+            Some(materials) => self.parse_materials(materials)?,
+            None => Vec::new(),
+        };
+
+        let nodes = document
+            .get("nodes")
+            .ok_or_else(|| root.error("missing \"nodes\""))?;
+        let nodes = self
+            .field(nodes, "nodes")
+            .as_array()?
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let path = format!("nodes[{}]", index);
+                self.parse_node(self.field(node, &path), &meshes, &materials)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let active_camera = match document.get("active_camera") {
+            Some(camera) => {
+                let field = self.field(camera, "active_camera");
+                let name = field.as_str()?;
+                let node = find_node(&nodes, name)
+                    .ok_or_else(|| field.error(&format!("no node is named \"{}\"", name)))?;
+                if node.camera.is_none() {
+                    return Err(field.error(&format!("node \"{}\" has no camera", name)));
+                }
+
+                Some(name.to_string())
+            }
+            None => None,
+        };
+
+        Ok(SceneDescription {
+            ambient,
+            meshes,
+            materials,
+            nodes,
+            active_camera,
+        })
+    }
+
+    fn parse_meshes(&self, meshes: &JsonValue) -> Result<Vec<MeshDescription>, EngineError> {
+        let entries = self.field(meshes, "meshes").entries(None)?;
+
+        entries
+            .iter()
+            .map(|(name, spec)| {
+                let path = format!("meshes.{}", name);
+                let source = self.mesh_source(self.field(spec, &path))?;
+
+                Ok(MeshDescription {
+                    name: name.clone(),
+                    source,
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    fn mesh_source(&self, field: Field) -> Result<MeshSource, EngineError> {
+        let entries = field.entries(Some(&["primitive", "file", "half_size", "colour"]))?;
+        let get = |key: &str| entries.iter().find(|(name, _)| name == key).map(|(_, v)| v);
+
+        match (get("primitive"), get("file")) {
+            (Some(primitive), None) => {
+                let path = format!("{}.primitive", field.path);
+                let primitive = self.field(primitive, &path);
+
+                match primitive.as_str()? {
+                    "triangle" => Ok(MeshSource::Triangle),
+                    "floor" => {
+                        let half_size = match get("half_size") {
+                            Some(value) => {
+                                let path = format!("{}.half_size", field.path);
+                                self.field(value, &path).as_f32()?
+                            }
+                            None => 1.0,
+                        };
+                        let colour = match get("colour") {
+                            Some(value) => {
+                                let path = format!("{}.colour", field.path);
+                                self.field(value, &path).as_vector3()?.into()
+                            }
+                            None => [1.0, 1.0, 1.0],
+                        };
+
+                        Ok(MeshSource::Floor { half_size, colour })
+                    }
+                    other => Err(primitive.error(&format!(
+                        "unknown primitive \"{}\", expected \"triangle\" or \"floor\"",
+                        other
+                    ))),
+                }
+            }
+            (None, Some(file)) => {
+                let path = format!("{}.file", field.path);
+                let file = self.field(file, &path).as_str()?;
+
+                Ok(MeshSource::File(self.base_dir.join(file)))
+            }
+            _ => Err(field.error("expected either \"primitive\" or \"file\"")),
+        }
+    }
+
+    fn parse_materials(
+        &self,
+        materials: &JsonValue,
+    ) -> Result<Vec<MaterialDescription>, EngineError> {
+        let entries = self.field(materials, "materials").entries(None)?;
+
+        entries
+            .iter()
+            .map(|(name, spec)| {
+                let path = format!("materials.{}", name);
+                self.parse_material(name, self.field(spec, &path))
+            })
+            .collect()
+    }
+
+    fn parse_material(&self, name: &str, field: Field) -> Result<MaterialDescription, EngineError> {
+        let entries = field.entries(Some(MATERIAL_KEYS))?;
+        let mut material = Material {
+            name: name.to_string(),
+            ..Material::default()
+        };
+        let mut texture = None;
+
+        for (key, value) in entries {
+            let path = format!("{}.{}", field.path, key);
+            let value = self.field(value, &path);
+
+            match key.as_str() {
+                "shader" => material.shader = value.as_choice(SHADERS)?,
+                "base_colour" => material.base_colour = value.as_colour()?,
+                "texture" => texture = Some((self.base_dir.join(value.as_str()?), path.clone())),
+                "emissive" => material.emissive = value.as_vector3()?.into(),
+                "specular_strength" => material.specular_strength = value.as_f32()?,
+                "shininess" => material.shininess = value.as_f32()?,
+                "blend" => material.state.blend = value.as_choice(BLEND_MODES)?,
+                "cull" => material.state.cull_mode = value.as_choice(CULL_MODES)?,
+                "depth_test" => material.state.depth_test = value.as_bool()?,
+                "depth_write" => material.state.depth_write = value.as_bool()?,
+                "double_sided" => material.state.double_sided = value.as_bool()?,
+                _ => unreachable!("keys are checked by entries"),
+            }
+        }
+
+        Ok(MaterialDescription { material, texture })
+    }
+
+    fn parse_node(
+        &self,
+        field: Field,
+        meshes: &[MeshDescription],
+        materials: &[MaterialDescription],
+    ) -> Result<NodeDescription, EngineError> {
+        field.entries(Some(NODE_KEYS))?;
+        let value = field.value;
+        let child = |key: &str| {
+            value
+                .get(key)
+                .map(|v| (v, format!("{}.{}", field.path, key)))
+        };
+
+        let name = match child("name") {
+            Some((v, path)) => self.field(v, &path).as_str()?.to_string(),
+            None => String::new(),
+        };
+
+        let mut transform = Transform::default();
+        if let Some((v, path)) = child("translation") {
+            transform.translation = self.field(v, &path).as_vector3()?;
+        }
+        match (child("rotation"), child("rotation_degrees")) {
+            (Some(_), Some(_)) => {
+                return Err(field.error("use either \"rotation\" or \"rotation_degrees\", not both"))
+            }
+            (Some((v, path)), None) => {
+                let q = self.field(v, &path).as_numbers(4)?;
+                transform.rotation = Quaternion {
+                    x: q[0],
+                    y: q[1],
+                    z: q[2],
+                    w: q[3],
+                }
+                .normalize();
+            }
+            (None, Some((v, path))) => {
+                let angles = self.field(v, &path).as_vector3()?;
+                transform.rotation = Quaternion::from_euler(
+                    angles.x.to_radians(),
+                    angles.y.to_radians(),
+                    angles.z.to_radians(),
+                );
+            }
+            (None, None) => {}
+        }
+        if let Some((v, path)) = child("scale") {
+            let scale = self.field(v, &path);
+            transform.scale = match v {
+                JsonValue::Number(s) => Vector3::new(*s as f32, *s as f32, *s as f32),
+                _ => scale
+                    .as_vector3()
+                    .map_err(|_| scale.error("expected a number or an array of 3 numbers"))?,
+            };
+        }
+
+        let (mesh, material) = match (child("mesh"), child("material")) {
+            (Some((v, path)), material) => {
+                let mesh_field = self.field(v, &path);
+                let mesh_name = mesh_field.as_str()?;
+                let mesh = meshes
+                    .iter()
+                    .position(|mesh| mesh.name == mesh_name)
+                    .ok_or_else(|| {
+                        mesh_field.error(&format!("no mesh is named \"{}\"", mesh_name))
+                    })?;
+
+                let material = match material {
+                    Some((v, path)) => {
+                        let material_field = self.field(v, &path);
+                        let material_name = material_field.as_str()?;
+                        let material = materials
+                            .iter()
+                            .position(|material| material.material.name == material_name)
+                            .ok_or_else(|| {
+                                material_field
+                                    .error(&format!("no material is named \"{}\"", material_name))
+                            })?;
+
+                        Some(material)
+                    }
+                    None => None,
+                };
+
+                (Some(mesh), material)
+            }
+            (None, Some(_)) => return Err(field.error("\"material\" needs a \"mesh\"")),
+            (None, None) => (None, None),
+        };
+
+        let light = match child("light") {
+            Some((v, path)) => {
+                let light_field = self.field(v, &path);
+                if self.light_count.get() == MAX_LIGHTS {
+                    return Err(light_field.error(&format!(
+                        "too many lights, a scene has at most {}",
+                        MAX_LIGHTS
+                    )));
+                }
+                self.light_count.set(self.light_count.get() + 1);
+
+                Some(self.parse_light(light_field)?)
+            }
+            None => None,
+        };
+
+        let camera = match child("camera") {
+            Some((v, path)) => Some(self.parse_camera(self.field(v, &path))?),
+            None => None,
+        };
+
+        let mut children = Vec::new();
+        if let Some((v, path)) = child("children") {
+            for (index, node) in self.field(v, &path).as_array()?.iter().enumerate() {
+                let path = format!("{}[{}]", path, index);
+                children.push(self.parse_node(self.field(node, &path), meshes, materials)?);
+            }
+        }
+
+        Ok(NodeDescription {
+            name,
+            transform,
+            mesh,
+            material,
+            light,
+            camera,
+            children,
+        })
+    }
+
+    fn parse_light(&self, field: Field) -> Result<Light, EngineError> {
+        field.entries(Some(&[
+            "type",
+            "direction",
+            "position",
+            "attenuation",
+            "colour",
+            "intensity",
+        ]))?;
+        let value = field.value;
+        let child = |key: &str| {
+            value
+                .get(key)
+                .map(|v| (v, format!("{}.{}", field.path, key)))
+        };
+
+        let colour = match child("colour") {
+            Some((v, path)) => self.field(v, &path).as_vector3()?,
+            None => Vector3::new(1.0, 1.0, 1.0),
+        };
+        let intensity = match child("intensity") {
+            Some((v, path)) => self.field(v, &path).as_f32()?,
+            None => 1.0,
+        };
+
+        let (kind_value, kind_path) =
+            child("type").ok_or_else(|| field.error("missing \"type\""))?;
+        let kind = match self.field(kind_value, &kind_path).as_str()? {
+            "directional" => {
+                let (v, path) = child("direction")
+                    .ok_or_else(|| field.error("directional lights need a \"direction\""))?;
+
+                LightKind::Directional {
+                    direction: self.field(v, &path).as_vector3()?.normalize(),
+                }
+            }
+            "point" => {
+                let position = match child("position") {
+                    Some((v, path)) => self.field(v, &path).as_vector3()?,
+                    None => Vector3::zero(),
+                };
+                let attenuation = match child("attenuation") {
+                    Some((v, path)) => {
+                        let a = self.field(v, &path).as_numbers(3)?;
+                        Attenuation {
+                            constant: a[0],
+                            linear: a[1],
+                            quadratic: a[2],
+                        }
+                    }
+                    None => Attenuation::default(),
+                };
+
+                LightKind::Point {
+                    position,
+                    attenuation,
+                }
+            }
+            other => {
+                return Err(self.field(kind_value, &kind_path).error(&format!(
+                    "unknown light type \"{}\", expected \"directional\" or \"point\"",
+                    other
+                )))
+            }
+        };
+
+        Ok(Light {
+            kind,
+            colour,
+            intensity,
+        })
+    }
+
+    fn parse_camera(&self, field: Field) -> Result<CameraComponent, EngineError> {
+        field.entries(Some(&["fov_y_degrees", "near", "far"]))?;
+        let mut camera = CameraComponent::default();
+
+        if let Some(v) = field.value.get("fov_y_degrees") {
+            let path = format!("{}.fov_y_degrees", field.path);
+            camera.fov_y = self.field(v, &path).as_f32()?.to_radians();
+        }
+        if let Some(v) = field.value.get("near") {
+            let path = format!("{}.near", field.path);
+            camera.near = self.field(v, &path).as_f32()?;
+        }
+        if let Some(v) = field.value.get("far") {
+            let path = format!("{}.far", field.path);
+            camera.far = self.field(v, &path).as_f32()?;
+        }
+
+        if camera.near <= 0.0 || camera.far <= camera.near {
+            return Err(field.error("expected 0 < near < far"));
+        }
+
+        Ok(camera)
+    }
+}
+
+struct SceneWriter<'a> {
+    scene: &'a Scene,
+    base_dir: &'a Path,
+    // shared meshes and materials are written once, under a name unique in the file
+    meshes: Vec<(String, MeshHandle)>,
+    materials: Vec<(String, Arc<Material>)>,
+}
+
+impl<'a> SceneWriter<'a> {
+    fn new(scene: &'a Scene, base_dir: &'a Path) -> Self {
+        SceneWriter {
+            scene,
+            base_dir,
+            meshes: Vec::new(),
+            materials: Vec::new(),
+        }
+    }
+
+    fn write(mut self) -> Result<JsonValue, EngineError> {
+        let mut nodes = Vec::new();
+        for &root in self.scene.roots() {
+            nodes.push(self.write_node(root)?);
+        }
+
+        let mut document = vec![("ambient".to_string(), vector3(self.scene.ambient))];
+
+        let mut meshes = Vec::new();
+        for (name, mesh) in &self.meshes {
+            meshes.push((name.clone(), self.write_mesh_source(name, mesh.source())?));
+        }
+        if !meshes.is_empty() {
+            document.push(("meshes".to_string(), JsonValue::Object(meshes)));
+        }
+
+        let mut materials = Vec::new();
+        for (name, material) in &self.materials {
+            materials.push((name.clone(), self.write_material(material)?));
+        }
+        if !materials.is_empty() {
+            document.push(("materials".to_string(), JsonValue::Object(materials)));
+        }
+
+        if let Some(camera) = self.scene.active_camera() {
+            let name = self.scene.node(camera)?.name.clone();
+            document.push(("active_camera".to_string(), JsonValue::String(name)));
+        }
+
+        document.push(("nodes".to_string(), JsonValue::Array(nodes)));
+
+        Ok(JsonValue::Object(document))
+    }
+
+    fn write_node(&mut self, id: NodeId) -> Result<JsonValue, EngineError> {
+        let node = self.scene.node(id)?;
+        let mut entries = Vec::new();
+
+        if !node.name.is_empty() {
+            entries.push(("name".to_string(), JsonValue::String(node.name.clone())));
+        }
+
+        let transform = node.transform();
+        let identity = Transform::default();
+        if transform.translation != identity.translation {
+            entries.push(("translation".to_string(), vector3(transform.translation)));
+        }
+        if transform.rotation != identity.rotation {
+            let q = transform.rotation;
+            let rotation =
+                JsonValue::Array(vec![number(q.x), number(q.y), number(q.z), number(q.w)]);
+            entries.push(("rotation".to_string(), rotation));
+        }
+        if transform.scale != identity.scale {
+            entries.push(("scale".to_string(), vector3(transform.scale)));
+        }
+
+        if let Some(component) = &node.mesh {
+            let mesh_name = self.mesh_name(&component.mesh, &node.name)?;
+            entries.push(("mesh".to_string(), JsonValue::String(mesh_name)));
+
+            if *component.material != Material::default() {
+                let material_name = self.material_name(&component.material);
+                entries.push(("material".to_string(), JsonValue::String(material_name)));
+            }
+        }
+
+        if let Some(light) = &node.light {
+            entries.push(("light".to_string(), write_light(light)));
+        }
+
+        if let Some(camera) = &node.camera {
+            let camera = JsonValue::Object(vec![
+                (
+                    "fov_y_degrees".to_string(),
+                    number(camera.fov_y.to_degrees()),
+                ),
+                ("near".to_string(), number(camera.near)),
+                ("far".to_string(), number(camera.far)),
+            ]);
+            entries.push(("camera".to_string(), camera));
+        }
+
+        if !node.children().is_empty() {
+            let mut children = Vec::new();
+            for &child in node.children() {
+                children.push(self.write_node(child)?);
+            }
+            entries.push(("children".to_string(), JsonValue::Array(children)));
+        }
+
+        Ok(JsonValue::Object(entries))
+    }
+
+    fn mesh_name(&mut self, mesh: &MeshHandle, node_name: &str) -> Result<String, EngineError> {
+        if let Some((name, _)) = self.meshes.iter().find(|(_, m)| m == mesh) {
+            return Ok(name.clone());
+        }
+
+        if *mesh.source() == MeshSource::Vertices {
+            return Err(EngineError::SceneError(format!(
+                "The mesh of node \"{}\" was created from vertices and can not be saved",
+                node_name
+            )));
+        }
+
+        let taken: Vec<&str> = self.meshes.iter().map(|(name, _)| name.as_str()).collect();
+        let name = unique_name(mesh.name(), "mesh", &taken);
+        self.meshes.push((name.clone(), mesh.clone()));
+
+        Ok(name)
+    }
+
+    // materials with the same name and parameters are merged, even when not shared in memory
+    fn material_name(&mut self, material: &Arc<Material>) -> String {
+        if let Some((name, _)) = self
+            .materials
+            .iter()
+            .find(|(_, m)| Arc::ptr_eq(m, material) || **m == **material)
+        {
+            return name.clone();
+        }
+
+        let taken: Vec<&str> = self
+            .materials
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let name = unique_name(&material.name, "material", &taken);
+        self.materials.push((name.clone(), material.clone()));
+
+        name
+    }
+
+    // only what differs from the default material is written
+    fn write_material(&self, material: &Material) -> Result<JsonValue, EngineError> {
+        let default = Material::default();
+        let mut entries = Vec::new();
+        let string = |value: &str| JsonValue::String(value.to_string());
+
+        if material.shader != default.shader {
+            entries.push((
+                "shader".to_string(),
+                string(choice_name(SHADERS, material.shader)),
+            ));
+        }
+        if material.base_colour != default.base_colour {
+            let colour = material.base_colour.iter().map(|&c| number(c)).collect();
+            entries.push(("base_colour".to_string(), JsonValue::Array(colour)));
+        }
+        if let Some(texture) = &material.base_colour_texture {
+            let path = texture.path().ok_or_else(|| {
+                EngineError::SceneError(format!(
+                    "The texture of material \"{}\" was not loaded from a file and can not be saved",
+                    material.name
+                ))
+            })?;
+            let path = relative_path(path, self.base_dir);
+            entries.push((
+                "texture".to_string(),
+                string(&path.to_string_lossy().replace('\\', "/")),
+            ));
+        }
+        if material.emissive != default.emissive {
+            entries.push(("emissive".to_string(), vector3(material.emissive.into())));
+        }
+        if material.specular_strength != default.specular_strength {
+            entries.push((
+                "specular_strength".to_string(),
+                number(material.specular_strength),
+            ));
+        }
+        if material.shininess != default.shininess {
+            entries.push(("shininess".to_string(), number(material.shininess)));
+        }
+
+        let (state, default_state) = (material.state, default.state);
+        if state.blend != default_state.blend {
+            entries.push((
+                "blend".to_string(),
+                string(choice_name(BLEND_MODES, state.blend)),
+            ));
+        }
+        if state.cull_mode != default_state.cull_mode {
+            entries.push((
+                "cull".to_string(),
+                string(choice_name(CULL_MODES, state.cull_mode)),
+            ));
+        }
+        if state.depth_test != default_state.depth_test {
+            entries.push(("depth_test".to_string(), JsonValue::Bool(state.depth_test)));
+        }
+        if state.depth_write != default_state.depth_write {
+            entries.push((
+                "depth_write".to_string(),
+                JsonValue::Bool(state.depth_write),
+            ));
+        }
+        if state.double_sided != default_state.double_sided {
+            entries.push((
+                "double_sided".to_string(),
+                JsonValue::Bool(state.double_sided),
+            ));
+        }
+
+        Ok(JsonValue::Object(entries))
+    }
+
+    fn write_mesh_source(&self, name: &str, source: &MeshSource) -> Result<JsonValue, EngineError> {
+        let primitive = |name: &str| ("primitive".to_string(), JsonValue::String(name.to_string()));
+
+        let entries = match source {
+            MeshSource::Triangle => vec![primitive("triangle")],
+            MeshSource::Floor { half_size, colour } => vec![
+                primitive("floor"),
+                ("half_size".to_string(), number(*half_size)),
+                ("colour".to_string(), vector3(Vector3::from(*colour))),
+            ],
+            MeshSource::File(path) => {
+                let path = relative_path(path, self.base_dir);
+                vec![(
+                    "file".to_string(),
+                    JsonValue::String(path.to_string_lossy().replace('\\', "/")),
+                )]
+            }
+            MeshSource::Vertices => {
+                return Err(EngineError::SceneError(format!(
+                    "Mesh \"{}\" was created from vertices and can not be saved",
+                    name
+                )))
+            }
+        };
+
+        Ok(JsonValue::Object(entries))
+    }
+}
+
+fn write_light(light: &Light) -> JsonValue {
+    let mut entries = Vec::new();
+
+    match light.kind {
+        LightKind::Directional { direction } => {
+            entries.push((
+                "type".to_string(),
+                JsonValue::String("directional".to_string()),
+            ));
+            entries.push(("direction".to_string(), vector3(direction)));
+        }
+        LightKind::Point {
+            position,
+            attenuation,
+        } => {
+            entries.push(("type".to_string(), JsonValue::String("point".to_string())));
+            if position != Vector3::zero() {
+                entries.push(("position".to_string(), vector3(position)));
+            }
+            if attenuation != Attenuation::default() {
+                let a = [
+                    attenuation.constant,
+                    attenuation.linear,
+                    attenuation.quadratic,
+                ];
+                let a = a.iter().map(|&value| number(value)).collect();
+                entries.push(("attenuation".to_string(), JsonValue::Array(a)));
+            }
+        }
+    }
+
+    entries.push(("colour".to_string(), vector3(light.colour)));
+    entries.push(("intensity".to_string(), number(light.intensity)));
+
+    JsonValue::Object(entries)
+}
+
+// f32 values go through f64 with their shortest f32 representation, so 0.1 is not written as
+// 0.10000000149011612
+fn number(value: f32) -> JsonValue {
+    JsonValue::Number(value.to_string().parse().unwrap_or(0.0))
+}
+
+fn vector3(v: Vector3) -> JsonValue {
+    JsonValue::Array(vec![number(v.x), number(v.y), number(v.z)])
+}
+
+fn choice_name<T: Copy + PartialEq>(choices: &[(&'static str, T)], value: T) -> &'static str {
+    choices
+        .iter()
+        .find(|&&(_, choice)| choice == value)
+        .map(|&(name, _)| name)
+        .unwrap()
+}
+
+fn unique_name(wanted: &str, fallback: &str, taken: &[&str]) -> String {
+    let base = if wanted.is_empty() { fallback } else { wanted };
+    if !taken.contains(&base) {
+        return base.to_string();
+    }
+
+    (2..)
+        .map(|n| format!("{}_{}", base, n))
+        .find(|name| !taken.contains(&name.as_str()))
+        .unwrap()
+}
+
+// paths inside the scene directory are written relative to it, the others as they are
+fn relative_path(path: &Path, base_dir: &Path) -> PathBuf {
+    path.strip_prefix(base_dir)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<SceneDescription, EngineError> {
+        parse_scene(source, "test.json", Path::new("scenes"))
+    }
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("{} must be rejected", source),
+            Err(EngineError::SceneError(message)) => message,
+            Err(err) => panic!("not a scene error: {:?}", err),
+        }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_value() {
+        for (source, expected) in &[
+            (
+                r#"{ "nodes": [ {}, {}, { "light": { "type": "point", "colour": "red" } } ] }"#,
+                "test.json: nodes[2].light.colour: expected an array of 3 numbers, found a string",
+            ),
+            (
+                r#"{ "nodes": [ { "children": [ { "light": { "type": "spot" } } ] } ] }"#,
+                "test.json: nodes[0].children[0].light.type: unknown light type \"spot\", \
+                 expected \"directional\" or \"point\"",
+            ),
+            (
+                r#"{ "nodes": [ { "mesh": "teapot" } ] }"#,
+                "test.json: nodes[0].mesh: no mesh is named \"teapot\"",
+            ),
+            (
+                r#"{ "materials": { "red": {} }, "nodes": [ { "material": "red" } ] }"#,
+                "test.json: nodes[0]: \"material\" needs a \"mesh\"",
+            ),
+            (
+                r#"{ "meshes": { "floor": { "primitive": "plane" } }, "nodes": [] }"#,
+                "test.json: meshes.floor.primitive: unknown primitive \"plane\", \
+                 expected \"triangle\" or \"floor\"",
+            ),
+            (
+                r#"{ "materials": { "red": { "blend": "multiply" } }, "nodes": [] }"#,
+                "test.json: materials.red.blend: unknown value \"multiply\", \
+                 expected one of: \"opaque\", \"alpha\", \"additive\"",
+            ),
+            (
+                r#"{ "nodes": [ { "camera": { "near": 2, "far": 1 } } ] }"#,
+                "test.json: nodes[0].camera: expected 0 < near < far",
+            ),
+            (
+                r#"{ "active_camera": "lamp", "nodes": [ { "name": "lamp" } ] }"#,
+                "test.json: active_camera: node \"lamp\" has no camera",
+            ),
+            (
+                r#"{ "ambient": 1 }"#,
+                "test.json: ambient: expected an array of 3 numbers, found a number",
+            ),
+            (r#"{ "meshes": {} }"#, "test.json: missing \"nodes\""),
+            (r#"{ "nodes": [ }"#, "test.json:1:14: unexpected '}'"),
+        ] {
+            assert_eq!(error(source), *expected);
+        }
+    }
+
+    #[test]
+    fn too_many_lights_are_rejected() {
+        let lamp = r#"{ "light": { "type": "point" } }"#;
+        let lamps = |count: usize| vec![lamp; count].join(", ");
+
+        assert!(parse(&format!(r#"{{ "nodes": [ {} ] }}"#, lamps(MAX_LIGHTS))).is_ok());
+        assert_eq!(
+            error(&format!(
+                r#"{{ "nodes": [ {{ "children": [ {} ] }} ] }}"#,
+                lamps(MAX_LIGHTS + 1)
+            )),
+            format!(
+                "test.json: nodes[0].children[{}].light: too many lights, a scene has at most {}",
+                MAX_LIGHTS, MAX_LIGHTS
+            )
+        );
+    }
+
+    #[test]
+    fn meshes_and_textures_are_described_before_loading() {
+        let description = parse(
+            r#"{
+              "meshes": { "floor": { "primitive": "floor", "half_size": 2 } },
+              "materials": { "glass": { "texture": "glass.png", "blend": "alpha" } },
+              "nodes": [ { "mesh": "floor", "material": "glass" } ]
+            }"#,
+        )
+        .unwrap();
+
+        let mesh = &description.meshes[0];
+        assert_eq!(mesh.name, "floor");
+        assert_eq!(mesh.path, "meshes.floor");
+        assert_eq!(
+            mesh.source,
+            MeshSource::Floor {
+                half_size: 2.0,
+                colour: [1.0, 1.0, 1.0]
+            }
+        );
+
+        let material = &description.materials[0];
+        assert_eq!(material.material.name, "glass");
+        assert_eq!(material.material.state.blend, BlendMode::AlphaBlend);
+        assert_eq!(
+            material.texture,
+            Some((
+                Path::new("scenes").join("glass.png"),
+                "materials.glass.texture".to_string()
+            ))
+        );
+
+        let node = &description.nodes[0];
+        assert_eq!((node.mesh, node.material), (Some(0), Some(0)));
+    }
+
+    #[test]
+    fn saved_scene_loads_back() {
+        let mut scene = Scene::new();
+        scene.ambient = Vector3::new(0.2, 0.3, 0.4);
+
+        let sun = scene.add_node("sun", None, Transform::default()).unwrap();
+        scene.node_mut(sun).unwrap().light = Some(Light::directional(
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(1.0, 0.9, 0.8),
+            0.7,
+        ));
+
+        let pivot_transform = Transform {
+            rotation: Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), 0.5),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+            ..Transform::default()
+        };
+        let pivot = scene.add_node("pivot", None, pivot_transform).unwrap();
+        let lamp_transform = Transform::from_translation(Vector3::new(1.0, 0.0, 0.0));
+        let lamp = scene.add_node("lamp", Some(pivot), lamp_transform).unwrap();
+        scene.node_mut(lamp).unwrap().light = Some(Light {
+            kind: LightKind::Point {
+                position: Vector3::new(0.0, 0.1, 0.0),
+                attenuation: Attenuation {
+                    constant: 1.0,
+                    linear: 0.5,
+                    quadratic: 0.25,
+                },
+            },
+            colour: Vector3::new(1.0, 0.5, 0.0),
+            intensity: 2.0,
+        });
+
+        let camera_transform = Transform::from_translation(Vector3::new(0.0, 1.0, 3.0));
+        let camera = scene.add_node("camera", None, camera_transform).unwrap();
+        scene.node_mut(camera).unwrap().camera = Some(CameraComponent {
+            fov_y: 50f32.to_radians(),
+            near: 0.2,
+            far: 50.0,
+        });
+        scene.set_active_camera(Some(camera)).unwrap();
+
+        let document = SceneWriter::new(&scene, Path::new("scenes"))
+            .write()
+            .unwrap()
+            .to_pretty_string();
+        let loaded = parse(&document).unwrap().build(&[], &[]).unwrap();
+
+        assert_eq!(loaded.ambient, scene.ambient);
+        let nodes: Vec<_> = scene.iter().map(|(_, node)| node).collect();
+        let loaded_nodes: Vec<_> = loaded.iter().map(|(_, node)| node).collect();
+        assert_eq!(loaded_nodes.len(), nodes.len());
+        for (loaded, node) in loaded_nodes.iter().zip(&nodes) {
+            assert_eq!(loaded.name, node.name);
+            assert_eq!(loaded.light, node.light);
+            assert_eq!(loaded.children().len(), node.children().len());
+
+            let (loaded_transform, transform) = (loaded.transform(), node.transform());
+            assert_eq!(loaded_transform.translation, transform.translation);
+            assert_eq!(loaded_transform.scale, transform.scale);
+            let (q, expected) = (loaded_transform.rotation, transform.rotation);
+            for (actual, expected) in [q.x, q.y, q.z, q.w]
+                .iter()
+                .zip(&[expected.x, expected.y, expected.z, expected.w])
+            {
+                assert_near(*actual, *expected);
+            }
+
+            assert_eq!(loaded.camera.is_some(), node.camera.is_some());
+            if let (Some(loaded), Some(camera)) = (loaded.camera, node.camera) {
+                assert_near(loaded.fov_y, camera.fov_y);
+                assert_eq!((loaded.near, loaded.far), (camera.near, camera.far));
+            }
+        }
+
+        let active = loaded.node(loaded.active_camera().unwrap()).unwrap();
+        assert_eq!(active.name, "camera");
+    }
+}
@@ -0,0 +1,452 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use vulkano::instance::{PhysicalDevice, PhysicalDeviceType};
+
+use crate::{
+    config::ConfigFile,
+    display_settings::{DisplaySettings, HdrMode, VsyncMode},
+    error_utils::EngineError,
+    vulkan_renderer::{ENABLE_VALIDATION_LAYERS, WINDOW_TITLE},
+};
+
+// read when it exists, like the input bindings
+pub const DEFAULT_SETTINGS_PATH: &str = "settings.cfg";
+// environment variables are the key in capitals with this prefix: APP_PRESENT_MODE
+const ENV_PREFIX: &str = "APP_";
+
+pub const USAGE: &str = "usage: vulkano-udemy [options] [scene.json]
+
+options, also read from settings.cfg ([window] and [renderer] sections) and from APP_<KEY>
+environment variables, the command line winning over the environment and the environment over
+the file:
+  --config <path>           settings file to read instead of settings.cfg
+  --title <text>            window title
+  --size <width>x<height>   window size
+  --position <x>,<y>        window position
+  --resizable <bool>        whether the window can be resized
+  --dpi <logical|physical>  whether size and position scale with the monitor
+  --gpu <choice>            discrete, integrated, an index or part of a name
+  --validation <bool>       Vulkan validation layers
+  --present-mode <mode>     fifo, relaxed, mailbox or immediate
+  --msaa <samples>          1 (off), 2, 4, 8...
+  --hdr <mode>              off or scrgb, when the display offers it
+  --srgb <bool>             whether SRGB swapchain formats come before UNORM ones
+  --help                    print this and exit";
+
+/// How the size and position of the window are measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DpiMode {
+    // scaled by the scale factor of the monitor, so the window looks as big everywhere
+    Logical,
+    // pixels of the monitor as is
+    Physical,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSettings {
+    pub title: String,
+    // None lets the platform decide
+    pub size: Option<[u32; 2]>,
+    pub position: Option<[i32; 2]>,
+    pub resizable: bool,
+    pub dpi: DpiMode,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: WINDOW_TITLE.to_string(),
+            size: None,
+            position: None,
+            resizable: true,
+            dpi: DpiMode::Logical,
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn titled(title: &str) -> Self {
+        WindowSettings {
+            title: title.to_string(),
+            ..WindowSettings::default()
+        }
+    }
+}
+
+/// Which GPU the renderer runs on, among those that can present to the window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpuChoice {
+    FirstSuitable,
+    Discrete,
+    Integrated,
+    // as listed by the Vulkan instance
+    Index(usize),
+    // any device whose name contains it, ignoring case
+    Name(String),
+}
+
+impl GpuChoice {
+    pub fn matches(&self, device: &PhysicalDevice) -> bool {
+        match self {
+            GpuChoice::FirstSuitable => true,
+            GpuChoice::Discrete => device.ty() == PhysicalDeviceType::DiscreteGpu,
+            GpuChoice::Integrated => device.ty() == PhysicalDeviceType::IntegratedGpu,
+            GpuChoice::Index(index) => device.index() == *index,
+            GpuChoice::Name(name) => device.name().to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+/// What the renderer is created with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RendererSettings {
+    pub window: WindowSettings,
+    pub display: DisplaySettings,
+    pub gpu: GpuChoice,
+    pub validation: bool,
+    // samples per pixel of the main pass, lowered to what the GPU supports
+    pub msaa_samples: u32,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        RendererSettings {
+            window: WindowSettings::default(),
+            display: DisplaySettings::default(),
+            gpu: GpuChoice::FirstSuitable,
+            validation: ENABLE_VALIDATION_LAYERS,
+            msaa_samples: 1,
+        }
+    }
+}
+
+/// Everything the app is started with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AppSettings {
+    pub renderer: RendererSettings,
+    pub scene_path: Option<PathBuf>,
+    pub show_help: bool,
+}
+
+impl AppSettings {
+    /// Settings from the defaults, then the settings file, then the environment, then the
+    /// command line `args` (without the program name).
+    pub fn load(args: &[String]) -> Result<Self, EngineError> {
+        Self::load_with(args, Path::new(DEFAULT_SETTINGS_PATH), |name| {
+            env::var(name).ok()
+        })
+    }
+
+    // `load` reading `default_path` when there is no --config, and environment variables
+    // through `env_var`, so tests do not depend on the process they run in
+    fn load_with(
+        args: &[String],
+        default_path: &Path,
+        env_var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, EngineError> {
+        let options = parse_args(args)?;
+        let mut settings = AppSettings::default();
+
+        let config_path = options
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "config")
+            .map(|(_, value)| PathBuf::from(value));
+        match &config_path {
+            Some(path) => settings.apply_file(path)?,
+            None if default_path.exists() => settings.apply_file(default_path)?,
+            None => (),
+        }
+
+        for &key in KEYS {
+            let name = format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"));
+            if let Some(value) = env_var(&name) {
+                settings.apply(key, &value).map_err(|message| {
+                    EngineError::ConfigError(format!("{}: {}", name, message))
+                })?;
+            }
+        }
+
+        for (key, value) in &options {
+            match key.as_str() {
+                "config" => (),
+                "help" => settings.show_help = true,
+                "scene" => settings.scene_path = Some(PathBuf::from(value)),
+                _ => settings.apply(key, value).map_err(|message| {
+                    EngineError::ConfigError(format!("--{}: {}", key, message))
+                })?,
+            }
+        }
+
+        Ok(settings)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), EngineError> {
+        self.apply_config(&ConfigFile::load(path)?)
+    }
+
+    fn apply_config(&mut self, config: &ConfigFile) -> Result<(), EngineError> {
+        for section in &config.sections {
+            if section.name != "window" && section.name != "renderer" {
+                return Err(EngineError::ConfigError(format!(
+                    "{}: unknown section [{}]",
+                    config.origin, section.name
+                )));
+            }
+
+            for entry in &section.entries {
+                self.apply(&entry.key, &entry.value)
+                    .map_err(|message| config.entry_error(entry, &message))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // the same keys are used by the file, the environment and the command line
+    fn apply(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let renderer = &mut self.renderer;
+
+        match key {
+            "title" => renderer.window.title = value.to_string(),
+            "size" => {
+                let (width, height) = split_pair(value, 'x')?;
+                renderer.window.size = Some([parse_number(width)?, parse_number(height)?]);
+            }
+            "position" => {
+                let (x, y) = split_pair(value, ',')?;
+                renderer.window.position = Some([parse_number(x)?, parse_number(y)?]);
+            }
+            "resizable" => renderer.window.resizable = parse_bool(value)?,
+            "dpi" => {
+                renderer.window.dpi = match value.to_ascii_lowercase().as_str() {
+                    "logical" => DpiMode::Logical,
+                    "physical" => DpiMode::Physical,
+                    _ => return Err(format!("expected logical or physical, got '{}'", value)),
+                }
+            }
+            "gpu" => {
+                renderer.gpu = match value.to_ascii_lowercase().as_str() {
+                    "" | "any" => GpuChoice::FirstSuitable,
+                    "discrete" => GpuChoice::Discrete,
+                    "integrated" => GpuChoice::Integrated,
+                    _ => match value.parse() {
+                        Ok(index) => GpuChoice::Index(index),
+                        Err(_) => GpuChoice::Name(value.to_string()),
+                    },
+                }
+            }
+            "validation" => renderer.validation = parse_bool(value)?,
+            "present-mode" => {
+                renderer.display.vsync = VsyncMode::parse(value).ok_or_else(|| {
+                    format!(
+                        "expected fifo, relaxed, mailbox or immediate, got '{}'",
+                        value
+                    )
+                })?
+            }
+            "msaa" => {
+                let samples: u32 = parse_number(value)?;
+                if !samples.is_power_of_two() || samples > 64 {
+                    return Err(format!(
+                        "expected 1, 2, 4, 8, 16, 32 or 64, got {}",
+                        samples
+                    ));
+                }
+                renderer.msaa_samples = samples;
+            }
+            "hdr" => {
+                renderer.display.surface_format.hdr = match value.to_ascii_lowercase().as_str() {
+                    "off" => HdrMode::Off,
+                    "scrgb" => HdrMode::ExtendedSrgbLinear,
+                    _ => return Err(format!("expected off or scrgb, got '{}'", value)),
+                }
+            }
+            "srgb" => renderer.display.surface_format.prefer_srgb = parse_bool(value)?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+
+        Ok(())
+    }
+}
+
+// the settings the environment may set, as named on the command line
+const KEYS: &[&str] = &[
+    "title",
+    "size",
+    "position",
+    "resizable",
+    "dpi",
+    "gpu",
+    "validation",
+    "present-mode",
+    "msaa",
+    "hdr",
+    "srgb",
+];
+
+// `--key value` and `--key=value` pairs in order, the positional argument as "scene"
+fn parse_args(args: &[String]) -> Result<Vec<(String, String)>, EngineError> {
+    let mut options = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let option = match arg.strip_prefix("--") {
+            Some(option) => option,
+            None => {
+                options.push(("scene".to_string(), arg.clone()));
+                continue;
+            }
+        };
+
+        if option == "help" {
+            options.push(("help".to_string(), String::new()));
+            continue;
+        }
+
+        let (key, value) = match option.find('=') {
+            Some(separator) => (&option[..separator], option[separator + 1..].to_string()),
+            None => match args.next() {
+                Some(value) => (option, value.clone()),
+                None => {
+                    return Err(EngineError::ConfigError(format!(
+                        "--{} needs a value\n\n{}",
+                        option, USAGE
+                    )))
+                }
+            },
+        };
+        options.push((key.to_string(), value));
+    }
+
+    Ok(options)
+}
+
+fn split_pair(value: &str, separator: char) -> Result<(&str, &str), String> {
+    let mut parts = value.splitn(2, separator);
+    match (parts.next(), parts.next()) {
+        (Some(first), Some(second)) => Ok((first.trim(), second.trim())),
+        _ => Err(format!("expected two values separated by '{}'", separator)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid number", value))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, got '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(line: &str, env_vars: &[(&str, &str)]) -> Result<AppSettings, EngineError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        load_args(&args, env_vars)
+    }
+
+    fn load_args(args: &[String], env_vars: &[(&str, &str)]) -> Result<AppSettings, EngineError> {
+        AppSettings::load_with(args, Path::new("no such settings.cfg"), |name| {
+            env_vars
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    fn error(line: &str, env_vars: &[(&str, &str)]) -> String {
+        match load(line, env_vars) {
+            Err(EngineError::ConfigError(message)) => message,
+            other => panic!("not a config error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_line_wins_over_environment_over_file() {
+        // the temp directory may have spaces in its path and be shared with other test runs
+        let path = env::temp_dir().join(format!(
+            "settings precedence test {}.cfg",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            "[window]\ntitle = file\n[renderer]\nmsaa = 2\npresent-mode = mailbox\n",
+        )
+        .unwrap();
+
+        let args = vec![
+            "--config".to_string(),
+            path.display().to_string(),
+            "--msaa".to_string(),
+            "8".to_string(),
+        ];
+        let env_vars = [("APP_MSAA", "4"), ("APP_TITLE", "environment")];
+        let settings = load_args(&args, &env_vars);
+        fs::remove_file(&path).unwrap();
+        let settings = settings.unwrap();
+
+        assert_eq!(settings.renderer.msaa_samples, 8);
+        assert_eq!(settings.renderer.window.title, "environment");
+        assert_eq!(settings.renderer.display.vsync, VsyncMode::LowLatency);
+        // left alone by all three
+        assert_eq!(settings.renderer.window.dpi, DpiMode::Logical);
+    }
+
+    #[test]
+    fn options_take_their_value_after_equals_or_space() {
+        let settings = load("--size=640x480 --title demo --gpu=1 scene.json", &[]).unwrap();
+
+        assert_eq!(settings.renderer.window.size, Some([640, 480]));
+        assert_eq!(settings.renderer.window.title, "demo");
+        assert_eq!(settings.renderer.gpu, GpuChoice::Index(1));
+        assert_eq!(settings.scene_path, Some(PathBuf::from("scene.json")));
+        assert!(!settings.show_help);
+        assert!(load("--help", &[]).unwrap().show_help);
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        assert!(error("--size", &[]).starts_with("--size needs a value"));
+        assert!(error("scene.json --msaa", &[]).starts_with("--msaa needs a value"));
+    }
+
+    #[test]
+    fn msaa_must_be_a_power_of_two() {
+        assert_eq!(load("--msaa 4", &[]).unwrap().renderer.msaa_samples, 4);
+        assert_eq!(
+            error("--msaa 3", &[]),
+            "--msaa: expected 1, 2, 4, 8, 16, 32 or 64, got 3"
+        );
+        assert_eq!(error("--msaa x", &[]), "--msaa: 'x' is not a valid number");
+        assert_eq!(
+            error("", &[("APP_MSAA", "128")]),
+            "APP_MSAA: expected 1, 2, 4, 8, 16, 32 or 64, got 128"
+        );
+    }
+
+    #[test]
+    fn unknown_settings_and_sections_are_errors() {
+        assert_eq!(
+            error("--volume 3", &[]),
+            "--volume: unknown setting 'volume'"
+        );
+
+        let config = ConfigFile::parse("[audio]\nvolume = 3\n", "test.cfg").unwrap();
+        match AppSettings::default().apply_config(&config) {
+            Err(EngineError::ConfigError(message)) => {
+                assert_eq!(message, "test.cfg: unknown section [audio]")
+            }
+            other => panic!("not a config error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::Arc;
+use vulkano::{
+    buffer::{BufferAccess, CpuBufferPool},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::{descriptor_set::PersistentDescriptorSet, PipelineLayoutAbstract},
+    device::Device,
+    format::Format,
+    framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass},
+    image::{AttachmentImage, ImageUsage},
+    pipeline::{
+        vertex::OneVertexOneInstanceDefinition, viewport::Viewport, GraphicsPipeline,
+        GraphicsPipelineAbstract,
+    },
+    sampler::{BorderColor, Filter, MipmapMode, Sampler, SamplerAddressMode},
+};
+
+use crate::{
+    debug_names,
+    error_utils::EngineError,
+    gpu_memory::{self, MemoryCategory, MemoryUsage},
+    instancing::{InstanceData, PreparedDraw},
+    lighting::Lights,
+    math::{Matrix4, Vector3},
+    mesh::Vertex,
+};
+
+// D16 is the only depth format every device can sample from
+pub const SHADOW_MAP_FORMAT: Format = Format::D16Unorm;
+
+/// How the shadow of the first directional light is rendered.
+///
+/// The shadow map covers a fixed box around `center`. Cascaded shadow maps would split the
+/// camera frustum into several of these boxes, each with its own map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    // width and height of the shadow map in texels
+    pub resolution: u32,
+    // depth offset against shadow acne, in shadow map depth units
+    pub depth_bias: f32,
+    // extra offset for surfaces at a grazing angle to the light
+    pub slope_bias: f32,
+    // samples (2r + 1)² texels around the lookup, 0 gives hard shadows
+    pub pcf_radius: u32,
+
+    pub center: Vector3,
+    // half the width of the box covered by the shadow map
+    pub extent: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 2048,
+            depth_bias: 0.0005,
+            slope_bias: 0.005,
+            pcf_radius: 1,
+            center: Vector3::zero(),
+            extent: 5.0,
+        }
+    }
+}
+
+// Matches the layout of UboLightSpace in the shadow vertex shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct UboLightSpace {
+    view_projection: [[f32; 4]; 4],
+}
+
+// Matches the layout of UboShadow in the main fragment shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct UboShadow {
+    light_view_projection: [[f32; 4]; 4],
+    depth_bias: f32,
+    slope_bias: f32,
+    pcf_radius: i32,
+    // index of the light in UboLights, -1 when no light casts shadows
+    caster_index: i32,
+}
+
+/// Depth only pass rendering the scene from the point of view of the first directional light,
+/// sampled by the main pass to find out which fragments that light can not reach.
+pub struct ShadowMap {
+    settings: ShadowSettings,
+
+    image: Arc<AttachmentImage>,
+    sampler: Arc<Sampler>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    dynamic_state: DynamicState,
+
+    light_space_pool: CpuBufferPool<UboLightSpace>,
+    shadow_pool: CpuBufferPool<UboShadow>,
+}
+
+impl ShadowMap {
+    pub fn new(device: Arc<Device>, settings: ShadowSettings) -> Result<Self, EngineError> {
+        let resolution = settings.resolution.max(1);
+
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            [resolution, resolution],
+            SHADOW_MAP_FORMAT,
+            ImageUsage {
+                depth_stencil_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+        )?;
+
+        // everything outside of the map reads as the far plane, so it is never in shadow
+        let border = SamplerAddressMode::ClampToBorder(BorderColor::FloatOpaqueWhite);
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            border,
+            border,
+            border,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )?;
+
+        let render_pass = Self::create_render_pass(device.clone())?;
+        let pipeline = Self::create_pipeline(device.clone(), render_pass.clone())?;
+        debug_names::name_image(&*image, "shadow map");
+        debug_names::name_object(&*sampler, "shadow map sampler");
+        debug_names::name_render_pass(&*render_pass, "shadow render pass");
+        debug_names::name_graphics_pipeline(&*pipeline, "shadow pipeline");
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [resolution as f32, resolution as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            ..DynamicState::none()
+        };
+
+        Ok(ShadowMap {
+            settings,
+            image,
+            sampler,
+            pipeline,
+            framebuffer,
+            dynamic_state,
+            light_space_pool: CpuBufferPool::uniform_buffer(device.clone()),
+            shadow_pool: CpuBufferPool::uniform_buffer(device),
+        })
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    /// Changes the settings that do not need a new map, false when `settings` needs one.
+    pub fn update_settings(&mut self, settings: ShadowSettings) -> bool {
+        if settings.resolution != self.settings.resolution {
+            return false;
+        }
+
+        self.settings = settings;
+        true
+    }
+
+    /// Records the shadow pass. It always runs, even without a directional light, so the map the
+    /// main pass samples is cleared.
+    pub(crate) fn record_shadow_pass(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        draws: &[PreparedDraw],
+        lights: &Lights,
+    ) {
+        let clear_values = vec![1f32.into()];
+        builder
+            .begin_render_pass(self.framebuffer.clone(), false, clear_values)
+            .unwrap();
+
+        if let Some((_, direction)) = lights.shadow_caster() {
+            let light_space = UboLightSpace {
+                view_projection: self.light_view_projection(direction).cols,
+            };
+            let uniform_buffer = self.light_space_pool.next(light_space).unwrap();
+            let layout = self.pipeline.descriptor_set_layout(0).unwrap();
+            let set = Arc::new(
+                PersistentDescriptorSet::start(layout.clone())
+                    .add_buffer(uniform_buffer)
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+
+            for draw in draws.iter().filter(|draw| draw.casts_shadow()) {
+                builder
+                    .draw(
+                        self.pipeline.clone(),
+                        &self.dynamic_state,
+                        draw.vertex_buffers.clone(),
+                        set.clone(),
+                        (),
+                    )
+                    .unwrap();
+            }
+        }
+
+        builder.end_render_pass().unwrap();
+    }
+
+    /// Parameters the main pass needs to sample the map, from a new buffer every frame.
+    pub(crate) fn shadow_uniform(&self, lights: &Lights) -> impl BufferAccess + Send + Sync {
+        let (caster_index, view_projection) = match lights.shadow_caster() {
+            Some((index, direction)) => (index as i32, self.light_view_projection(direction)),
+            None => (-1, Matrix4::identity()),
+        };
+
+        let shadow = UboShadow {
+            light_view_projection: view_projection.cols,
+            depth_bias: self.settings.depth_bias,
+            slope_bias: self.settings.slope_bias,
+            pcf_radius: self.settings.pcf_radius as i32,
+            caster_index,
+        };
+
+        self.shadow_pool.next(shadow).unwrap()
+    }
+
+    pub(crate) fn image(&self) -> Arc<AttachmentImage> {
+        self.image.clone()
+    }
+
+    pub(crate) fn sampler(&self) -> Arc<Sampler> {
+        self.sampler.clone()
+    }
+
+    pub(crate) fn memory_usage(&self, usage: &mut MemoryUsage) {
+        let resolution = self.settings.resolution.max(1);
+        usage.add(
+            MemoryCategory::RenderTargets,
+            gpu_memory::image_bytes([resolution, resolution], SHADOW_MAP_FORMAT),
+        );
+        usage.add(
+            MemoryCategory::Staging,
+            gpu_memory::pool_bytes(&self.light_space_pool)
+                + gpu_memory::pool_bytes(&self.shadow_pool),
+        );
+    }
+
+    fn create_render_pass(
+        device: Arc<Device>,
+    ) -> Result<Arc<dyn RenderPassAbstract + Send + Sync>, EngineError> {
+        let render_pass = Arc::new(
+            vulkano::single_pass_renderpass!(device,
+                attachments: {
+                    depth: {
+                        load: Clear,
+                        store: Store,
+                        format: SHADOW_MAP_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [],
+                    depth_stencil: {depth}
+                }
+            )
+            .unwrap(),
+        );
+
+        Ok(render_pass)
+    }
+
+    fn create_pipeline(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
+        mod vertex_shader {
+            vulkano_shaders::shader! {
+                ty: "vertex",
+                src: "
+                #version 450
+
+                layout(set = 0, binding = 0) uniform UboLightSpace {
+                    mat4 viewProjection;
+                } uboLightSpace;
+
+                layout(location = 0) in vec3 position;
+                layout(location = 4) in mat4 model;
+
+                void main() {
+                    gl_Position = uboLightSpace.viewProjection * model * vec4(position, 1.0);
+                }"
+            }
+        }
+
+        // only the depth is written
+        mod fragment_shader {
+            vulkano_shaders::shader! {
+                ty: "fragment",
+                src: "
+                #version 450
+
+                void main() {
+                }"
+            }
+        }
+
+        let vertex_shader = vertex_shader::Shader::load(device.clone())?;
+        let fragment_shader = fragment_shader::Shader::load(device.clone())?;
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<Vertex, InstanceData>::new())
+                .vertex_shader(vertex_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                // single sided geometry such as the triangle must cast a shadow from both sides
+                .cull_mode_disabled()
+                .depth_stencil_simple_depth()
+                .fragment_shader(fragment_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass, 0).unwrap())
+                .build(device)?,
+        );
+
+        Ok(pipeline)
+    }
+
+    fn light_view_projection(&self, direction: Vector3) -> Matrix4 {
+        let extent = self.settings.extent;
+        // far enough back for everything in the box to be in front of the light
+        let eye = self.settings.center - direction * (extent * 2.0);
+        let up = if direction.y.abs() > 0.99 {
+            Vector3::new(0.0, 0.0, -1.0)
+        } else {
+            Vector3::up()
+        };
+
+        let view = Matrix4::look_at(eye, self.settings.center, up);
+        let projection = Matrix4::orthographic(-extent, extent, -extent, extent, 0.0, extent * 4.0);
+
+        projection * view
+    }
+}
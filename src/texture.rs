@@ -0,0 +1,89 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::{
+    device::Queue,
+    format::Format,
+    image::{Dimensions, ImmutableImage},
+    sync::GpuFuture,
+};
+
+use crate::{debug_names, error_utils::EngineError};
+
+/// Colour texture sampled by materials, stored as sRGB.
+pub struct Texture {
+    // file the texture was loaded from, None for generated ones
+    path: Option<PathBuf>,
+    image: Arc<ImmutableImage<Format>>,
+}
+
+impl Texture {
+    /// Loads a PNG, JPEG or any other format the image crate reads. Blocks until the upload is
+    /// done.
+    pub fn load(queue: Arc<Queue>, path: &Path) -> Result<Arc<Self>, EngineError> {
+        let pixels = image::open(path)?.to_rgba();
+        let (width, height) = pixels.dimensions();
+
+        Self::from_pixels(
+            queue,
+            Some(path.to_path_buf()),
+            width,
+            height,
+            pixels.into_raw(),
+        )
+    }
+
+    /// Single white texel, bound when a material has no texture.
+    pub fn white(queue: Arc<Queue>) -> Result<Arc<Self>, EngineError> {
+        Self::from_pixels(queue, None, 1, 1, vec![255; 4])
+    }
+
+    fn from_pixels(
+        queue: Arc<Queue>,
+        path: Option<PathBuf>,
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    ) -> Result<Arc<Self>, EngineError> {
+        let (image, upload) = ImmutableImage::from_iter(
+            rgba.into_iter(),
+            Dimensions::Dim2d { width, height },
+            Format::R8G8B8A8Srgb,
+            queue,
+        )?;
+        upload.then_signal_fence_and_flush()?.wait(None)?;
+        let name = match &path {
+            Some(path) => path.display().to_string(),
+            None => String::from("generated texture"),
+        };
+        debug_names::name_image(&*image, &name);
+
+        Ok(Arc::new(Texture { path, image }))
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.image.dimensions().width_height()
+    }
+
+    pub(crate) fn image(&self) -> Arc<ImmutableImage<Format>> {
+        self.image.clone()
+    }
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [width, height] = self.dimensions();
+        write!(f, "Texture({:?}, {}x{})", self.path, width, height)
+    }
+}
+
+// same image on the GPU
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.image, &other.image)
+    }
+}
@@ -1,89 +1,1045 @@
+use image::{ImageBuffer, Rgba};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer},
-    command_buffer::{AutoCommandBufferBuilder, DynamicState},
-    descriptor::PipelineLayoutAbstract,
-    device::{Device, DeviceExtensions, Queue, QueuesIter},
-    format::Format,
-    framebuffer::{
-        Framebuffer, FramebufferAbstract, RenderPass, RenderPassAbstract, RenderPassDesc, Subpass,
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, CpuBufferPool},
+    command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState},
+    descriptor::{
+        descriptor_set::{DescriptorSet, PersistentDescriptorSet},
+        PipelineLayoutAbstract,
     },
-    image::{ImageLayout, ImageUsage, SwapchainImage},
+    device::{Device, DeviceExtensions, Queue},
+    format::{ClearValue, Format, FormatTy},
+    framebuffer::{FramebufferAbstract, LoadOp, RenderPassAbstract, RenderPassDesc},
+    image::AttachmentImage,
     instance::{
         debug::{DebugCallback, MessageSeverity, MessageType},
         layers_list, ApplicationInfo, Instance, InstanceExtensions, PhysicalDevice, QueueFamily,
         Version,
     },
-    pipeline::{
-        blend::{AttachmentBlend, BlendFactor},
-        vertex::SingleBufferDefinition,
-        viewport::Viewport,
-        GraphicsPipeline, GraphicsPipelineAbstract,
-    },
-    swapchain::{
-        acquire_next_image, AcquireError, ColorSpace, FullscreenExclusive, PresentMode,
-        SupportedPresentModes, Surface, SurfaceTransform, Swapchain, SwapchainCreationError,
-    },
-    sync::{now, FlushError, GpuFuture, SharingMode},
+    pipeline::GraphicsPipelineAbstract,
+    sampler::Sampler,
+    swapchain::{acquire_next_image, AcquireError, Surface},
+    sync::{now, FlushError, GpuFuture},
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize},
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder, WindowId},
 };
 
-use crate::{error_utils::EngineError, utilities::QueueFamilyIndices};
+use crate::{
+    camera::Camera,
+    compute::{self, ComputeDispatch},
+    debug_draw::{DebugDraw, DebugRenderer, PreparedDebugLines},
+    debug_names,
+    device_features::{self, OptionalFeatures},
+    display_settings::{self, DisplaySettings, WindowMode},
+    error_utils::EngineError,
+    frame_stats::FrameTimings,
+    gpu_memory::{self, MemoryCategory, MemoryReport, MemoryUsage},
+    gpu_profiler::{GpuProfiler, GpuRegionTiming},
+    instancing::{DrawQueue, MeshInstance, PreparedDraw},
+    lighting::{Lights, UboLights},
+    material::{Material, MaterialBindings, ShaderProgram},
+    mesh::{MeshSource, Vertex},
+    overlay::{OverlayFrame, OverlayRenderer},
+    pipeline_cache::{PipelineCache, PolygonMode},
+    render_window::RenderWindow,
+    resources::{MeshHandle, Resources},
+    scene::Scene,
+    scene_file,
+    settings::{DpiMode, GpuChoice, RendererSettings, WindowSettings},
+    shadows::{ShadowMap, ShadowSettings},
+    utilities::QueueFamilyIndices,
+};
 
 const VALIDATION_LAYERS: &[&str] = &["VK_LAYER_KHRONOS_validation"];
 
-#[cfg(all(debug_assertions))]
-const ENABLE_VALIDATION_LAYERS: bool = true;
+pub(crate) const WINDOW_TITLE: &str = "Udemy tutorial";
+pub const DEPTH_FORMAT: Format = Format::D16Unorm;
+
+#[cfg(debug_assertions)]
+pub(crate) const ENABLE_VALIDATION_LAYERS: bool = true;
 #[cfg(not(debug_assertions))]
-const ENABLE_VALIDATION_LAYERS: bool = false;
+pub(crate) const ENABLE_VALIDATION_LAYERS: bool = false;
+
+// Matches the layout of UboViewProjection in the vertex shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct UboViewProjection {
+    projection: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+}
+
+/// What set 0 holds for a frame: the camera, the lights and their shadow map. Uploaded once and
+/// bound with the layout of each pipeline of the main pass; unlit pipelines only take the camera.
+pub(crate) struct FrameUniforms {
+    view_projection: Arc<dyn BufferAccess + Send + Sync>,
+    lights: Arc<dyn BufferAccess + Send + Sync>,
+    shadow: Arc<dyn BufferAccess + Send + Sync>,
+    shadow_map: Arc<AttachmentImage>,
+    shadow_sampler: Arc<Sampler>,
+}
+
+impl FrameUniforms {
+    // the uniform buffers come from the pools every frame, so frames in flight keep their own
+    // values
+    pub fn new(
+        view_projection_pool: &CpuBufferPool<UboViewProjection>,
+        lights_pool: &CpuBufferPool<UboLights>,
+        shadow_map: &ShadowMap,
+        camera: &Camera,
+        lights: &Lights,
+        aspect_ratio: f32,
+    ) -> Self {
+        let view_projection = UboViewProjection {
+            projection: camera.projection_matrix(aspect_ratio).cols,
+            view: camera.view_matrix().cols,
+        };
+
+        FrameUniforms {
+            view_projection: Arc::new(view_projection_pool.next(view_projection).unwrap()),
+            lights: Arc::new(lights_pool.next(lights.uniform(camera.position)).unwrap()),
+            shadow: Arc::new(shadow_map.shadow_uniform(lights)),
+            shadow_map: shadow_map.image(),
+            shadow_sampler: shadow_map.sampler(),
+        }
+    }
+
+    pub fn descriptor_set(
+        &self,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        shader: ShaderProgram,
+    ) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let layout = pipeline.descriptor_set_layout(0).unwrap();
+        let set = PersistentDescriptorSet::start(layout.clone())
+            .add_buffer(self.view_projection.clone())
+            .unwrap();
+
+        match shader {
+            ShaderProgram::Lit => Arc::new(
+                set.add_buffer(self.lights.clone())
+                    .unwrap()
+                    .add_buffer(self.shadow.clone())
+                    .unwrap()
+                    .add_sampled_image(self.shadow_map.clone(), self.shadow_sampler.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            ),
+            ShaderProgram::Unlit => Arc::new(set.build().unwrap()),
+        }
+    }
+}
+
+/// Pixels read back from the swapchain, already converted to RGBA8 and sRGB encoded.
+pub type CapturedFrame = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+// the presented image copied to a host visible buffer, sized from the image actually drawn since
+// the swapchain may be recreated by the frame
+struct FrameCapture {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    dimensions: [u32; 2],
+    format: Format,
+}
 
-#[derive(Default, Debug, Clone)]
-struct Vertex {
-    position: [f32; 3],
+// the command buffer of a pass, with the name of its GPU profiler region
+type Pass = (&'static str, AutoCommandBuffer);
+
+// the device with its graphics and presentation queues, which may be the same queue
+type DeviceQueues = (Arc<Device>, Arc<Queue>, Arc<Queue>);
+
+// the queued work a frame consumes
+struct FrameInput {
+    draws: Vec<PreparedDraw>,
+    dispatches: Vec<ComputeDispatch>,
+    debug_lines: PreparedDebugLines,
+}
+
+// what rendering a frame to a window did
+enum FrameOutcome {
+    // the swapchain was out of date, nothing was drawn
+    Skipped,
+    // with the copy of the image when a capture was requested
+    Presented(Option<FrameCapture>),
 }
 
-#[allow(unused)]
 pub struct VulkanRenderer {
     pub instance: Arc<Instance>,
     pub device: Arc<Device>,
 
+    graphics_queue: Arc<Queue>,
+    presentation_queue: Arc<Queue>,
+
+    windows: HashMap<WindowId, RenderWindow>,
+    // the window created with the renderer, captures and the overlay panels go there
+    primary_window: WindowId,
+    // pipelines are shared, so every window renders in the format of the first one
+    surface_format: Format,
+    // of the main pass, 1 when it is not multisampled
+    msaa_samples: u32,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    pipelines: PipelineCache,
+
+    draw_queue: DrawQueue,
+    materials: MaterialBindings,
+    // used by `draw_instanced`
+    default_material: Arc<Material>,
+    resources: Resources,
+
+    view_projection_pool: CpuBufferPool<UboViewProjection>,
+
+    lights: Lights,
+    lights_pool: CpuBufferPool<UboLights>,
+    shadow_map: ShadowMap,
+    debug_draw: DebugDraw,
+    debug_renderer: DebugRenderer,
+    // recorded at the start of the next frame
+    compute_dispatches: Vec<ComputeDispatch>,
+    overlay_renderer: OverlayRenderer,
+
+    // keeps the GPU work of the last submitted frame, of any window, so we can wait for it
+    // before reusing resources
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+
+    // None when the graphics queue can not write timestamps
+    gpu_profiler: Option<GpuProfiler>,
+
     // must live to keep working
-    surface: Arc<Surface<Window>>,
-    debug_callback: Option<DebugCallback>,
+    _debug_callback: Option<DebugCallback>,
 }
 
 impl VulkanRenderer {
-    pub fn init(event_loop: &EventLoop<()>) -> Result<Self, EngineError> {
-        let instance = Self::create_instance()?;
-        let debug_callback = Self::setup_debug_callback(&instance);
-        let surface = Self::create_surface(instance.clone(), &event_loop)?;
-        let physycal_device = Self::get_physical_device(&instance, &surface)?;
-        let (device, mut queues) = Self::create_logical_device(physycal_device, &surface)?;
-        let (swapchain, images) = Self::create_swapchain(
-            physycal_device,
-            surface.clone(),
+    pub fn init(
+        event_loop: &EventLoopWindowTarget<()>,
+        settings: RendererSettings,
+    ) -> Result<Self, EngineError> {
+        let instance = Self::create_instance(settings.validation)?;
+        let debug_callback = Self::setup_debug_callback(&instance, settings.validation);
+        let surface = Self::create_surface(instance.clone(), event_loop, &settings.window)?;
+        let physycal_device = Self::get_physical_device(&instance, &surface, &settings.gpu)?;
+        let (device, graphics_queue, presentation_queue) =
+            Self::create_logical_device(physycal_device, &surface)?;
+        let surface_capabilities = surface.capabilities(physycal_device)?;
+        let (surface_format, colour_space) = settings
+            .display
+            .surface_format
+            .choose(&surface_capabilities.supported_formats)?;
+        let msaa_samples = Self::supported_samples(physycal_device, settings.msaa_samples);
+        println!("Surface format: {:?} in {:?}", surface_format, colour_space);
+        let mut window = RenderWindow::new(
             device.clone(),
-            &mut queues,
+            graphics_queue.clone(),
+            presentation_queue.clone(),
+            surface,
+            settings.display,
+            (surface_format, colour_space),
         )?;
-        let pipeline = Self::create_graphic_pipeline(device.clone(), swapchain.clone())?;
+        window.set_title(&settings.window.title);
+        let render_pass =
+            Self::create_render_pass(device.clone(), surface_format, true, msaa_samples)?;
+        let pipelines = PipelineCache::new(device.clone(), render_pass.clone(), surface_format)?;
+        let materials = MaterialBindings::new(device.clone(), graphics_queue.clone())?;
+        let resources = Resources::new(
+            graphics_queue.clone(),
+            window.swapchain_images().len() as u64,
+        );
 
-        let result = VulkanRenderer {
-            instance: instance,
-            device,
-            surface,
-            debug_callback,
+        let gpu_profiler = GpuProfiler::new(device.clone(), graphics_queue.clone())?;
+        let shadow_map = ShadowMap::new(device.clone(), ShadowSettings::default())?;
+        let debug_renderer =
+            DebugRenderer::new(device.clone(), render_pass.clone(), surface_format)?;
+        let overlay_renderer =
+            OverlayRenderer::new(device.clone(), graphics_queue.clone(), surface_format, true)?;
+        window.build_framebuffers(&render_pass, &overlay_renderer)?;
+
+        let primary_window = window.id();
+        let mut result = VulkanRenderer {
+            instance: instance.clone(),
+            device: device.clone(),
+            graphics_queue,
+            presentation_queue,
+            windows: HashMap::new(),
+            primary_window,
+            surface_format,
+            msaa_samples,
+            render_pass,
+            pipelines,
+            draw_queue: DrawQueue::new(device.clone()),
+            materials,
+            default_material: Arc::new(Material::default()),
+            resources,
+            view_projection_pool: CpuBufferPool::uniform_buffer(device.clone()),
+            lights: Lights::default(),
+            lights_pool: CpuBufferPool::uniform_buffer(device.clone()),
+            shadow_map,
+            debug_draw: DebugDraw::default(),
+            debug_renderer,
+            compute_dispatches: Vec::new(),
+            overlay_renderer,
+            previous_frame_end: Some(now(device).boxed()),
+            gpu_profiler,
+            _debug_callback: debug_callback,
         };
+        result.insert_window(window)?;
 
         Ok(result)
     }
 
-    fn create_instance() -> Result<Arc<Instance>, EngineError> {
-        if ENABLE_VALIDATION_LAYERS {
+    /// Opens another window drawn by the same device, with its own camera, swapchain and
+    /// display settings. The surface format policy of `display` is ignored: the pipelines are
+    /// shared, so the window renders in the format of the primary window.
+    pub fn add_window(
+        &mut self,
+        event_loop: &EventLoopWindowTarget<()>,
+        window_settings: &WindowSettings,
+        display: DisplaySettings,
+    ) -> Result<WindowId, EngineError> {
+        let surface = Self::create_surface(self.instance.clone(), event_loop, window_settings)?;
+        if !surface.is_supported(self.presentation_queue.family())? {
+            return Err(EngineError::VulkanValidationError(String::from(
+                "The presentation queue can not present to the new window",
+            )));
+        }
+
+        let capabilities = surface.capabilities(self.device.physical_device())?;
+        let colour_space = capabilities
+            .supported_formats
+            .iter()
+            .find(|(format, _)| *format == self.surface_format)
+            .map(|&(_, colour_space)| colour_space)
+            .ok_or_else(|| {
+                EngineError::VulkanValidationError(format!(
+                    "The new window does not support the {:?} format of the others",
+                    self.surface_format
+                ))
+            })?;
+
+        let mut window = RenderWindow::new(
+            self.device.clone(),
+            self.graphics_queue.clone(),
+            self.presentation_queue.clone(),
+            surface,
+            display,
+            (self.surface_format, colour_space),
+        )?;
+        window.set_title(&window_settings.title);
+        window.build_framebuffers(&self.render_pass, &self.overlay_renderer)?;
+
+        let id = window.id();
+        self.insert_window(window)?;
+
+        Ok(id)
+    }
+
+    /// Closes a window opened with `add_window`. The primary window lives as long as the
+    /// renderer.
+    pub fn close_window(&mut self, id: WindowId) -> Result<(), EngineError> {
+        if id == self.primary_window {
+            return Err(EngineError::VulkanValidationError(String::from(
+                "The primary window can not be closed",
+            )));
+        }
+
+        // frames in flight keep the swapchain alive, the window goes away right now
+        self.windows.remove(&id);
+        self.update_frames_in_flight();
+
+        Ok(())
+    }
+
+    // applies the window mode of the settings, the swapchain picks up the new size on the
+    // first frame
+    fn insert_window(&mut self, mut window: RenderWindow) -> Result<(), EngineError> {
+        let window_mode = window.window_mode();
+        if window_mode != WindowMode::Windowed {
+            window.set_window_mode(window_mode)?;
+        }
+
+        self.windows.insert(window.id(), window);
+        self.update_frames_in_flight();
+
+        Ok(())
+    }
+
+    // every window presents in turn, so a resource is only done with once all their frames are
+    fn update_frames_in_flight(&mut self) {
+        let frames_in_flight = self
+            .windows
+            .values()
+            .map(|window| window.swapchain_images().len() as u64)
+            .sum();
+        self.resources.set_frames_in_flight(frames_in_flight);
+    }
+
+    pub fn primary_window(&self) -> WindowId {
+        self.primary_window
+    }
+
+    pub fn primary(&self) -> &RenderWindow {
+        &self.windows[&self.primary_window]
+    }
+
+    pub fn primary_mut(&mut self) -> &mut RenderWindow {
+        self.windows.get_mut(&self.primary_window).unwrap()
+    }
+
+    pub fn render_window_mut(&mut self, id: WindowId) -> Option<&mut RenderWindow> {
+        self.windows.get_mut(&id)
+    }
+
+    pub fn create_mesh(&mut self, vertices: &[Vertex]) -> Result<MeshHandle, EngineError> {
+        self.resources.create_mesh(vertices)
+    }
+
+    /// Creates a mesh from a primitive or a file, which scenes can save back to disk.
+    pub fn load_mesh(&mut self, name: &str, source: MeshSource) -> Result<MeshHandle, EngineError> {
+        self.resources.load_mesh(name, source)
+    }
+
+    pub fn load_scene(&mut self, path: &Path) -> Result<Scene, EngineError> {
+        scene_file::load_scene(&mut self.resources, path)
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// What the renderer allocated in each category, next to the heaps of the device and their
+    /// budget when the driver reports it.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut usage = MemoryUsage::default();
+        self.resources.memory_usage(&mut usage);
+        self.draw_queue.memory_usage(&mut usage);
+        self.materials.memory_usage(&mut usage);
+        self.shadow_map.memory_usage(&mut usage);
+        self.debug_renderer.memory_usage(&mut usage);
+        self.overlay_renderer.memory_usage(&mut usage);
+
+        for window in self.windows.values() {
+            let swapchain = window.swapchain();
+            let dimensions = swapchain.dimensions();
+            let swapchain_bytes = gpu_memory::image_bytes(dimensions, swapchain.format());
+            let samples = self.msaa_samples as u64;
+            let multisampled_colour_bytes = if samples > 1 {
+                swapchain_bytes * samples
+            } else {
+                0
+            };
+            usage.add(
+                MemoryCategory::RenderTargets,
+                swapchain_bytes * window.swapchain_images().len() as u64
+                    + multisampled_colour_bytes
+                    + gpu_memory::image_bytes(dimensions, DEPTH_FORMAT) * samples,
+            );
+        }
+        usage.add(
+            MemoryCategory::Staging,
+            gpu_memory::pool_bytes(&self.view_projection_pool)
+                + gpu_memory::pool_bytes(&self.lights_pool),
+        );
+
+        MemoryReport::new(self.device.physical_device(), usage)
+    }
+
+    /// Queues `mesh` to be drawn once per instance in the next frame, with a single draw call,
+    /// using the default material.
+    pub fn draw_instanced(&mut self, mesh: &MeshHandle, instances: &[MeshInstance]) {
+        let material = self.default_material.clone();
+        self.draw_with_material(mesh, &material, instances);
+    }
+
+    /// Like `draw_instanced`, with the shaders, parameters and render state of `material`.
+    pub fn draw_with_material(
+        &mut self,
+        mesh: &MeshHandle,
+        material: &Arc<Material>,
+        instances: &[MeshInstance],
+    ) {
+        self.draw_queue.push(mesh, material, instances);
+    }
+
+    /// Queues the meshes of the scene for the next frame and replaces the lights with the
+    /// scene's lights.
+    pub fn draw_scene(&mut self, scene: &mut Scene) {
+        scene.submit(&mut self.draw_queue, &mut self.lights);
+    }
+
+    /// Lines, boxes and other debug primitives drawn over the next frame.
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    /// User interface drawn over the next frame of a window, after everything else. Set it
+    /// again every frame it should stay on screen.
+    pub fn set_overlay(
+        &mut self,
+        id: WindowId,
+        overlay: Option<OverlayFrame>,
+    ) -> Result<(), EngineError> {
+        if let Some(overlay) = &overlay {
+            self.overlay_renderer.prepare(overlay)?;
+        }
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.set_overlay(overlay);
+        }
+
+        Ok(())
+    }
+
+    /// Queues a dispatch for the next frame, before the passes drawing it so they can read what
+    /// it writes. The frame empties the queue, so a dispatch runs once, and a skipped frame
+    /// drops it like the queued draws.
+    pub fn dispatch_compute(&mut self, dispatch: ComputeDispatch) {
+        self.compute_dispatches.push(dispatch);
+    }
+
+    /// Runs the dispatches once the frames in flight are done, and waits for them.
+    pub fn run_compute(&mut self, dispatches: &[ComputeDispatch]) -> Result<(), EngineError> {
+        let after = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| now(self.device.clone()).boxed());
+        let result = compute::run(
+            self.device.clone(),
+            self.graphics_queue.clone(),
+            after,
+            dispatches,
+        );
+        self.previous_frame_end = Some(now(self.device.clone()).boxed());
+
+        result
+    }
+
+    pub fn polygon_mode(&self) -> PolygonMode {
+        self.pipelines.polygon_mode()
+    }
+
+    /// Switches the main pass between filled triangles, their edges and their vertices.
+    pub fn set_polygon_mode(&mut self, polygon_mode: PolygonMode) {
+        self.pipelines.set_polygon_mode(polygon_mode);
+    }
+
+    pub fn polygon_modes_emulated(&self) -> bool {
+        self.pipelines.polygon_modes_emulated()
+    }
+
+    /// Draws line and point modes the way devices without fill_mode_non_solid do.
+    pub fn set_polygon_modes_emulated(&mut self, emulated: bool) {
+        self.pipelines.set_polygon_modes_emulated(emulated);
+    }
+
+    /// Number of pipelines built so far, one per shader program and render state drawn.
+    pub fn pipeline_count(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    /// Optional features the device was created with, the others have fallbacks.
+    pub fn optional_features(&self) -> OptionalFeatures {
+        OptionalFeatures::enabled_on(&self.device)
+    }
+
+    /// Samples per pixel of the main pass, 1 when it is not multisampled.
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// The sample counts `set_msaa_samples` takes on this device, from 1.
+    pub fn supported_msaa_samples(&self) -> Vec<u32> {
+        let supported = Self::sample_counts(self.device.physical_device());
+
+        (0..7)
+            .map(|bit| 1 << bit)
+            .filter(|samples| supported & samples != 0)
+            .collect()
+    }
+
+    /// Changes the samples per pixel of the main pass like `--msaa` does, lowered to what the
+    /// device supports. Rebuilds the render pass with its pipelines and the framebuffers of
+    /// every window, frames in flight keep the old ones.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<(), EngineError> {
+        let samples = Self::supported_samples(self.device.physical_device(), samples);
+        if samples == self.msaa_samples {
+            return Ok(());
+        }
+
+        let render_pass =
+            Self::create_render_pass(self.device.clone(), self.surface_format, true, samples)?;
+        self.debug_renderer = DebugRenderer::new(
+            self.device.clone(),
+            render_pass.clone(),
+            self.surface_format,
+        )?;
+        self.pipelines.set_render_pass(render_pass.clone());
+        for window in self.windows.values_mut() {
+            window.build_framebuffers(&render_pass, &self.overlay_renderer)?;
+        }
+        self.render_pass = render_pass;
+        self.msaa_samples = samples;
+
+        Ok(())
+    }
+
+    pub fn shadow_settings(&self) -> &ShadowSettings {
+        self.shadow_map.settings()
+    }
+
+    /// Changes the shadow settings. A new resolution recreates the shadow map, waiting for the
+    /// frames using the old one.
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) -> Result<(), EngineError> {
+        if self.shadow_map.update_settings(settings) {
+            return Ok(());
+        }
+
+        if let Some(previous_frame_end) = self.previous_frame_end.take() {
+            previous_frame_end
+                .then_signal_fence_and_flush()?
+                .wait(None)?;
+        }
+        self.previous_frame_end = Some(now(self.device.clone()).boxed());
+
+        self.shadow_map = ShadowMap::new(self.device.clone(), settings)?;
+
+        Ok(())
+    }
+
+    /// Renders the queued draws to the primary window.
+    pub fn draw(&mut self) -> Result<(), EngineError> {
+        self.draw_window(self.primary_window)
+    }
+
+    /// Renders the queued draws to a window, with its camera, and empties the queue. Windows
+    /// showing the same scene each get it queued again before they are drawn.
+    ///
+    /// The queue is emptied even when the frame is skipped, while the window is minimized or
+    /// its swapchain is out of date, so the draws are not piled onto the next frame.
+    pub fn draw_window(&mut self, id: WindowId) -> Result<(), EngineError> {
+        self.render_frame(id)?;
+
+        Ok(())
+    }
+
+    /// GPU time of each profiled region of the frame, empty when timestamps are not supported.
+    pub fn gpu_timings(&self) -> &[GpuRegionTiming] {
+        match &self.gpu_profiler {
+            Some(profiler) => profiler.timings(),
+            None => &[],
+        }
+    }
+
+    /// Renders the queued draws to the primary window, like `draw`, and copies the swapchain
+    /// image that is about to be presented into a host visible buffer. Blocks until the GPU has
+    /// finished the frame.
+    pub fn capture_frame(&mut self) -> Result<CapturedFrame, EngineError> {
+        if !self.primary().supports_capture() {
+            return Err(EngineError::VulkanCaptureError(String::from(
+                "Swapchain images can not be used as transfer source",
+            )));
+        }
+        // the capture buffer holds four bytes a pixel, checked before the frame is rendered
+        Self::capture_swaps_red_and_blue(self.surface_format)?;
+
+        // an out of date swapchain skips the frame, so we try again with the recreated one and
+        // the same draws, the queue is empty by then
+        let mut input = self.take_frame_input();
+        let mut attempts = 0;
+        let capture = loop {
+            if let FrameOutcome::Presented(Some(capture)) =
+                self.render_prepared_frame(self.primary_window, &mut input, true)?
+            {
+                break capture;
+            }
+            attempts += 1;
+            if attempts > 2 {
+                return Err(EngineError::VulkanCaptureError(String::from(
+                    "Swapchain kept being out of date while capturing",
+                )));
+            }
+        };
+
+        let [width, height] = capture.dimensions;
+        let pixels = capture.buffer.read().map_err(|err| {
+            EngineError::VulkanCaptureError(format!("Could not read capture buffer: {:?}", err))
+        })?;
+        let rgba = Self::pixels_to_rgba(capture.format, &pixels)?;
+
+        ImageBuffer::from_raw(width, height, rgba).ok_or_else(|| {
+            EngineError::VulkanCaptureError(String::from(
+                "Capture buffer size does not match swapchain dimensions",
+            ))
+        })
+    }
+
+    pub fn save_frame(&mut self, path: &Path) -> Result<(), EngineError> {
+        let frame = self.capture_frame()?;
+        frame.save(path)?;
+
+        Ok(())
+    }
+
+    fn render_frame(&mut self, id: WindowId) -> Result<FrameOutcome, EngineError> {
+        let mut input = self.take_frame_input();
+        self.render_prepared_frame(id, &mut input, false)
+    }
+
+    // taken before anything can skip the frame, a skipped frame drops them
+    fn take_frame_input(&mut self) -> FrameInput {
+        FrameInput {
+            draws: self.draw_queue.prepare(),
+            dispatches: std::mem::take(&mut self.compute_dispatches),
+            debug_lines: self.debug_renderer.prepare(&mut self.debug_draw),
+        }
+    }
+
+    fn render_prepared_frame(
+        &mut self,
+        id: WindowId,
+        input: &mut FrameInput,
+        capture: bool,
+    ) -> Result<FrameOutcome, EngineError> {
+        // out of the map while it is drawn, so the shared state can be borrowed next to it
+        let mut window = self.windows.remove(&id).ok_or_else(|| {
+            EngineError::VulkanValidationError(format!("{:?} is not a window of the renderer", id))
+        })?;
+        let result = self.render_window_frame(&mut window, input, capture);
+        self.windows.insert(id, window);
+
+        result
+    }
+
+    fn render_window_frame(
+        &mut self,
+        window: &mut RenderWindow,
+        input: &mut FrameInput,
+        capture: bool,
+    ) -> Result<FrameOutcome, EngineError> {
+        let frame_time = window.begin_frame();
+
+        // frees resources of frames the GPU has already finished
+        if let Some(previous_frame_end) = self.previous_frame_end.as_mut() {
+            previous_frame_end.cleanup_finished();
+        }
+
+        if window.needs_recreation() {
+            if !window.recreate_swapchain(&self.render_pass, &self.overlay_renderer)? {
+                return Ok(FrameOutcome::Skipped);
+            }
+            self.update_frames_in_flight();
+        }
+
+        let acquire_start = Instant::now();
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(window.swapchain().clone(), None) {
+                Ok(result) => result,
+                Err(AcquireError::OutOfDate) => {
+                    window.mark_out_of_date();
+                    return Ok(FrameOutcome::Skipped);
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+        let acquire_time = acquire_start.elapsed();
+
+        if suboptimal {
+            window.mark_out_of_date();
+        }
+
+        let (passes, capture) = self.record_passes(window, image_index, input, capture)?;
+
+        if let Some(profiler) = self.gpu_profiler.as_mut() {
+            profiler.begin_frame();
+        }
+
+        let present_start = Instant::now();
+        let mut future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| now(self.device.clone()).boxed())
+            .join(acquire_future)
+            .boxed();
+        for (region, command_buffer) in passes {
+            future = self.execute_pass(future, region, command_buffer)?;
+        }
+
+        let future = future
+            .then_swapchain_present(
+                self.presentation_queue.clone(),
+                window.swapchain().clone(),
+                image_index,
+            )
+            .then_signal_fence_and_flush();
+        let present_time = present_start.elapsed();
+        self.resources.end_frame();
+
+        if let Some(profiler) = self.gpu_profiler.as_mut() {
+            profiler.end_frame(future.is_ok());
+        }
+
+        match future {
+            Ok(future) => {
+                let timings = frame_time.map(|frame_time| FrameTimings {
+                    frame: frame_time,
+                    acquire: acquire_time,
+                    present: present_time,
+                });
+                window.end_frame(timings, self.gpu_timings());
+
+                if capture.is_some() {
+                    // the capture buffer is only filled once the GPU reaches the fence
+                    future.wait(None)?;
+                }
+                self.previous_frame_end = Some(future.boxed());
+                Ok(FrameOutcome::Presented(capture))
+            }
+            Err(FlushError::OutOfDate) => {
+                window.mark_out_of_date();
+                self.previous_frame_end = Some(now(self.device.clone()).boxed());
+                Ok(FrameOutcome::Skipped)
+            }
+            Err(err) => {
+                self.previous_frame_end = Some(now(self.device.clone()).boxed());
+                Err(err.into())
+            }
+        }
+    }
+
+    // executes the command buffer of a pass after `future`, inside the debug label of its
+    // region and between its timestamps
+    fn execute_pass(
+        &mut self,
+        future: Box<dyn GpuFuture>,
+        region: &'static str,
+        command_buffer: AutoCommandBuffer,
+    ) -> Result<Box<dyn GpuFuture>, EngineError> {
+        let queue = self.graphics_queue.clone();
+
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        if let Some((begin, end)) = debug_names::labels(&self.device, &queue, region)? {
+            before.push(begin);
+            after.push(end);
+        }
+        let timestamps = match self.gpu_profiler.as_mut() {
+            Some(profiler) => profiler.region(region)?,
+            None => None,
+        };
+        if let Some((begin, end)) = timestamps {
+            before.push(begin);
+            after.insert(0, end);
+        }
+
+        let mut future = future;
+        for begin in before {
+            future = future.then_execute(queue.clone(), begin)?.boxed();
+        }
+        future = future.then_execute(queue.clone(), command_buffer)?.boxed();
+        for end in after {
+            future = future.then_execute(queue.clone(), end)?.boxed();
+        }
+
+        Ok(future)
+    }
+
+    // one command buffer per pass, so the GPU profiler can time each of them as a region
+    fn record_passes(
+        &mut self,
+        window: &mut RenderWindow,
+        image_index: usize,
+        input: &mut FrameInput,
+        capture: bool,
+    ) -> Result<(Vec<Pass>, Option<FrameCapture>), EngineError> {
+        let mut passes = Vec::new();
+        let draws = &input.draws;
+
+        // recorded once, a capture retried after they were submitted does not run them again
+        let dispatches = std::mem::take(&mut input.dispatches);
+        if !dispatches.is_empty() {
+            let mut builder = self.start_command_buffer();
+            compute::record_dispatches(&mut builder, &dispatches)?;
+            passes.push(("compute", builder.build().unwrap()));
+        }
+
+        let mut builder = self.start_command_buffer();
+        self.shadow_map
+            .record_shadow_pass(&mut builder, draws, &self.lights);
+        passes.push(("shadow pass", builder.build().unwrap()));
+
+        let mut builder = self.start_command_buffer();
+
+        let [width, height] = window.swapchain().dimensions();
+        let frame = FrameUniforms::new(
+            &self.view_projection_pool,
+            &self.lights_pool,
+            &self.shadow_map,
+            window.camera(),
+            &self.lights,
+            width as f32 / height as f32,
+        );
+
+        Self::record_render_pass(
+            &mut builder,
+            window.framebuffer(image_index),
+            &mut self.pipelines,
+            &self.materials,
+            window.dynamic_state(),
+            draws,
+            &input.debug_lines,
+            &frame,
+        )?;
+        passes.push(("main pass", builder.build().unwrap()));
+
+        if let Some(overlay) = window.take_overlay() {
+            let mut builder = self.start_command_buffer();
+            self.overlay_renderer.record(
+                &mut builder,
+                window.overlay_framebuffer(image_index),
+                &overlay,
+            )?;
+            passes.push(("overlay", builder.build().unwrap()));
+        }
+
+        let capture = if capture {
+            let image = window.swapchain_images()[image_index].clone();
+            let [width, height] = image.dimensions();
+            let buffer = CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::transfer_destination(),
+                true,
+                (0..width * height * 4).map(|_| 0u8),
+            )?;
+            debug_names::name_buffer(&*buffer, "frame capture");
+
+            let mut builder = self.start_command_buffer();
+            // vulkano moves the image to TRANSFER_SRC_OPTIMAL and back to PRESENT_SRC_KHR for us
+            builder
+                .copy_image_to_buffer(image, buffer.clone())
+                .map_err(|err| {
+                    EngineError::VulkanCaptureError(format!(
+                        "Could not copy the swapchain image: {:?}",
+                        err
+                    ))
+                })?;
+            passes.push(("capture", builder.build().unwrap()));
+
+            Some(FrameCapture {
+                buffer,
+                dimensions: [width, height],
+                format: window.swapchain().format(),
+            })
+        } else {
+            None
+        };
+
+        Ok((passes, capture))
+    }
+
+    fn start_command_buffer(&self) -> AutoCommandBufferBuilder {
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.graphics_queue.family(),
+        )
+        .unwrap()
+    }
+
+    // shared with the headless renderer, so image tests exercise the same commands as the window
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record_render_pass(
+        builder: &mut AutoCommandBufferBuilder,
+        framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+        pipelines: &mut PipelineCache,
+        materials: &MaterialBindings,
+        dynamic_state: &DynamicState,
+        draws: &[PreparedDraw],
+        debug_lines: &PreparedDebugLines,
+        frame: &FrameUniforms,
+    ) -> Result<(), EngineError> {
+        // multisampled passes resolve into an attachment that is not cleared
+        let clear_values = (0..framebuffer.num_attachments())
+            .filter_map(|index| framebuffer.attachment_desc(index))
+            .map(|attachment| match attachment.load {
+                LoadOp::Clear if attachment.format.ty() == FormatTy::Depth => 1f32.into(),
+                LoadOp::Clear => [0.0, 0.0, 0.0, 1.0].into(),
+                _ => ClearValue::None,
+            })
+            .collect();
+
+        builder
+            .begin_render_pass(framebuffer, false, clear_values)
+            .unwrap();
+
+        // pipelines of the same shader program have the same layout, so they share set 0
+        let mut frame_sets = HashMap::new();
+
+        for draw in draws {
+            let material = &draw.material;
+            let pipeline = pipelines.get(material.shader, material.state)?;
+            let frame_program = pipelines.frame_set_program(material.shader);
+            let frame_set = frame_sets
+                .entry(frame_program)
+                .or_insert_with(|| frame.descriptor_set(&pipeline, frame_program))
+                .clone();
+            let material_set = materials.descriptor_set(&pipeline, material);
+
+            builder
+                .draw(
+                    pipeline,
+                    dynamic_state,
+                    draw.vertex_buffers.clone(),
+                    (frame_set, material_set),
+                    (),
+                )
+                .unwrap();
+        }
+
+        debug_lines.record(builder, dynamic_state, frame);
+
+        builder.end_render_pass().unwrap();
+
+        Ok(())
+    }
+
+    // Whether the bytes of a captured `format` pixel are in BGRA order, or an error for the formats
+    // whose pixels are not four bytes.
+    fn capture_swaps_red_and_blue(format: Format) -> Result<bool, EngineError> {
+        match format {
+            Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb => Ok(true),
+            Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb => Ok(false),
+            _ => Err(EngineError::VulkanCaptureError(format!(
+                "Capture of swapchain format {:?} is not supported",
+                format
+            ))),
+        }
+    }
+
+    // The presentation engine reads an UNORM image with SrgbNonLinear color space as already sRGB
+    // encoded, and an SRGB image stores its values encoded. Either way the bytes we read back are
+    // what is shown on screen, so only the channel order needs fixing before writing a PNG.
+    pub(crate) fn pixels_to_rgba(format: Format, pixels: &[u8]) -> Result<Vec<u8>, EngineError> {
+        let swap_red_and_blue = Self::capture_swaps_red_and_blue(format)?;
+
+        let rgba = pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let (red, blue) = if swap_red_and_blue {
+                    (pixel[2], pixel[0])
+                } else {
+                    (pixel[0], pixel[2])
+                };
+
+                // the window is opaque, so whatever alpha the pipeline wrote is not what was shown
+                vec![red, pixel[1], blue, 255]
+            })
+            .collect();
+
+        Ok(rgba)
+    }
+
+    pub(crate) fn create_instance(validation: bool) -> Result<Arc<Instance>, EngineError> {
+        if validation {
             if !Self::check_validation_layer_support() {
                 println!("Validation layers requested, but not available!\n\n");
             } else {
@@ -106,7 +1062,7 @@ impl VulkanRenderer {
             }),
         };
 
-        let extensions = Self::get_required_instance_extensions();
+        let extensions = Self::get_required_instance_extensions(validation);
 
         if !Self::check_instance_extension_support(&extensions) {
             return Err(EngineError::VulkanValidationError(String::from(
@@ -114,7 +1070,7 @@ impl VulkanRenderer {
             )));
         }
 
-        let instance = if ENABLE_VALIDATION_LAYERS && Self::check_validation_layer_support() {
+        let instance = if validation && Self::check_validation_layer_support() {
             Instance::new(
                 Some(&app_info),
                 &extensions,
@@ -129,32 +1085,56 @@ impl VulkanRenderer {
 
     fn create_surface(
         instance: Arc<Instance>,
-        events_loop: &EventLoop<()>,
+        event_loop: &EventLoopWindowTarget<()>,
+        settings: &WindowSettings,
     ) -> Result<Arc<Surface<Window>>, EngineError> {
-        let surface = WindowBuilder::new().build_vk_surface(&events_loop, instance)?;
+        let mut builder = WindowBuilder::new()
+            .with_title(settings.title.as_str())
+            .with_resizable(settings.resizable);
+        if let Some([width, height]) = settings.size {
+            builder = match settings.dpi {
+                DpiMode::Logical => builder.with_inner_size(LogicalSize::new(width, height)),
+                DpiMode::Physical => builder.with_inner_size(PhysicalSize::new(width, height)),
+            };
+        }
+        let surface = builder.build_vk_surface(event_loop, instance)?;
+
+        // winit only places windows once they exist
+        if let Some([x, y]) = settings.position {
+            let window = surface.window();
+            match settings.dpi {
+                DpiMode::Logical => window.set_outer_position(LogicalPosition::new(x, y)),
+                DpiMode::Physical => window.set_outer_position(PhysicalPosition::new(x, y)),
+            }
+        }
 
         Ok(surface)
     }
 
-    fn get_required_instance_extensions() -> InstanceExtensions {
+    fn get_required_instance_extensions(validation: bool) -> InstanceExtensions {
         // This method returns the intersect between the ideal winit requirements and supported_by_core (vkEnumerateInstanceExtensionProperties).
         // There is no error handling, just the intersect result whatever it is
         // So, it doesn't make sense to validate if some requirement returned by it is missing on core
         let mut extensions = vulkano_win::required_extensions();
 
         // here is a extension request that will be validated by our check_instance_extension_support
-        if ENABLE_VALIDATION_LAYERS {
+        if validation {
             extensions.ext_debug_utils = true;
         }
 
+        // only asked for when available, without them the memory report has no budget figures and
+        // surfaces only offer sRGB
         extensions
+            .union(&gpu_memory::budget_instance_extensions())
+            .union(&display_settings::surface_instance_extensions())
     }
 
     fn check_validation_layer_support() -> bool {
-        let layers: Vec<_> = layers_list()
-            .unwrap()
-            .map(|l| l.name().to_owned())
-            .collect();
+        // without a Vulkan loader instance creation reports the error
+        let layers: Vec<_> = match layers_list() {
+            Ok(layers) => layers.map(|l| l.name().to_owned()).collect(),
+            Err(_) => return false,
+        };
 
         println!("Available validation layers:");
         for l in &layers {
@@ -166,15 +1146,18 @@ impl VulkanRenderer {
             .all(|layer_name| layers.contains(&layer_name.to_string()))
     }
 
-    fn setup_debug_callback(instance: &Arc<Instance>) -> Option<DebugCallback> {
-        if !ENABLE_VALIDATION_LAYERS {
+    pub(crate) fn setup_debug_callback(
+        instance: &Arc<Instance>,
+        validation: bool,
+    ) -> Option<DebugCallback> {
+        if !validation {
             return None;
         }
 
         let msg_severity = MessageSeverity::errors_and_warnings();
         let msg_type = MessageType::all();
 
-        DebugCallback::new(&instance, msg_severity, msg_type, |msg| {
+        DebugCallback::new(instance, msg_severity, msg_type, |msg| {
             println!("validation layer: {:?}", msg.description);
         })
         .ok()
@@ -187,7 +1170,7 @@ impl VulkanRenderer {
         let value = InstanceExtensions::supported_by_core()
             .expect("Could not get core instance extensions from Vulkan");
 
-        value.intersection(&extensions).eq(&extensions)
+        value.intersection(extensions).eq(extensions)
     }
 
     fn display_supported_by_core() {
@@ -197,21 +1180,59 @@ impl VulkanRenderer {
         }
     }
 
+    // the first suitable device matching `choice`, or the first suitable one when none does
     fn get_physical_device<'a>(
         instance: &'a Arc<Instance>,
         surface: &Arc<Surface<Window>>,
+        choice: &GpuChoice,
     ) -> Result<PhysicalDevice<'a>, EngineError> {
-        let mut physical_device_list = PhysicalDevice::enumerate(&instance);
+        let suitable: Vec<PhysicalDevice> = PhysicalDevice::enumerate(instance)
+            .filter(|device| Self::check_device_suitable(device, surface))
+            .collect();
 
-        while let Some(device) = physical_device_list.next() {
-            if Self::check_device_suitable(&device, surface) {
-                return Ok(device);
+        let device = match suitable.iter().find(|device| choice.matches(device)) {
+            Some(device) => *device,
+            None => {
+                let device = *suitable.first().ok_or_else(|| {
+                    EngineError::VulkanValidationError(String::from(
+                        "No valid physical device available",
+                    ))
+                })?;
+                eprintln!(
+                    "No suitable GPU matches {:?}, using {}",
+                    choice,
+                    device.name()
+                );
+                device
             }
+        };
+        println!("GPU: {} ({:?})", device.name(), device.ty());
+
+        Ok(device)
+    }
+
+    // bit n is set when the colour and depth attachments can both have 2^n samples
+    fn sample_counts(physical: PhysicalDevice) -> u32 {
+        let limits = physical.limits();
+        limits.framebuffer_color_sample_counts() & limits.framebuffer_depth_sample_counts()
+    }
+
+    // the most samples up to `requested` that colour and depth attachments both support
+    fn supported_samples(physical: PhysicalDevice, requested: u32) -> u32 {
+        let supported = Self::sample_counts(physical);
+
+        let mut samples = requested.max(1);
+        while samples > 1 && supported & samples == 0 {
+            samples /= 2;
+        }
+        if samples != requested {
+            eprintln!(
+                "{} samples per pixel are not supported, using {}",
+                requested, samples
+            );
         }
 
-        Err(EngineError::VulkanValidationError(String::from(
-            "No valid physical device available",
-        )))
+        samples
     }
 
     fn check_device_suitable(
@@ -222,7 +1243,8 @@ impl VulkanRenderer {
         let extensions = Self::get_required_device_extensions();
 
         queue_families.is_valid()
-            && Self::check_device_extension_support(&physical_device, &extensions)
+            && Self::check_device_extension_support(physical_device, &extensions)
+            && device_features::supports_required_features(*physical_device)
     }
 
     fn get_queue_families<'a>(
@@ -271,10 +1293,15 @@ impl VulkanRenderer {
     fn create_logical_device(
         physical: PhysicalDevice,
         surface: &Arc<Surface<Window>>,
-    ) -> Result<(Arc<Device>, QueuesIter), EngineError> {
-        let device_ext = Self::get_required_device_extensions();
+    ) -> Result<DeviceQueues, EngineError> {
+        let device_ext = Self::get_required_device_extensions()
+            .union(&display_settings::optional_device_extensions(physical));
+
+        let queue_family_indices = Self::get_queue_families(&physical, surface);
+        let graphics_family_id = queue_family_indices.graphics_family.unwrap().id();
+        let presentation_family_id = queue_family_indices.presentation_family.unwrap().id();
 
-        let families: Vec<(QueueFamily, f32)> = Self::get_queue_families(&physical, surface)
+        let families: Vec<(QueueFamily, f32)> = queue_family_indices
             .into_vec()
             .into_iter()
             .map(|x| (x, 0.5))
@@ -282,235 +1309,140 @@ impl VulkanRenderer {
 
         let (device, queues) = Device::new(
             physical,
-            physical.supported_features(),
+            &device_features::device_features(physical),
             &device_ext,
             families,
         )?;
+        println!(
+            "Optional features: {:?}",
+            OptionalFeatures::enabled_on(&device)
+        );
+
+        // one queue is created per family, so the same queue is used when both families are the same
+        let queues: Vec<Arc<Queue>> = queues.collect();
+        let graphics_queue = queues
+            .iter()
+            .find(|q| q.family().id() == graphics_family_id)
+            .unwrap()
+            .clone();
+        let presentation_queue = queues
+            .iter()
+            .find(|q| q.family().id() == presentation_family_id)
+            .unwrap()
+            .clone();
 
-        Ok((device, queues))
+        Ok((device, graphics_queue, presentation_queue))
     }
 
-    fn create_swapchain(
-        physical: PhysicalDevice,
-        surface: Arc<Surface<Window>>,
+    // presentable render passes leave the image ready for the swapchain, the others (headless
+    // targets) keep it as a color attachment. With more than one sample, the pass draws to
+    // multisampled attachments and resolves colour into the image, framebuffers then take the
+    // multisampled colour, the image and the depth, in that order
+    pub(crate) fn create_render_pass(
         device: Arc<Device>,
-        queues: &mut QueuesIter,
-    ) -> Result<(Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>), EngineError> {
-        let (swapchain, images) = {
-            let surface_capabilities = surface.capabilities(physical)?;
-
-            let (surface_format, color_space) =
-                Self::choose_best_surface_format(surface_capabilities.supported_formats);
-
-            let presentation_mode =
-                Self::choose_best_presentation_mode(surface_capabilities.present_modes);
-
-            let mut image_count: u32 = surface_capabilities.min_image_count + 1;
-            if let Some(max_image_count) = surface_capabilities.max_image_count {
-                image_count = std::cmp::min(image_count, max_image_count)
-            }
-
-            // Opaque (VK_COMPOSITE_ALPHA_OPAQUE_BIT_KHR) is the first element, if available, in the iter() implementation
-            let alpha = surface_capabilities
-                .supported_composite_alpha
-                .iter()
-                .next()
-                .unwrap();
-
-            // VkExtent2D is created inside swapchain creation and uses dimensions values to be built
-            let mut dimensions: [u32; 2] = surface.window().inner_size().into();
-            dimensions[0] = std::cmp::max(
-                surface_capabilities.min_image_extent[0],
-                std::cmp::min(surface_capabilities.max_image_extent[0], dimensions[0]),
-            );
-            dimensions[1] = std::cmp::max(
-                surface_capabilities.min_image_extent[1],
-                std::cmp::min(surface_capabilities.max_image_extent[1], dimensions[1]),
-            );
-
-            let sharing_mode: SharingMode = {
-                let mut queue_list: Vec<u32> = Vec::new();
-                while let Some(q) = queues.next() {
-                    queue_list.push(q.id_within_family());
-                }
-
-                if queue_list.len() == 1 {
-                    SharingMode::Exclusive
-                } else {
-                    SharingMode::Concurrent(queue_list)
-                }
+        format: Format,
+        presentable: bool,
+        samples: u32,
+    ) -> Result<Arc<dyn RenderPassAbstract + Send + Sync>, EngineError> {
+        // the renderpass macro evaluates layouts away from our locals, so they must be paths
+        macro_rules! color_render_pass {
+            ($final_layout:path) => {
+                Arc::new(
+                    vulkano::single_pass_renderpass!(device.clone(),
+                        attachments: {
+                            color: {
+                                load: Clear,
+                                store: Store,
+                                format: format,
+                                samples: 1,
+                                initial_layout: ImageLayout::Undefined,
+                                final_layout: $final_layout,
+                            },
+                            depth: {
+                                load: Clear,
+                                store: DontCare,
+                                format: DEPTH_FORMAT,
+                                samples: 1,
+                            }
+                        },
+                        pass: {
+                            color: [color],
+                            depth_stencil: {depth}
+                        }
+                    )
+                    .unwrap(),
+                )
+            };
+            ($final_layout:path, multisampled) => {
+                Arc::new(
+                    vulkano::single_pass_renderpass!(device.clone(),
+                        attachments: {
+                            multisampled_color: {
+                                load: Clear,
+                                store: DontCare,
+                                format: format,
+                                samples: samples,
+                            },
+                            color: {
+                                load: DontCare,
+                                store: Store,
+                                format: format,
+                                samples: 1,
+                                initial_layout: ImageLayout::Undefined,
+                                final_layout: $final_layout,
+                            },
+                            depth: {
+                                load: Clear,
+                                store: DontCare,
+                                format: DEPTH_FORMAT,
+                                samples: samples,
+                            }
+                        },
+                        pass: {
+                            color: [multisampled_color],
+                            depth_stencil: {depth},
+                            resolve: [color]
+                        }
+                    )
+                    .unwrap(),
+                )
             };
-
-            Swapchain::new(
-                device.clone(),
-                surface.clone(),
-                image_count,
-                surface_format,
-                dimensions,
-                1,
-                ImageUsage::color_attachment(),
-                sharing_mode,
-                SurfaceTransform::Identity,
-                alpha,
-                presentation_mode,
-                FullscreenExclusive::Default,
-                true,
-                color_space,
-            )?
-        };
-
-        Ok((swapchain, images))
-    }
-
-    fn choose_best_surface_format(
-        avalilable_formats: Vec<(Format, ColorSpace)>,
-    ) -> (Format, ColorSpace) {
-        let best_format = avalilable_formats.clone().into_iter().find(|f| {
-            (f.0 == Format::R8G8B8A8Unorm || f.0 == Format::B8G8R8A8Unorm)
-                && f.1 == ColorSpace::SrgbNonLinear
-        });
-
-        if let Some(format) = best_format {
-            return format;
         }
 
-        return avalilable_formats[0];
-    }
-
-    fn choose_best_presentation_mode(supported_modes: SupportedPresentModes) -> PresentMode {
-        if supported_modes.mailbox {
-            return PresentMode::Mailbox;
-        }
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = match (presentable, samples) {
+            (true, 1) => color_render_pass!(ImageLayout::PresentSrc),
+            (false, 1) => color_render_pass!(ImageLayout::ColorAttachmentOptimal),
+            (true, _) => color_render_pass!(ImageLayout::PresentSrc, multisampled),
+            (false, _) => color_render_pass!(ImageLayout::ColorAttachmentOptimal, multisampled),
+        };
+        debug_names::name_render_pass(&*render_pass, "main render pass");
 
-        return PresentMode::Fifo;
+        Ok(render_pass)
     }
+}
 
-    fn create_graphic_pipeline(
-        device: Arc<Device>,
-        swapchain: Arc<Swapchain<Window>>,
-    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, EngineError> {
-        vulkano::impl_vertex!(Vertex, position);
-
-        mod vertex_shader {
-            vulkano_shaders::shader! {
-                ty: "vertex",
-                src: "
-                #version 450
-    
-                layout(location = 0) in vec3 position;
-
-                layout(location = 0) out vec3 fragColour;
-
-                // Triangle vertex colours
-                vec3 colours[3] = vec3[](
-                    vec3(1.0, 0.0, 0.0),
-                    vec3(0.0, 1.0, 0.0),
-                    vec3(0.0, 0.0, 1.0)
-                );
-    
-                void main() {
-                    gl_Position = vec4(position, 1.0);
-                    fragColour = colours[gl_VertexIndex];
-                }"
-            }
-        }
-
-        mod fragment_shader {
-            vulkano_shaders::shader! {
-                ty: "fragment",
-                src: "
-                #version 450
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                layout(location = 0) in vec3 fragColour;
-    
-                layout(location = 0) out vec4 f_color;
-    
-                void main() {
-                    f_color = vec4(fragColour, 1.0);
-                }"
-            }
-        }
+    #[test]
+    fn captured_pixels_come_out_as_opaque_rgba() {
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8];
 
-        let vertex_shader = vertex_shader::Shader::load(device.clone())?;
-        let fragment_shader = fragment_shader::Shader::load(device.clone())?;
-
-        let render_pass = Arc::new(
-            vulkano::single_pass_renderpass!(device.clone(),
-                attachments: {
-                    color: {
-                        load: Clear,
-                        store: Store,
-                        format: swapchain.format(),
-                        samples: 1,
-                        initial_layout: ImageLayout::Undefined,
-                        final_layout: ImageLayout::PresentSrc,
-                    }
-                },
-                pass: {
-                    color: [color],
-                    depth_stencil: {}
-                }
-            )
-            .unwrap(),
+        assert_eq!(
+            VulkanRenderer::pixels_to_rgba(Format::B8G8R8A8Srgb, &pixels).unwrap(),
+            vec![3, 2, 1, 255, 7, 6, 5, 255]
         );
-
-        let mut blend_info = AttachmentBlend::alpha_blending();
-        blend_info.alpha_source = BlendFactor::One;
-        blend_info.alpha_destination = BlendFactor::Zero;
-
-        let pipeline = Arc::new(
-            GraphicsPipeline::start()
-                // Defines what kind of vertex input is expected.
-                .vertex_input_single_buffer::<Vertex>()
-                // The vertex shader.
-                .vertex_shader(vertex_shader.main_entry_point(), ())
-                // VK_STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO
-                .triangle_list()
-                // Defines the viewport (explanations below).
-                .viewports_dynamic_scissors_irrelevant(1)
-                // rasterizerCreateInfo.frontFace = VK_FRONT_FACE_CLOCKWISE
-                .front_face_clockwise()
-                // rasterizerCreateInfo.cullMode = VK_CULL_MODE_BACK_BIT
-                .cull_mode_back()
-                // POLYGON_MODE_FILL - lets test what other values does to the final render :)
-                .polygon_mode_fill()
-                // VK_STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO
-                .blend_collective(blend_info)
-                // The fragment shader.
-                .fragment_shader(fragment_shader.main_entry_point(), ())
-                // STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO
-                // multisamplingCreateInfo.rasterizationSamples is tied to render_pass (VkAttachmentDescription).
-                // There is a TODO there. Default is 1 (VK_SAMPLE_COUNT_1_BIT)
-                .sample_shading_disabled()
-                // This graphics pipeline object concerns the first pass of the render pass.
-                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-                // Now that everything is specified, we call `build`.
-                .build(device.clone())
-                .unwrap(),
+        assert_eq!(
+            VulkanRenderer::pixels_to_rgba(Format::R8G8B8A8Unorm, &pixels).unwrap(),
+            vec![1, 2, 3, 255, 5, 6, 7, 255]
         );
-
-        Ok(pipeline)
-    }
-
-    fn create_vertex_to_draw(device: Arc<Device>) {
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(
-            device.clone(),
-            BufferUsage::all(),
-            false,
-            [
-                Vertex {
-                    position: [-0.5, -0.25, 0.0],
-                },
-                Vertex {
-                    position: [0.0, 0.5, 0.0],
-                },
-                Vertex {
-                    position: [0.25, -0.1, 0.0],
-                },
-            ]
-            .iter()
-            .cloned(),
-        )
-        .unwrap();
+        match VulkanRenderer::pixels_to_rgba(Format::R16G16B16A16Sfloat, &pixels) {
+            Err(EngineError::VulkanCaptureError(message)) => assert_eq!(
+                message,
+                "Capture of swapchain format R16G16B16A16Sfloat is not supported"
+            ),
+            other => panic!("not a capture error: {:?}", other),
+        }
     }
 }